@@ -1,101 +1,138 @@
-use std::{
-    collections::HashMap,
-    io::{BufReader, Write},
-    time::Instant,
-};
-
-use criterion::Criterion;
-use rand::{distributions::Alphanumeric, Rng};
-use serde::{Deserialize, Serialize};
-
-/// Stores the information of the entry in the vault
-/// Eg: instagram:username:password:personal id:re-promt-master-pass:false;
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
-pub struct Account {
-    /// Name can also act as it, as it need to be unique for all entries in the vault
-    pub name: String,
-
-    /// Username, email, phone number or any other identifier of the account on the website
-    username: String,
-
-    /// Other notes regarding the account, can be 2FA keys or anything
-    /// It is in form on K,V, where K is the identifier of the value
-    ///
-    /// Eg: instagram:username:password:[2FA, burger mango]
-    notes: Option<HashMap<String, Option<String>>>,
-}
+//! Load, lookup, add & dump of vaults having 1k, 10k & 100k entries, in every storage format
 
-fn rand_str() -> String {
-    rand::thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(rand::thread_rng().gen_range(0..100))
-        .map(char::from)
-        .collect()
-}
+use std::path::PathBuf;
 
-impl Account {
-    /// Constructs a new [Account] with given name, username and password
-    pub fn new(name: &str, username: &str) -> Self {
-        Self {
-            name: name.to_owned(),
-            username: username.to_owned(),
-            notes: Some(HashMap::new()),
-        }
-    }
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
 
-    pub fn new_rand() -> Self {
-        Self {
-            name: rand_str(),
-            username: rand_str(),
-            notes: Some(HashMap::from([
-                (rand_str(), Some(rand_str())),
-                (rand_str(), Some(rand_str())),
-            ])),
-        }
-    }
+// Binary crate has no library to link, so the benchmarks build the vault modules on their own
+#[allow(dead_code, unused_imports)]
+#[path = "../src/pass/mod.rs"]
+mod pass;
+
+use pass::backend::BackendKind;
+use pass::entry::PasswordEntry;
+use pass::header::VaultHeader;
+use pass::kdf::Kdf;
+use pass::master::{MasterPassword, Verified};
+use pass::store::{PasswordStore, VaultPayload};
+use pass::util::backup_path;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+
+const KINDS: [BackendKind; 3] = [BackendKind::File, BackendKind::Sqlite, BackendKind::Log];
+
+fn master() -> MasterPassword<Verified> {
+    MasterPassword::from_pass("Bench123@").expect("Unable to create master password")
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct AccColl {
-    accounts: HashMap<String, Account>,
+fn entry(n: usize) -> PasswordEntry {
+    PasswordEntry::new(
+        format!("service {n}"),
+        Some(format!("user {n}")),
+        Some(format!("password {n}")),
+        Some("notes".to_owned()),
+    )
 }
 
-fn fill_file() {
-    let accounts = (0..100000)
-        .map(|_| Account::new_rand())
-        .map(|acc| (acc.name.clone(), acc))
-        .collect::<HashMap<_, _>>();
-    let mut file = std::fs::File::create("./account_store.dat").unwrap();
-    let data = bincode::serialize(&accounts).unwrap();
-    file.write_all(&data).unwrap();
+/// Vault of `size` entries, its key is derived without cost as only storage is measured
+fn vault(size: usize) -> PasswordStore {
+    let header = VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1 });
+    let mut store = PasswordStore::with_payload(VaultPayload::default(), &master(), header)
+        .expect("Unable to derive key");
+    (0..size).for_each(|n| {
+        store.insert_entry(entry(n));
+    });
+    store
 }
 
-fn read_file() {
-    let file = std::fs::File::open("./account_store.dat").unwrap();
-    let reader = BufReader::new(file);
-    let _data: AccColl = bincode::deserialize_from(reader).unwrap();
+/// Vault of `size` entries stored in the format
+fn stored_vault(kind: BackendKind, size: usize) -> (PasswordStore, PathBuf) {
+    let path = std::env::temp_dir().join(format!("pass_rs_bench_{kind:?}_{size}"));
+    let store = vault(size);
+
+    let mut backend = kind.create(&path).expect("Unable to create vault");
+    store
+        .dump_into(backend.as_mut())
+        .expect("Unable to dump vault");
+
+    (store, path)
+}
+
+fn remove_vault(path: PathBuf) {
+    let _ = std::fs::remove_file(&path);
+    let _ = std::fs::remove_file(backup_path(&path));
 }
 
-pub fn create_benchmark(c: &mut Criterion) {
-    c.bench_function("Create account", |b| b.iter(fill_file));
+fn load(c: &mut Criterion) {
+    let mut group = c.benchmark_group("load");
+    group.sample_size(10);
+
+    for size in SIZES {
+        for kind in KINDS {
+            let (_, path) = stored_vault(kind, size);
+            group.bench_with_input(BenchmarkId::new(kind.name(), size), &path, |b, path| {
+                b.iter(|| PasswordStore::load(path, &master()).expect("Unable to load vault"))
+            });
+            remove_vault(path);
+        }
+    }
 }
 
-pub fn read_benchmark(c: &mut Criterion) {
-    c.bench_function("Read accounts", |b| b.iter(read_file));
+fn lookup(c: &mut Criterion) {
+    let mut group = c.benchmark_group("lookup");
+
+    for size in SIZES {
+        let store = vault(size);
+        let id = store.get(format!("service {}", size / 2))[0].id;
+
+        group.bench_with_input(BenchmarkId::new("service", size), &store, |b, store| {
+            b.iter(|| store.get(format!("service {}", size / 2)))
+        });
+        group.bench_with_input(BenchmarkId::new("id", size), &store, |b, store| {
+            b.iter(|| store.get_by_id(id).expect("Entry exists"))
+        });
+        group.bench_with_input(BenchmarkId::new("fuzzy", size), &store, |b, store| {
+            b.iter(|| store.fuzzy_find("servce 42"))
+        });
+    }
 }
 
-// criterion_group!(benches, read_benchmark);
-//
-// criterion_main!(benches);
+fn add(c: &mut Criterion) {
+    let mut group = c.benchmark_group("add");
 
-fn main() {
-    fill_file();
+    for size in SIZES {
+        let mut store = vault(size);
+        let mut n = size;
 
-    for _ in 0..100 {
-        let time = Instant::now();
-        read_file();
-        let elasped = time.elapsed();
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                n += 1;
+                store.insert_entry(entry(n))
+            })
+        });
+    }
+}
 
-        println!("{:?}", elasped);
+/// Dump after a change of one entry, as done by most commands
+fn dump(c: &mut Criterion) {
+    let mut group = c.benchmark_group("dump");
+    group.sample_size(10);
+
+    for size in SIZES {
+        for kind in KINDS {
+            let (mut store, path) = stored_vault(kind, size);
+            let id = store.get("service 0")[0].id;
+
+            group.bench_with_input(BenchmarkId::new(kind.name(), size), &path, |b, path| {
+                b.iter(|| {
+                    store.entry_mut(id).expect("Entry exists").mark_accessed();
+                    store.dump(path).expect("Unable to dump vault")
+                })
+            });
+            remove_vault(path);
+        }
     }
 }
+
+criterion_group!(benches, load, lookup, add, dump);
+criterion_main!(benches);
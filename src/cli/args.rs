@@ -1,21 +1,45 @@
 use std::borrow::BorrowMut;
-
-use clap::{Args, Parser, Subcommand};
-use inquire::{Password, PasswordDisplayMode};
-
-use crate::pass::master::{MasterPassword, Verified};
-use crate::pass::store::print_table;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{TimeDelta, Utc};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use inquire::{validator::Validation, CustomUserError, Password, PasswordDisplayMode, Text};
+use uuid::Uuid;
+
+#[cfg(unix)]
+use std::time::Duration;
+
+#[cfg(unix)]
+use crate::pass::agent::{Agent, AGENT_TIMEOUT_SECS};
+use crate::pass::attachment::attachments_dir;
+use crate::pass::backend::BackendKind;
+use crate::pass::doctor::{Doctor, Severity};
+use crate::pass::kdf::{
+    Kdf, ARGON2_ITERATIONS, ARGON2_MEMORY_KIB, ARGON2_PARALLELISM, PBKDF2_ITERATIONS,
+};
+use crate::pass::kind::{ApiToken, Card, EntryKind, EntryKindError, SecureNote, SshKey};
+use crate::pass::lock::{self, LockMode, LOCK_TIMEOUT_SECS};
+use crate::pass::master::{
+    handle_master_not_initialised, MasterPassword, PasswordSource, Verified, MASTER_PASS_STORE,
+};
+use crate::pass::store::{print_table, TableColumn};
 use crate::pass::util::{
-    ask_for_confirm, choose_entry_with_interaction, input_number, print_pass_entry_info,
-    prompt_string, PASS_DIR_PATH,
+    ask_for_confirm, backup_path, choose_entry_with_interaction, format_size, format_time,
+    input_number, print_pass_entry_info, prompt_string, prompt_string_without_skip,
 };
 use crate::pass::{
-    entry::PasswordEntry,
-    store::{PasswordStore, PasswordStoreError, PASS_ENTRY_STORE},
-    util::copy_to_clipboard,
+    entry::{CustomField, PasswordEntry},
+    otp::{Otp, OtpError, OtpKind},
+    store::{PasswordStore, PasswordStoreError, VaultPayload, PASS_ENTRY_STORE},
+    util::{copy_secret, copy_to_clipboard},
+    vault::{Vault, VaultError},
 };
 
-use super::CliError;
+use super::{
+    reprompt_master_password, CliError, VAULT_DAMAGED_EXIT_CODE, VAULT_ISSUES_EXIT_CODE,
+    WRONG_MASTER_PASSWORD_EXIT_CODE,
+};
 
 // CLI Design
 #[derive(Parser)]
@@ -26,6 +50,33 @@ use super::CliError;
     about = "A easy-to-use CLI password manager"
 )]
 pub struct Cli {
+    /// Name or path of the vault to use (default: the vault chosen by `vault use`)
+    #[arg(long, global = true, env = "PASS_RS_VAULT")]
+    pub vault: Option<String>,
+
+    /// Read master password from the first line of stdin, instead of prompting
+    #[arg(long, global = true)]
+    pub password_stdin: bool,
+
+    /// Read master password from stdout of a shell command, instead of prompting
+    #[arg(
+        long,
+        global = true,
+        value_name = "COMMAND",
+        conflicts_with = "password_stdin"
+    )]
+    pub password_command: Option<String>,
+
+    /// Seconds to wait for other processes using the vault, 0 to fail at once
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECONDS",
+        env = "PASS_RS_LOCK_TIMEOUT",
+        default_value_t = LOCK_TIMEOUT_SECS
+    )]
+    pub lock_timeout: u64,
+
     /// Subcommand to do some operation like add, remove, etc.
     #[command(subcommand)]
     pub command: Option<Command>,
@@ -49,7 +100,10 @@ pub enum Command {
     Update(UpdateArgs),
 
     /// List all made password
-    List,
+    List(ListArgs),
+
+    /// List all tags along with the number of entries having them
+    Tags,
 
     /// Get a password entry
     Get(GetArgs),
@@ -57,11 +111,69 @@ pub enum Command {
     /// Fuzzy search passsword entries
     Search(SearchArgs),
 
+    /// Copy the current one-time password of an entry
+    Otp(OtpArgs),
+
+    /// List (or restore) the previous passwords of an entry
+    History(HistoryArgs),
+
+    /// Show or change the settings of vault
+    Config(ConfigArgs),
+
+    /// Attach a file to an entry, encrypted by the vault
+    Attach(AttachArgs),
+
+    /// List the attachments of an entry
+    Attachments(AttachmentsArgs),
+
+    /// Write an attachment of an entry to a file
+    Extract(ExtractArgs),
+
     /// Generate a password
     Gen(GenArgs),
 
     /// Reset features for pass directory
     Reset(ResetArgs),
+
+    /// Manage the key derivation function of vault
+    Kdf(KdfArgs),
+
+    /// Convert the vault into another storage format
+    Migrate(MigrateArgs),
+
+    /// Check integrity of the vault & restore it from backup if it is unreadable
+    Doctor,
+
+    /// Manage multiple vaults, each having its own master password
+    Vault(VaultArgs),
+
+    /// Unlock the vault for a session by a background agent holding its key
+    #[cfg(unix)]
+    Agent(AgentArgs),
+
+    /// Make the agent forget all keys & exit
+    #[cfg(unix)]
+    Lock,
+}
+
+impl Command {
+    /// Lock of vault held while running the command, `None` if it does not use the vault
+    pub fn lock_mode(&self) -> Option<LockMode> {
+        match self {
+            Command::List(_) | Command::Tags | Command::Search(_) | Command::Attachments(_) => {
+                Some(LockMode::Shared)
+            }
+            Command::Gen(_) => None,
+            Command::Vault(arg) => match arg.command {
+                VaultCommand::Delete { .. } => Some(LockMode::Exclusive),
+                VaultCommand::Create { .. } | VaultCommand::List | VaultCommand::Use { .. } => None,
+            },
+            #[cfg(unix)]
+            Command::Agent(_) | Command::Lock => None,
+            // Reading an entry also records its access
+            _ => Some(LockMode::Exclusive),
+        }
+    }
 }
 
 #[derive(Args, Debug, Clone)]
@@ -78,11 +190,35 @@ pub struct AddArgs {
     #[clap(short, default_value = None)]
     password: Option<String>,
 
+    #[clap(skip)]
     random_password: bool,
 
     /// Notes for the account
     #[clap(long, short, default_value = None)]
     notes: Option<String>,
+
+    #[command(flatten)]
+    fields: FieldArgs,
+
+    /// TOTP/HOTP secret of the account, as `otpauth://` URI or base32 secret
+    #[arg(long, value_name = "URI|SECRET", value_parser = parse_otp)]
+    otp: Option<Otp>,
+
+    /// Folder of the entry, e.g. `work/aws/prod`
+    #[arg(long)]
+    folder: Option<String>,
+
+    /// Tag of the entry
+    #[arg(long)]
+    tag: Vec<String>,
+
+    /// Type of the entry, prompting for the fields specific to it
+    #[arg(long = "type", value_enum, default_value_t)]
+    entry_type: EntryType,
+
+    /// Ask master password again before revealing or changing the entry
+    #[arg(long)]
+    reprompt: bool,
 }
 
 impl From<&mut AddArgs> for PasswordEntry {
@@ -96,30 +232,270 @@ impl From<&mut AddArgs> for PasswordEntry {
     }
 }
 
+/// Custom fields given by flags, value is prompted if it is not given
+#[derive(Args, Debug, Clone, Default)]
+pub struct FieldArgs {
+    /// Custom field of the account (value is prompted if omitted)
+    #[arg(long = "field", value_name = "NAME[=VALUE]", value_parser = parse_field)]
+    visible: Vec<(String, Option<String>)>,
+
+    /// Custom field of the account, masked while showing the entry (value is prompted if omitted)
+    #[arg(long = "hidden-field", value_name = "NAME[=VALUE]", value_parser = parse_field)]
+    hidden: Vec<(String, Option<String>)>,
+}
+
+fn parse_field(value: &str) -> Result<(String, Option<String>), String> {
+    let (name, value) = match value.split_once('=') {
+        Some((name, value)) => (name.trim(), Some(value.to_owned())),
+        None => (value.trim(), None),
+    };
+
+    match name.is_empty() {
+        true => Err("Name of field can not be empty".to_owned()),
+        false => Ok((name.to_owned(), value)),
+    }
+}
+
+fn parse_otp(value: &str) -> Result<Otp, OtpError> {
+    Otp::parse(value)
+}
+
+impl FieldArgs {
+    /// Custom fields given by flags, prompting for the missing values
+    fn custom_fields(&self) -> Result<Vec<CustomField>, PasswordStoreError> {
+        let visible = self.visible.iter().map(|field| (field, false));
+        let hidden = self.hidden.iter().map(|field| (field, true));
+
+        visible
+            .chain(hidden)
+            .map(|((name, value), hidden)| {
+                let value = match (value, hidden) {
+                    (Some(value), _) => value.clone(),
+                    (None, false) => prompt_string_without_skip(format!("Enter {name}: "))
+                        .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?,
+                    (None, true) => Password::new(&format!("Enter {name}: "))
+                        .with_display_mode(PasswordDisplayMode::Masked)
+                        .without_confirmation()
+                        .prompt()
+                        .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?,
+                };
+
+                Ok(CustomField::new(name, value, hidden))
+            })
+            .collect()
+    }
+}
+
+/// Type of entry made by `add`
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EntryType {
+    #[default]
+    Login,
+    Note,
+    Card,
+    ApiToken,
+    SshKey,
+}
+
+impl EntryType {
+    /// Prompt for the fields specific to type, logins use the usual prompts instead
+    fn prompt_kind(self, service: &str) -> Result<EntryKind, CliError> {
+        println!();
+
+        let kind = match self {
+            EntryType::Login => EntryKind::Login,
+            EntryType::Note => EntryKind::Note(
+                SecureNote::new(prompt_note(service)?).map_err(CliError::InvalidEntry)?,
+            ),
+            EntryType::Card => {
+                let holder = prompt_string(
+                    format!("Enter card holder for {service}: "),
+                    "Press <Esc> to skip the holder",
+                )
+                .map_err(|_| CliError::UnableToReadFromConsole)?;
+                let number = prompt_secret("Enter card number: ", |input| {
+                    Card::parse_number(input).map(|_| ())
+                })?;
+                let expiry = Text::new("Enter expiry (MM/YY): ")
+                    .with_validator(validate_with(|input| Card::parse_expiry(input).map(|_| ())))
+                    .prompt()
+                    .map_err(|_| CliError::UnableToReadFromConsole)?;
+                let cvv = prompt_secret("Enter CVV: ", |input| Card::parse_cvv(input).map(|_| ()))?;
+
+                EntryKind::Card(
+                    Card::new(holder, number, expiry, cvv).map_err(CliError::InvalidEntry)?,
+                )
+            }
+            EntryType::ApiToken => {
+                let token = prompt_secret("Enter token: ", |input| {
+                    ApiToken::new(input, "", None::<&str>).map(|_| ())
+                })?;
+                let scopes = prompt_string(
+                    "Enter scopes (comma separated): ",
+                    "Press <Esc> to skip the scopes",
+                )
+                .map_err(|_| CliError::UnableToReadFromConsole)?;
+                let expires_on = Text::new("Enter expiry (YYYY-MM-DD): ")
+                    .with_help_message("Press <Esc> if the token never expires")
+                    .with_validator(validate_with(|input| {
+                        ApiToken::parse_expiry(input).map(|_| ())
+                    }))
+                    .prompt_skippable()
+                    .map_err(|_| CliError::UnableToReadFromConsole)?;
+
+                EntryKind::ApiToken(
+                    ApiToken::new(token, scopes.unwrap_or_default(), expires_on)
+                        .map_err(CliError::InvalidEntry)?,
+                )
+            }
+            EntryType::SshKey => {
+                let private_key = prompt_key_file("Enter path of private key: ", false, |key| {
+                    SshKey::validate_private_key(key)
+                })?
+                .ok_or(CliError::UnableToReadFromConsole)?;
+                let public_key = prompt_key_file("Enter path of public key: ", true, |key| {
+                    SshKey::validate_public_key(key)
+                })?;
+                let passphrase = Password::new("Enter passphrase of key: ")
+                    .with_display_mode(PasswordDisplayMode::Masked)
+                    .with_help_message("Press <Esc> if the key has no passphrase")
+                    .without_confirmation()
+                    .prompt_skippable()
+                    .map_err(|_| CliError::UnableToReadFromConsole)?;
+
+                EntryKind::SshKey(
+                    SshKey::new(private_key, public_key, passphrase)
+                        .map_err(CliError::InvalidEntry)?,
+                )
+            }
+        };
+
+        Ok(kind)
+    }
+}
+
+/// Validator of inquire prompts showing the error of `validate`
+fn validate_with(
+    validate: fn(&str) -> Result<(), EntryKindError>,
+) -> impl Fn(&str) -> Result<Validation, CustomUserError> + Clone {
+    move |input: &str| match validate(input) {
+        Ok(()) => Ok(Validation::Valid),
+        Err(err) => Ok(Validation::Invalid(err.to_string().into())),
+    }
+}
+
+/// Masked prompt, re-asking until `validate` accepts the input
+fn prompt_secret(
+    message: &str,
+    validate: fn(&str) -> Result<(), EntryKindError>,
+) -> Result<String, CliError> {
+    Password::new(message)
+        .with_display_mode(PasswordDisplayMode::Masked)
+        .with_display_toggle_enabled()
+        .without_confirmation()
+        .with_validator(validate_with(validate))
+        .prompt()
+        .map_err(|_| CliError::UnableToReadFromConsole)
+}
+
+/// Lines of a note until an empty line
+fn prompt_note(service: &str) -> Result<String, CliError> {
+    colour::green_ln!("Enter note for {}, an empty line finishes it", service);
+
+    let mut lines = Vec::new();
+    loop {
+        let line = Text::new("")
+            .prompt()
+            .map_err(|_| CliError::UnableToReadFromConsole)?;
+
+        match line.is_empty() {
+            true if !lines.is_empty() => break,
+            true => continue,
+            false => lines.push(line),
+        }
+    }
+
+    Ok(lines.join("\n"))
+}
+
+/// Path of a key file, re-asking until the file is read & accepted by `validate`
+fn prompt_key_file(
+    message: &str,
+    skippable: bool,
+    validate: fn(&str) -> Result<(), EntryKindError>,
+) -> Result<Option<String>, CliError> {
+    let read_key = move |path: &str| {
+        std::fs::read_to_string(path.trim())
+            .map_err(|err| format!("Unable to read {}: {err}", path.trim()))
+            .and_then(|key| validate(&key).map(|_| key).map_err(|err| err.to_string()))
+    };
+
+    let prompt = Text::new(message).with_validator(move |path: &str| match read_key(path) {
+        Ok(_) => Ok(Validation::Valid),
+        Err(err) => Ok(Validation::Invalid(err.into())),
+    });
+    let path = match skippable {
+        true => prompt
+            .with_help_message("Press <Esc> to skip the key")
+            .prompt_skippable(),
+        false => prompt.prompt().map(Some),
+    }
+    .map_err(|_| CliError::UnableToReadFromConsole)?;
+
+    // File was validated by prompt
+    Ok(path.and_then(|path| read_key(&path).ok()))
+}
+
 impl AddArgs {
     pub fn add_entries(
         &mut self,
         master_password: &MasterPassword<Verified>,
     ) -> anyhow::Result<()> {
-        let mut manager =
-            PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), master_password.to_owned())?;
+        let mut manager = PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), master_password)?;
 
-        self.password.is_none().then(|| self.set_params());
+        let kind = match self.entry_type {
+            EntryType::Login => {
+                self.password.is_none().then(|| self.set_params());
+                None
+            }
+            _ if self.password.is_some() => Err(CliError::PasswordOfTypedEntry)?,
+            entry_type => {
+                let kind = entry_type.prompt_kind(&self.service)?;
+                self.set_notes()?;
+
+                // Secret of typed entries is kept in its kind
+                self.password = Some(String::new());
+                Some(kind)
+            }
+        };
+        let fields = self.fields.custom_fields()?;
 
         // Push the new entries
-        manager.push_entry(self.into());
+        let mut entry = PasswordEntry::from(&mut *self);
+        if let Some(kind) = kind {
+            entry.change_kind(kind);
+        }
+        fields.into_iter().for_each(|field| entry.set_field(field));
+        entry.change_otp(self.otp.clone());
+        entry.change_folder(self.folder.as_ref());
+        entry.set_reprompt(self.reprompt);
+        self.tag.iter().for_each(|tag| {
+            entry.add_tag(tag);
+        });
+        manager.push_entry(entry);
 
         // New entries are pushed to database
         manager.dump(PASS_ENTRY_STORE.to_path_buf())?;
 
         if self.random_password {
-            colour::green_ln!("\nPassword copied to clipboard");
-            copy_to_clipboard(
+            println!();
+            copy_secret(
+                "Password",
                 self.password
-                    .clone()
+                    .as_deref()
                     .expect("Unreachable: Password is generated randomly"),
-            )
-            .expect("Unable to clipboard");
+                false,
+            )?;
         }
 
         // TODO: Impl Drop trait to automatically dump all password entries in DB
@@ -154,16 +530,19 @@ impl AddArgs {
             });
 
         // Prompt for notes & set in object
-        self.notes
-            .is_none()
-            .then(|| -> Result<(), PasswordStoreError> {
-                self.borrow_mut().notes = prompt_string(
-                    format!("Enter notes for {service}: "),
-                    "Press <Esc> to skip the notes",
-                )
-                .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?;
-                Ok(())
-            });
+        self.notes.is_none().then(|| self.set_notes());
+    }
+
+    fn set_notes(&mut self) -> Result<(), PasswordStoreError> {
+        if self.notes.is_none() {
+            self.notes = prompt_string(
+                format!("Enter notes for {}: ", self.service),
+                "Press <Esc> to skip the notes",
+            )
+            .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?;
+        }
+
+        Ok(())
     }
 
     fn set_password(&mut self) -> Result<(), PasswordStoreError> {
@@ -225,7 +604,12 @@ impl AddArgs {
 #[derive(Args)]
 pub struct RemoveArgs {
     /// Service name for identify any password
-    service: String,
+    #[arg(required_unless_present = "id")]
+    service: Option<String>,
+
+    /// Id of the entry (shown by `list --show-ids`), instead of service name
+    #[arg(long, conflicts_with = "service")]
+    id: Option<Uuid>,
 }
 
 impl RemoveArgs {
@@ -233,9 +617,15 @@ impl RemoveArgs {
         &mut self,
         master_password: MasterPassword<Verified>,
     ) -> anyhow::Result<()> {
-        let manager = PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), master_password.clone())?;
+        let manager = PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        if let Some(id) = self.id {
+            let entry = manager.get_by_id(id)?;
+            print_pass_entry_info([entry.clone()]);
+            return Self::handle_one_entry_found(manager, vec![entry]);
+        }
 
-        let found_entry = manager.get(&self.service);
+        let found_entry = manager.get(self.service());
 
         if found_entry.is_empty() {
             self.handle_no_entry_found(manager)?;
@@ -248,17 +638,17 @@ impl RemoveArgs {
         Ok(())
     }
 
-    fn handle_no_entry_found(&self, mut manager: PasswordStore) -> Result<(), PasswordStoreError> {
+    fn handle_no_entry_found(&self, manager: PasswordStore) -> anyhow::Result<()> {
         colour::e_red_ln!(
             "Can't find matching entry with service name '{}'",
-            self.service
+            self.service()
         );
 
         let fuzzy_search_choice = ask_for_confirm("Want to do fuzzy search for this?")
             .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?;
 
         if fuzzy_search_choice {
-            let fuzzy_search = manager.fuzzy_find(&self.service);
+            let fuzzy_search = manager.fuzzy_find(self.service());
             print_pass_entry_info(&fuzzy_search);
 
             match choose_entry_with_interaction(
@@ -266,12 +656,11 @@ impl RemoveArgs {
                 "Which entry to remove? (eg. 1,2,3): ",
             ) {
                 Ok(entry) => {
-                    ask_for_confirm("Confirm to remove? ")
+                    if ask_for_confirm("Confirm to remove? ")
                         .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?
-                        .then(|| -> Result<(), PasswordStoreError> {
-                            manager.remove(vec![entry])?;
-                            Ok(())
-                        });
+                    {
+                        Self::remove(manager, vec![entry])?;
+                    }
                 }
                 Err(_) => {
                     colour::e_red_ln!("there is nothing to do");
@@ -284,14 +673,20 @@ impl RemoveArgs {
         Ok(())
     }
 
+    fn service(&self) -> &str {
+        self.service
+            .as_deref()
+            .expect("Unreachable: Service is required without id")
+    }
+
     fn handle_one_entry_found(
-        mut manager: PasswordStore,
+        manager: PasswordStore,
         found_entry: Vec<PasswordEntry>,
-    ) -> Result<(), PasswordStoreError> {
+    ) -> anyhow::Result<()> {
         colour::blue_ln!("Found matching service name");
 
         match ask_for_confirm("Confirm to remove this entry?") {
-            Ok(true) => manager.remove(found_entry)?,
+            Ok(true) => Self::remove(manager, found_entry)?,
             Err(_) | Ok(false) => {
                 colour::e_red_ln!("Aborted!!");
             }
@@ -301,15 +696,15 @@ impl RemoveArgs {
     }
 
     fn handle_multiple_entry_found(
-        mut manager: PasswordStore,
+        manager: PasswordStore,
         found_entry: Vec<PasswordEntry>,
-    ) -> Result<(), PasswordStoreError> {
+    ) -> anyhow::Result<()> {
         colour::green_ln!("Found {} matching entries", found_entry.len());
         print_pass_entry_info(&found_entry);
 
         match choose_entry_with_interaction(found_entry, "Which entry to remove? (eg. 1,2,3): ") {
             Ok(entry) => {
-                manager.remove(vec![entry])?;
+                Self::remove(manager, vec![entry])?;
             }
             Err(_) => {
                 colour::e_red_ln!("there is nothing to do");
@@ -318,165 +713,1121 @@ impl RemoveArgs {
 
         Ok(())
     }
+
+    /// Remove the entries, protected entries need master password again
+    fn remove(mut manager: PasswordStore, entries: Vec<PasswordEntry>) -> anyhow::Result<()> {
+        entries.iter().try_for_each(reprompt_master_password)?;
+        manager.remove(entries, PASS_ENTRY_STORE.as_path())?;
+
+        Ok(())
+    }
 }
 
 #[derive(Args)]
 pub struct UpdateArgs {
     /// Service name for identify any password
-    service: String,
-}
+    #[arg(required_unless_present = "id")]
+    service: Option<String>,
 
-pub fn list_entries(master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
-    let manager = PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), master_password)?;
+    /// Id of the entry (shown by `list --show-ids`), instead of service name
+    #[arg(long, conflicts_with = "service")]
+    id: Option<Uuid>,
 
-    print_table(manager.passwords);
+    /// New username/email of the account
+    #[clap(long, short, aliases=&["user"], default_value = None)]
+    username: Option<String>,
 
-    println!("Not showing passwords due to security reasons");
-    println!("Use '$ pass_rs get <service>' to get password entry info with password");
+    /// New password of the account
+    #[clap(long, short, default_value = None, conflicts_with = "generate")]
+    password: Option<String>,
 
-    Ok(())
-}
+    /// Generate a new random password for the account
+    #[clap(long, short)]
+    generate: bool,
 
-#[derive(Args)]
-pub struct GetArgs {
-    /// Service name to identify any password
-    service: String,
+    /// New notes for the account
+    #[clap(long, short, default_value = None)]
+    notes: Option<String>,
 
-    #[arg(short, long)]
-    print: bool,
+    #[command(flatten)]
+    fields: FieldArgs,
+
+    /// Remove a custom field of the account
+    #[arg(long, value_name = "NAME")]
+    remove_field: Vec<String>,
+
+    /// New TOTP/HOTP secret of the account, as `otpauth://` URI or base32 secret
+    #[arg(long, value_name = "URI|SECRET", value_parser = parse_otp, conflicts_with = "remove_otp")]
+    otp: Option<Otp>,
+
+    /// Remove the TOTP/HOTP secret of the account
+    #[arg(long)]
+    remove_otp: bool,
+
+    /// Move the entry into a folder, `/` moves it to the top level
+    #[arg(long)]
+    folder: Option<String>,
+
+    /// Add a tag to the entry
+    #[arg(long)]
+    tag: Vec<String>,
+
+    /// Remove a tag of the entry
+    #[arg(long, value_name = "TAG")]
+    remove_tag: Vec<String>,
+
+    /// Ask master password again before revealing or changing the entry
+    #[arg(long, conflicts_with = "no_reprompt")]
+    reprompt: bool,
+
+    /// Stop asking master password again for the entry
+    #[arg(long)]
+    no_reprompt: bool,
 }
 
-impl GetArgs {
-    pub fn get_entries(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
-        let manager = PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), master_password)?;
+impl UpdateArgs {
+    pub fn update_entries(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let mut manager = PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
 
-        let result = manager.get(&self.service);
+        let found_entry = match self.id {
+            Some(id) => vec![manager.get_by_id(id)?],
+            None => manager.get(self.service()),
+        };
 
-        if result.is_empty() {
-            self.handle_no_entry_found(manager)?;
-        } else if result.len() == 1 {
-            self.handle_one_entry_found(result)?;
+        let entry = if found_entry.is_empty() {
+            self.handle_no_entry_found(&manager)?
+        } else if found_entry.len() == 1 {
+            colour::blue_ln!("Found matching service name");
+            found_entry.first().cloned()
         } else {
-            self.handle_multiple_entry_found(result)?;
+            colour::green_ln!("Found {} matching entries", found_entry.len());
+            print_pass_entry_info(&found_entry);
+
+            choose_entry_with_interaction(found_entry, "Which entry to update? (eg. 1,2,3): ").ok()
+        };
+
+        let Some(entry) = entry else {
+            colour::e_red_ln!("there is nothing to do");
+            return Ok(());
+        };
+        reprompt_master_password(&entry)?;
+
+        let (updated_entry, generated_password) = self.update_entry(entry.clone())?;
+
+        // Replace the entry & store the changes in database
+        manager.update(&entry, updated_entry)?;
+        manager.dump(PASS_ENTRY_STORE.to_path_buf())?;
+
+        colour::green_ln!("Successfully updated entry");
+
+        if let Some(password) = generated_password {
+            copy_secret("Password", password, false)?;
         }
 
         Ok(())
     }
 
-    fn handle_no_entry_found(&self, manager: PasswordStore) -> Result<(), PasswordStoreError> {
+    fn service(&self) -> &str {
+        self.service
+            .as_deref()
+            .expect("Unreachable: Service is required without id")
+    }
+
+    fn handle_no_entry_found(
+        &self,
+        manager: &PasswordStore,
+    ) -> Result<Option<PasswordEntry>, PasswordStoreError> {
         colour::e_red_ln!(
             "Can't find matching entry with service name '{}'",
-            self.service
+            self.service()
         );
 
         let fuzzy_search_choice = ask_for_confirm("Want to do fuzzy search for this?")
             .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?;
 
-        if fuzzy_search_choice {
-            let fuzzy_search = manager.fuzzy_find(&self.service);
-            print_pass_entry_info(&fuzzy_search);
-
-            match choose_entry_with_interaction(
-                fuzzy_search,
-                "Which entry password to show? (eg. 1,2,3): ",
-            ) {
-                Ok(entry) => {
-                    self.print_pass(entry);
-                }
-                Err(_) => {
-                    colour::e_red_ln!("there is nothing to do");
-                }
-            };
-        } else {
-            colour::e_red_ln!("there is nothing to do");
+        if !fuzzy_search_choice {
+            return Ok(None);
         }
 
-        Ok(())
+        let fuzzy_search = manager.fuzzy_find(self.service());
+        print_pass_entry_info(&fuzzy_search);
+
+        Ok(
+            choose_entry_with_interaction(fuzzy_search, "Which entry to update? (eg. 1,2,3): ")
+                .ok(),
+        )
     }
 
-    fn handle_one_entry_found(
+    /// Apply the flags (or prompt for the missing ones) to the entry.
+    /// Also returns the new password if it was randomly generated.
+    fn update_entry(
         &self,
-        found_entry: impl AsRef<[PasswordEntry]>,
-    ) -> Result<(), PasswordStoreError> {
-        let entry = found_entry
-            .as_ref()
-            .first()
-            .expect("Unreachable: Only one entry exists at index 0")
-            .clone();
+        mut entry: PasswordEntry,
+    ) -> Result<(PasswordEntry, Option<String>), PasswordStoreError> {
+        let service = entry.service.clone();
 
-        // Print the password of found 1 entry
-        self.print_pass(entry);
+        println!();
 
-        Ok(())
-    }
+        // Update the username
+        match &self.username {
+            Some(username) => entry.change_username(Some(username.clone())),
+            None => {
+                if let Some(username) = prompt_string(
+                    format!("Enter new username for {service}: "),
+                    "Press <Esc> to keep the current username",
+                )
+                .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?
+                {
+                    entry.change_username(Some(username));
+                }
+            }
+        };
 
-    fn handle_multiple_entry_found(
-        &self,
-        found_entry: Vec<PasswordEntry>,
-    ) -> Result<(), PasswordStoreError> {
-        colour::green_ln!("Found {} matching entries", found_entry.len());
-        print_pass_entry_info(&found_entry);
+        // Update the password
+        let mut generated_password = None;
+        if let Some(password) = &self.password {
+            entry.change_password(password);
+        } else if self.generate {
+            let password = GenArgs::default()
+                .generator()
+                .generate_one()
+                .expect("Unreachable: Default generator is always valid");
+            entry.change_password(&password);
+            generated_password = Some(password);
+        } else if ask_for_confirm("Change password?")
+            .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?
+        {
+            let random = ask_for_confirm("Generate random password?")
+                .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?;
 
-        match choose_entry_with_interaction(
-            found_entry,
-            "Which entry password to show? (eg. 1,2,3): ",
-        ) {
-            Ok(entry) => {
-                self.print_pass(entry);
+            if random {
+                let password = AddArgs::generate_random_password_with_interaction()?;
+                entry.change_password(&password);
+                generated_password = Some(password);
+            } else {
+                entry.change_password(AddArgs::generate_new_password()?);
             }
-            Err(_) => {
-                colour::e_red_ln!("there is nothing to do");
+        }
+
+        // Update the notes
+        match &self.notes {
+            Some(notes) => entry.change_notes(Some(notes.clone())),
+            None => {
+                if let Some(notes) = prompt_string(
+                    format!("Enter new notes for {service}: "),
+                    "Press <Esc> to keep the current notes",
+                )
+                .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?
+                {
+                    entry.change_notes(Some(notes));
+                }
             }
         };
 
-        Ok(())
-    }
-
-    fn print_pass(&self, entry: PasswordEntry) {
-        let password = entry.get_pass_str();
+        // Update the custom fields
+        for name in &self.remove_field {
+            if !entry.remove_field(name) {
+                colour::e_red_ln!("No field '{name}' in entry of {service}");
+            }
+        }
+        self.fields
+            .custom_fields()?
+            .into_iter()
+            .for_each(|field| entry.set_field(field));
+
+        // Update the one-time password secret
+        if self.remove_otp {
+            entry.change_otp(None);
+        } else if let Some(otp) = &self.otp {
+            entry.change_otp(Some(otp.clone()));
+        }
 
-        copy_to_clipboard(password.clone()).expect("Unable to copy to clipboard");
-        colour::green_ln!("Password copied to clipboard");
+        // Update the folder & tags
+        if let Some(folder) = &self.folder {
+            entry.change_folder(Some(folder));
+        }
+        for tag in &self.remove_tag {
+            if !entry.remove_tag(tag) {
+                colour::e_red_ln!("No tag '{tag}' in entry of {service}");
+            }
+        }
+        self.tag.iter().for_each(|tag| {
+            entry.add_tag(tag);
+        });
 
-        if self.print {
-            colour::yellow_ln!("Password: {}", password);
+        if self.reprompt || self.no_reprompt {
+            entry.set_reprompt(self.reprompt);
         }
+
+        Ok((entry, generated_password))
     }
 }
 
-#[derive(Args, Debug)]
-pub struct SearchArgs {
-    service: String,
-}
+#[derive(Args)]
+pub struct ListArgs {
+    /// Only list entries in the folder (or its subfolders)
+    #[arg(long)]
+    folder: Option<String>,
 
-impl SearchArgs {
-    pub fn fuzzy_search(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
-        let manager = PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), master_password)?;
+    /// Only list entries having the tag, all of the tags if given multiple times
+    #[arg(long)]
+    tag: Vec<String>,
 
-        let result = manager.fuzzy_find(self.service.clone());
-        match result.is_empty() {
-            true => {
-                colour::e_red_ln!("No entry exist related to '{}'", self.service);
-            }
-            false => {
-                // TODO: Make methods like fuzzy_find_by_username & fuzzy_find_by_service
-                colour::green_ln!("Your search results: ");
+    /// Show the id of entries, to target them by `--id`
+    #[arg(long)]
+    show_ids: bool,
 
-                print_table(result);
-            }
-        };
+    /// Show additional columns
+    #[arg(long, value_enum, value_delimiter = ',')]
+    columns: Vec<ListColumn>,
 
-        Ok(())
-    }
+    /// Sort entries by the field, oldest first for timestamps
+    #[arg(long, value_enum)]
+    sort: Option<SortKey>,
+
+    /// Only list entries not modified for the age, e.g. `12h`, `180d`, `4w`, `6m` or `1y`
+    #[arg(long, value_name = "AGE", value_parser = parse_age)]
+    older_than: Option<TimeDelta>,
 }
 
-#[derive(Args, Debug)]
-pub struct GenArgs {
-    /// Length of generated password
-    #[clap(default_value_t = 12)]
-    length: usize,
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListColumn {
+    Created,
+    Modified,
+    PasswordChanged,
+    Accessed,
+}
 
-    /// Number of password to be generated
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Service,
+    Created,
+    Modified,
+    PasswordChanged,
+    Accessed,
+}
+
+impl From<ListColumn> for TableColumn {
+    fn from(value: ListColumn) -> Self {
+        match value {
+            ListColumn::Created => TableColumn::Created,
+            ListColumn::Modified => TableColumn::Modified,
+            ListColumn::PasswordChanged => TableColumn::PasswordChanged,
+            ListColumn::Accessed => TableColumn::Accessed,
+        }
+    }
+}
+
+/// Parse `<number><unit>`, where unit is one of `h`, `d`, `w`, `m` (30 days) or `y` (365 days)
+fn parse_age(value: &str) -> Result<TimeDelta, String> {
+    let value = value.trim();
+    let split = value.len() - value.chars().last().map_or(0, char::len_utf8);
+    let (number, unit) = value.split_at(split);
+
+    let number = number
+        .parse::<i64>()
+        .map_err(|_| format!("Invalid age '{value}', expected e.g. 180d"))?;
+    let hours = match unit {
+        "h" => 1,
+        "d" => 24,
+        "w" => 7 * 24,
+        "m" => 30 * 24,
+        "y" => 365 * 24,
+        _ => {
+            return Err(format!(
+                "Invalid unit of age '{value}', expected h, d, w, m or y"
+            ))
+        }
+    };
+
+    number
+        .checked_mul(hours)
+        .and_then(TimeDelta::try_hours)
+        .ok_or_else(|| format!("Age '{value}' is too large"))
+}
+
+/// Parse `<number>[unit]`, where unit is one of `K`, `M` or `G` (binary), bytes otherwise
+fn parse_size(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((idx, 'K' | 'k')) => (&value[..idx], 1 << 10),
+        Some((idx, 'M' | 'm')) => (&value[..idx], 1 << 20),
+        Some((idx, 'G' | 'g')) => (&value[..idx], 1 << 30),
+        _ => (value, 1),
+    };
+
+    number
+        .parse::<u64>()
+        .map_err(|_| format!("Invalid size '{value}', expected e.g. 10M"))?
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("Size '{value}' is too large"))
+}
+
+impl ListArgs {
+    pub fn list_entries(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let manager =
+            PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        let mut entries = manager.filter(self.folder.as_ref(), &self.tag);
+
+        // Entries without modification time are older than timestamps, so these are stale too
+        if let Some(age) = self.older_than {
+            let threshold = Utc::now() - age;
+            entries.retain(|entry| entry.modified_at.is_none_or(|time| time < threshold));
+        }
+
+        match self.sort {
+            Some(SortKey::Service) => entries.sort_by(|a, b| a.service.cmp(&b.service)),
+            Some(SortKey::Created) => entries.sort_by_key(|entry| entry.created_at),
+            Some(SortKey::Modified) => entries.sort_by_key(|entry| entry.modified_at),
+            Some(SortKey::PasswordChanged) => {
+                entries.sort_by_key(|entry| entry.password_changed_at)
+            }
+            Some(SortKey::Accessed) => entries.sort_by_key(|entry| entry.last_accessed_at),
+            None => {}
+        }
+
+        // Timestamp used for sorting or filtering is always shown
+        let mut columns = self.columns.clone();
+        let implied = match self.sort {
+            Some(SortKey::Created) => Some(ListColumn::Created),
+            Some(SortKey::Modified) => Some(ListColumn::Modified),
+            Some(SortKey::PasswordChanged) => Some(ListColumn::PasswordChanged),
+            Some(SortKey::Accessed) => Some(ListColumn::Accessed),
+            Some(SortKey::Service) | None => None,
+        };
+        let older_than = self.older_than.map(|_| ListColumn::Modified);
+        for column in [implied, older_than].into_iter().flatten() {
+            if !columns.contains(&column) {
+                columns.push(column);
+            }
+        }
+
+        let mut columns = columns.into_iter().map(Into::into).collect::<Vec<_>>();
+        if self.show_ids {
+            columns.push(TableColumn::Id);
+        }
+
+        print_table(entries, &columns);
+
+        println!("Not showing passwords due to security reasons");
+        println!("Use '$ pass_rs get <service>' to get password entry info with password");
+
+        Ok(())
+    }
+}
+
+/// Check integrity of the vault, offering to restore it from backup if it is unreadable.
+/// Exits with a distinct code unless the vault is healthy
+pub fn doctor(source: &PasswordSource) -> anyhow::Result<()> {
+    let store = PASS_ENTRY_STORE.as_path();
+    if !backup_path(store).exists() {
+        handle_master_not_initialised();
+    }
+
+    let mut doctor = Doctor::new(store, MASTER_PASS_STORE.as_path());
+    doctor.check_files();
+
+    let master = MasterPassword::from_pass(source.read()?)?;
+    doctor.check_vault(&master);
+    doctor.check_backup(&master);
+    print_findings(&doctor);
+
+    if doctor.is_restorable() {
+        let backup = backup_path(store);
+        let message = format!(
+            "Restore vault from {}? Changes saved after it are lost",
+            backup.display()
+        );
+
+        if ask_for_confirm(message).unwrap_or(false) {
+            PasswordStore::restore_backup(store, &master)?;
+
+            doctor = Doctor::new(store, MASTER_PASS_STORE.as_path());
+            doctor.check_files();
+            doctor.check_vault(&master);
+            print_findings(&doctor);
+        }
+    }
+
+    if let Some(code) = exit_code(&doctor) {
+        lock::exit(code);
+    }
+    Ok(())
+}
+
+/// Exit code of doctor for its findings, none for a healthy vault
+fn exit_code(doctor: &Doctor) -> Option<i32> {
+    match doctor.severity() {
+        _ if doctor.is_wrong_password() => Some(WRONG_MASTER_PASSWORD_EXIT_CODE),
+        Severity::Ok => None,
+        Severity::Warning => Some(VAULT_ISSUES_EXIT_CODE),
+        Severity::Error => Some(VAULT_DAMAGED_EXIT_CODE),
+    }
+}
+
+fn print_findings(doctor: &Doctor) {
+    for finding in doctor.findings() {
+        let line = format!("{:<16} {}", finding.check.name(), finding.message);
+        match finding.severity {
+            Severity::Ok => colour::green_ln!("ok     {line}"),
+            Severity::Warning => colour::yellow_ln!("warn   {line}"),
+            Severity::Error => colour::red_ln!("error  {line}"),
+        }
+    }
+
+    match doctor.severity() {
+        Severity::Ok => colour::green_ln!("Vault is healthy"),
+        Severity::Warning => colour::yellow_ln!("Vault is readable, but has issues"),
+        Severity::Error => colour::red_ln!("Vault is damaged"),
+    }
+}
+
+pub fn list_tags(master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+    let manager = PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+    let tags = manager.tags();
+    if tags.is_empty() {
+        colour::green_ln!("No tags available");
+        return Ok(());
+    }
+
+    tags.iter()
+        .for_each(|(tag, count)| colour::green_ln!("{} ({})", tag, count));
+
+    Ok(())
+}
+
+#[derive(Args)]
+pub struct GetArgs {
+    /// Service name to identify any password
+    #[arg(required_unless_present = "id")]
+    service: Option<String>,
+
+    /// Id of the entry (shown by `list --show-ids`), instead of service name
+    #[arg(long, conflicts_with = "service")]
+    id: Option<Uuid>,
+
+    /// Print the secret too, needed where clipboard is unavailable (e.g. over SSH)
+    #[arg(short, long)]
+    print: bool,
+
+    /// Copy a custom field instead of the password
+    #[arg(short, long, value_name = "NAME")]
+    field: Option<String>,
+}
+
+impl GetArgs {
+    pub fn get_entries(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let mut manager =
+            PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        if let Some(id) = self.id {
+            let entry = manager.get_by_id(id)?;
+            self.print_pass(&mut manager, entry)?;
+            return Ok(());
+        }
+
+        let result = manager.get(self.service());
+
+        if result.is_empty() {
+            self.handle_no_entry_found(&mut manager)?;
+        } else if result.len() == 1 {
+            self.handle_one_entry_found(&mut manager, result)?;
+        } else {
+            self.handle_multiple_entry_found(&mut manager, result)?;
+        }
+
+        Ok(())
+    }
+
+    fn service(&self) -> &str {
+        self.service
+            .as_deref()
+            .expect("Unreachable: Service is required without id")
+    }
+
+    fn handle_no_entry_found(&self, manager: &mut PasswordStore) -> anyhow::Result<()> {
+        colour::e_red_ln!(
+            "Can't find matching entry with service name '{}'",
+            self.service()
+        );
+
+        let fuzzy_search_choice = ask_for_confirm("Want to do fuzzy search for this?")
+            .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?;
+
+        if fuzzy_search_choice {
+            let fuzzy_search = manager.fuzzy_find(self.service());
+            print_pass_entry_info(&fuzzy_search);
+
+            match choose_entry_with_interaction(
+                fuzzy_search,
+                "Which entry password to show? (eg. 1,2,3): ",
+            ) {
+                Ok(entry) => {
+                    self.print_pass(manager, entry)?;
+                }
+                Err(_) => {
+                    colour::e_red_ln!("there is nothing to do");
+                }
+            };
+        } else {
+            colour::e_red_ln!("there is nothing to do");
+        }
+
+        Ok(())
+    }
+
+    fn handle_one_entry_found(
+        &self,
+        manager: &mut PasswordStore,
+        found_entry: impl AsRef<[PasswordEntry]>,
+    ) -> anyhow::Result<()> {
+        let entry = found_entry
+            .as_ref()
+            .first()
+            .expect("Unreachable: Only one entry exists at index 0")
+            .clone();
+
+        // Print the password of found 1 entry
+        self.print_pass(manager, entry)?;
+
+        Ok(())
+    }
+
+    fn handle_multiple_entry_found(
+        &self,
+        manager: &mut PasswordStore,
+        found_entry: Vec<PasswordEntry>,
+    ) -> anyhow::Result<()> {
+        colour::green_ln!("Found {} matching entries", found_entry.len());
+        print_pass_entry_info(&found_entry);
+
+        match choose_entry_with_interaction(
+            found_entry,
+            "Which entry password to show? (eg. 1,2,3): ",
+        ) {
+            Ok(entry) => {
+                self.print_pass(manager, entry)?;
+            }
+            Err(_) => {
+                colour::e_red_ln!("there is nothing to do");
+            }
+        };
+
+        Ok(())
+    }
+
+    /// Show the password (or field) of entry & record that it is used
+    fn print_pass(&self, manager: &mut PasswordStore, entry: PasswordEntry) -> anyhow::Result<()> {
+        reprompt_master_password(&entry)?;
+
+        // Only the secrets of used entry are decrypted
+        let entry = manager.unseal(entry.id)?;
+
+        match &self.field {
+            Some(name) => self.print_field(&entry, name)?,
+            None => {
+                match entry.kind.secret() {
+                    Some(secret) => self.print_kind(&entry, secret)?,
+                    None => {
+                        let password = entry.get_pass_str();
+
+                        copy_secret("Password", password, self.print)?;
+                        if self.print {
+                            colour::yellow_ln!("Password: {}", password);
+                        }
+                    }
+                }
+
+                // Hidden fields are only revealed by `--field`
+                for field in &entry.fields {
+                    colour::blue_ln!("{}: {}", field.name, field.display_value());
+                }
+            }
+        }
+
+        manager.entry_mut(entry.id)?.mark_accessed();
+        manager.dump(PASS_ENTRY_STORE.as_path())?;
+
+        Ok(())
+    }
+
+    /// Copy the main secret of typed entry, all its details are shown only by `--print`
+    fn print_kind(
+        &self,
+        entry: &PasswordEntry,
+        (name, secret): (&str, &str),
+    ) -> anyhow::Result<()> {
+        copy_secret(name, secret, self.print)?;
+
+        match self.print {
+            true => entry
+                .kind
+                .details()
+                .iter()
+                .for_each(|(name, value)| colour::yellow_ln!("{}: {}", name, value)),
+            false => colour::blue_ln!("{}", entry.kind.summary()),
+        }
+
+        Ok(())
+    }
+
+    fn print_field(&self, entry: &PasswordEntry, name: impl AsRef<str>) -> anyhow::Result<()> {
+        let Some(field) = entry.field(name.as_ref()) else {
+            colour::e_red_ln!("No field '{}' in entry of {}", name.as_ref(), entry.service);
+            return Ok(());
+        };
+
+        copy_secret(&field.name, field.value.expose(), self.print)?;
+        if self.print {
+            colour::yellow_ln!("{}: {}", field.name, field.value.expose());
+        }
+
+        Ok(())
+    }
+}
+
+/// Entry of service, offering fuzzy search if none matches & a choice if many match
+fn select_entry(
+    manager: &PasswordStore,
+    service: impl AsRef<str>,
+) -> Result<Option<PasswordEntry>, PasswordStoreError> {
+    let service = service.as_ref();
+    let found_entry = manager.get(service);
+
+    if !found_entry.is_empty() {
+        if found_entry.len() > 1 {
+            colour::green_ln!("Found {} matching entries", found_entry.len());
+            print_pass_entry_info(&found_entry);
+        }
+        return Ok(choose_entry_with_interaction(found_entry, "Which entry? (eg. 1,2,3): ").ok());
+    }
+
+    colour::e_red_ln!("Can't find matching entry with service name '{service}'");
+
+    let fuzzy_search_choice = ask_for_confirm("Want to do fuzzy search for this?")
+        .map_err(|_| PasswordStoreError::UnableToReadFromConsole)?;
+    if !fuzzy_search_choice {
+        return Ok(None);
+    }
+
+    let fuzzy_search = manager.fuzzy_find(service);
+    print_pass_entry_info(&fuzzy_search);
+    Ok(choose_entry_with_interaction(fuzzy_search, "Which entry? (eg. 1,2,3): ").ok())
+}
+
+#[derive(Args)]
+pub struct OtpArgs {
+    /// Service name to identify any password
+    service: String,
+}
+
+impl OtpArgs {
+    pub fn show_code(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let mut manager =
+            PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        let Some(entry) = select_entry(&manager, &self.service)? else {
+            colour::e_red_ln!("there is nothing to do");
+            return Ok(());
+        };
+        let entry = manager.unseal(entry.id)?;
+
+        let Some(mut otp) = entry.otp.clone() else {
+            colour::e_red_ln!("No one-time password in entry of {}", entry.service);
+            return Ok(());
+        };
+        reprompt_master_password(&entry)?;
+
+        let (code, remaining) = otp.next_code();
+
+        // Counter of HOTP has moved, so the same code is never given twice
+        let stored_entry = manager.entry_mut(entry.id)?;
+        if let OtpKind::Hotp { .. } = otp.kind {
+            stored_entry.change_otp(Some(otp));
+        }
+        stored_entry.mark_accessed();
+        manager.dump(PASS_ENTRY_STORE.as_path())?;
+
+        colour::yellow_ln!("Code: {}", code);
+        if let Some(remaining) = remaining {
+            colour::blue_ln!("Valid for {}s", remaining);
+        }
+
+        match copy_to_clipboard(code) {
+            Ok(()) => colour::green_ln!("Code copied to clipboard"),
+            Err(e) => colour::e_red_ln!("{}", e),
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    /// Service name to identify any password
+    #[arg(required_unless_present = "id")]
+    service: Option<String>,
+
+    /// Id of the entry (shown by `list --show-ids`), instead of service name
+    #[arg(long, conflicts_with = "service")]
+    id: Option<Uuid>,
+
+    /// Make the previous password of the number (as listed) current again
+    #[arg(long, value_name = "N")]
+    restore: Option<usize>,
+}
+
+impl HistoryArgs {
+    pub fn run(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let mut manager =
+            PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        let entry = match (self.id, &self.service) {
+            (Some(id), _) => Some(manager.get_by_id(id)?),
+            (None, Some(service)) => select_entry(&manager, service)?,
+            (None, None) => None,
+        };
+        let Some(entry) = entry else {
+            colour::e_red_ln!("there is nothing to do");
+            return Ok(());
+        };
+        let entry = manager.unseal(entry.id)?;
+
+        match self.restore {
+            Some(n) => {
+                reprompt_master_password(&entry)?;
+                Self::restore(&mut manager, entry, n)?
+            }
+            None => Self::print_history(&entry),
+        }
+
+        Ok(())
+    }
+
+    fn print_history(entry: &PasswordEntry) {
+        if entry.history.is_empty() {
+            colour::green_ln!("No previous passwords of {}", entry.service);
+            return;
+        }
+
+        colour::green_ln!("Previous passwords of {} (latest first):", entry.service);
+        entry
+            .history
+            .iter()
+            .enumerate()
+            .for_each(|(idx, previous)| {
+                println!(
+                    "{}. ******** (set: {}, replaced: {})",
+                    idx + 1,
+                    previous
+                        .set_at
+                        .map(format_time)
+                        .unwrap_or("unknown".to_string()),
+                    format_time(previous.replaced_at)
+                )
+            });
+        println!("Use '$ pass_rs history <service> --restore <N>' to roll back");
+    }
+
+    fn restore(
+        manager: &mut PasswordStore,
+        entry: PasswordEntry,
+        n: usize,
+    ) -> Result<(), PasswordStoreError> {
+        let mut restored_entry = entry.clone();
+        if !restored_entry.restore_password(n) {
+            match n == 0 || n > entry.history.len() {
+                true => colour::e_red_ln!(
+                    "No previous password {} of {}, it has {}",
+                    n,
+                    entry.service,
+                    entry.history.len()
+                ),
+                false => colour::e_red_ln!(
+                    "Previous password {} of {} is the current password",
+                    n,
+                    entry.service
+                ),
+            }
+            return Err(PasswordStoreError::NothingToDo);
+        }
+
+        manager.update(&entry, restored_entry)?;
+        manager.dump(PASS_ENTRY_STORE.as_path())?;
+
+        colour::green_ln!("Restored previous password {} of {}", n, entry.service);
+
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+pub struct ConfigArgs {
+    /// Number of previous passwords kept by each entry
+    #[arg(long, value_name = "N")]
+    history_depth: Option<usize>,
+
+    /// Largest attachment, e.g. `512K` or `10M`
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    attachment_limit: Option<u64>,
+}
+
+impl ConfigArgs {
+    pub fn run(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let mut manager =
+            PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+        let settings = manager.payload.settings.clone();
+
+        if self.history_depth.is_none() && self.attachment_limit.is_none() {
+            colour::green_ln!("History depth: {}", settings.history_depth);
+            colour::green_ln!(
+                "Attachment limit: {}",
+                format_size(settings.attachment_limit)
+            );
+            return Ok(());
+        }
+
+        if let Some(depth) = self.history_depth {
+            let is_shrunk = depth < settings.history_depth;
+            if is_shrunk
+                && !ask_for_confirm(
+                    "Previous passwords beyond the new depth are forgotten. Continue?",
+                )?
+            {
+                colour::e_red_ln!("Aborted!!");
+                return Ok(());
+            }
+
+            manager.set_history_depth(depth)?;
+            colour::green_ln!("History depth set to {}", depth);
+        }
+
+        // Limit only applies to new attachments
+        if let Some(limit) = self.attachment_limit {
+            manager.payload.settings.attachment_limit = limit;
+            colour::green_ln!("Attachment limit set to {}", format_size(limit));
+        }
+
+        manager.dump(PASS_ENTRY_STORE.as_path())?;
+
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+pub struct AttachArgs {
+    /// Service name to identify any password
+    service: String,
+
+    /// File to attach
+    file: PathBuf,
+
+    /// Name of the attachment (default: name of the file)
+    #[arg(long)]
+    name: Option<String>,
+}
+
+impl AttachArgs {
+    pub fn run(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let mut manager =
+            PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        let Some(entry) = select_entry(&manager, &self.service)? else {
+            colour::e_red_ln!("there is nothing to do");
+            return Ok(());
+        };
+        reprompt_master_password(&entry)?;
+        let entry = manager.unseal(entry.id)?;
+
+        let name = match (&self.name, self.file.file_name()) {
+            (Some(name), _) => name.clone(),
+            (None, Some(name)) => name.to_string_lossy().into_owned(),
+            (None, None) => Err(CliError::NoAttachmentName)?,
+        };
+        let is_replaced = entry.attachment(&name).is_some();
+        if is_replaced
+            && !ask_for_confirm(format!("Replace attachment '{name}' of {}?", entry.service))?
+        {
+            colour::e_red_ln!("Aborted!!");
+            return Ok(());
+        }
+
+        // Oversized file is refused before it is read into memory
+        let size = std::fs::metadata(&self.file)
+            .map_err(CliError::UnableToReadFile)?
+            .len();
+        manager.check_attachment_size(size)?;
+        let content = std::fs::read(&self.file).map_err(CliError::UnableToReadFile)?;
+
+        let blob_dir = attachments_dir(PASS_ENTRY_STORE.as_path());
+        manager.attach(entry.id, &name, content, &blob_dir)?;
+        manager.dump(PASS_ENTRY_STORE.as_path())?;
+
+        // Blob of the replaced attachment is no longer referenced, which is known only by the
+        // attachments of every entry
+        if is_replaced {
+            manager.unseal_all()?;
+            manager.collect_garbage(PASS_ENTRY_STORE.as_path())?;
+        }
+
+        colour::green_ln!(
+            "Attached '{}' ({}) to {}",
+            name,
+            format_size(size),
+            entry.service
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+pub struct AttachmentsArgs {
+    /// Service name to identify any password
+    service: String,
+}
+
+impl AttachmentsArgs {
+    pub fn run(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let mut manager =
+            PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        let Some(entry) = select_entry(&manager, &self.service)? else {
+            colour::e_red_ln!("there is nothing to do");
+            return Ok(());
+        };
+        let entry = manager.unseal(entry.id)?;
+
+        if entry.attachments.is_empty() {
+            colour::green_ln!("No attachments in entry of {}", entry.service);
+            return Ok(());
+        }
+
+        for attachment in &entry.attachments {
+            colour::green_ln!(
+                "{} ({}, added: {})",
+                attachment.name,
+                format_size(attachment.size),
+                format_time(attachment.added_at)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+pub struct ExtractArgs {
+    /// Service name to identify any password
+    service: String,
+
+    /// Name of the attachment (as listed by `attachments`)
+    name: String,
+
+    /// File to write the attachment to, `-` for stdout (default: name of the attachment)
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+impl ExtractArgs {
+    pub fn run(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let mut manager =
+            PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        let Some(entry) = select_entry(&manager, &self.service)? else {
+            colour::e_red_ln!("there is nothing to do");
+            return Ok(());
+        };
+        let entry = manager.unseal(entry.id)?;
+
+        let Some(attachment) = entry.attachment(&self.name) else {
+            colour::e_red_ln!(
+                "No attachment '{}' in entry of {}",
+                self.name,
+                entry.service
+            );
+            return Ok(());
+        };
+        reprompt_master_password(&entry)?;
+
+        let content = attachment
+            .content(attachments_dir(PASS_ENTRY_STORE.as_path()))
+            .map_err(PasswordStoreError::Attachment)?;
+
+        let output = self
+            .output
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(&attachment.name));
+        if output.as_os_str() == "-" {
+            std::io::stdout()
+                .write_all(&content)
+                .map_err(CliError::UnableToWriteFile)?;
+        } else {
+            if output.exists() && !ask_for_confirm(format!("Overwrite {}?", output.display()))? {
+                colour::e_red_ln!("Aborted!!");
+                return Ok(());
+            }
+
+            write_private_file(&output, &content).map_err(CliError::UnableToWriteFile)?;
+            colour::green_ln!("Extracted '{}' to {}", attachment.name, output.display());
+        }
+
+        manager.entry_mut(entry.id)?.mark_accessed();
+        manager.dump(PASS_ENTRY_STORE.as_path())?;
+
+        Ok(())
+    }
+}
+
+/// Write a file readable only by the user, as attachments are secrets
+fn write_private_file(path: impl AsRef<Path>, content: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    options.open(path)?.write_all(content.as_ref())
+}
+
+#[derive(Args, Debug)]
+pub struct SearchArgs {
+    service: String,
+}
+
+impl SearchArgs {
+    pub fn fuzzy_search(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let manager =
+            PasswordStore::load_metadata(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        let result = manager.fuzzy_find(self.service.clone());
+        match result.is_empty() {
+            true => {
+                colour::e_red_ln!("No entry exist related to '{}'", self.service);
+            }
+            false => {
+                // TODO: Make methods like fuzzy_find_by_username & fuzzy_find_by_service
+                colour::green_ln!("Your search results: ");
+
+                print_table(result, &[]);
+            }
+        };
+
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct GenArgs {
+    /// Length of generated password
+    #[clap(default_value_t = 12)]
+    length: usize,
+
+    /// Number of password to be generated
     #[arg(short = 'n', default_value_t = 1)]
     count: usize,
 
@@ -497,6 +1848,19 @@ pub struct GenArgs {
     symbols: bool,
 }
 
+impl Default for GenArgs {
+    fn default() -> Self {
+        GenArgs {
+            length: 12,
+            count: 1,
+            uppercase: false,
+            lowercase: false,
+            digits: false,
+            symbols: false,
+        }
+    }
+}
+
 impl GenArgs {
     /// Generate random password based on flags
     pub fn generate_password(self) {
@@ -567,7 +1931,7 @@ impl GenArgs {
 
 #[derive(Args, Debug)]
 pub struct ResetArgs {
-    /// Flag to remove whole "pass" directory from db
+    /// Flag to remove the whole vault, named vaults are kept when resetting the default one
     #[arg(long, default_value_t = false)]
     hard: bool,
 
@@ -577,26 +1941,44 @@ pub struct ResetArgs {
 }
 
 impl ResetArgs {
-    pub fn reset(&self) -> Result<(), CliError> {
+    pub fn reset(&self, master_password: MasterPassword<Verified>) -> Result<(), CliError> {
         if self.hard {
             Self::reset_hard()?;
         } else {
-            Self::reset_passwords()?;
+            Self::reset_passwords(master_password)?;
         }
 
         Ok(())
     }
 
     fn reset_hard() -> Result<(), CliError> {
+        let vault = Vault::selected();
+        if !vault.is_named() {
+            return Err(CliError::UnableToResetVault(VaultError::NotOwned(
+                vault.name(),
+            )));
+        }
+
+        let message = match vault.is_default() {
+            true => {
+                "Do you really want to remove the default vault? Named vaults are kept".to_owned()
+            }
+            false => format!(
+                "Do you really want to remove whole '{}' vault?",
+                vault.name()
+            ),
+        };
         let confirm_for_removal =
-            ask_for_confirm("Do you really want to remove whole 'pass' directory?")
-                .map_err(|_| CliError::UnableToReadFromConsole)?;
+            ask_for_confirm(message).map_err(|_| CliError::UnableToReadFromConsole)?;
 
         match confirm_for_removal {
+            true if vault.is_default() => {
+                vault.reset().map_err(CliError::UnableToResetVault)?;
+                colour::green_ln!("Default vault has been removed");
+            }
             true => {
-                std::fs::remove_dir_all(PASS_DIR_PATH.as_path())
-                    .map_err(CliError::UnableToResetPassDir)?;
-                colour::green_ln!("`pass` directory has been removed");
+                vault.delete().map_err(CliError::UnableToResetVault)?;
+                colour::green_ln!("Vault '{}' has been removed", vault.name());
             }
             false => {
                 colour::e_red_ln!("Reset command has been aborted");
@@ -606,15 +1988,21 @@ impl ResetArgs {
         Ok(())
     }
 
-    fn reset_passwords() -> Result<(), CliError> {
+    fn reset_passwords(master_password: MasterPassword<Verified>) -> Result<(), CliError> {
         let confirm_for_removal =
             ask_for_confirm("Do you really want to reset all password entry?")
                 .map_err(|_| CliError::UnableToReadFromConsole)?;
 
         match confirm_for_removal {
             true => {
-                std::fs::remove_file(PASS_ENTRY_STORE.as_path())
-                    .map_err(CliError::UnableToResetPassDir)?;
+                // Keep the vault (& key check of master password) but without any entry
+                let mut manager =
+                    PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), &master_password)
+                        .map_err(CliError::UnableToResetPassEntry)?;
+                manager.payload = VaultPayload::default();
+                manager
+                    .dump(PASS_ENTRY_STORE.to_path_buf())
+                    .map_err(CliError::UnableToResetPassEntry)?;
                 colour::green_ln!("All password entry has been reset");
             }
             false => {
@@ -625,3 +2013,357 @@ impl ResetArgs {
         Ok(())
     }
 }
+
+#[derive(Args)]
+pub struct KdfArgs {
+    /// Operation on the key derivation function
+    #[command(subcommand)]
+    command: KdfCommand,
+}
+
+#[derive(Subcommand)]
+pub enum KdfCommand {
+    /// Show the key derivation function used by vault
+    Show,
+
+    /// Re-derive the vault key with new parameters & re-encrypt the vault
+    Upgrade(KdfUpgradeArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct KdfUpgradeArgs {
+    /// Key derivation function to use
+    #[arg(long, value_enum, default_value_t = KdfAlgorithm::Argon2id)]
+    algorithm: KdfAlgorithm,
+
+    /// Memory cost in KiB (Argon2id only), at most 4 GiB
+    #[arg(long, default_value_t = ARGON2_MEMORY_KIB)]
+    memory: u32,
+
+    /// Number of iterations (default: 3 for Argon2id, 600000 for PBKDF2)
+    #[arg(long)]
+    iterations: Option<u32>,
+
+    /// Degree of parallelism (Argon2id only)
+    #[arg(long, default_value_t = ARGON2_PARALLELISM)]
+    parallelism: u32,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum KdfAlgorithm {
+    Argon2id,
+    Pbkdf2,
+}
+
+impl From<&KdfUpgradeArgs> for Kdf {
+    fn from(value: &KdfUpgradeArgs) -> Self {
+        match value.algorithm {
+            KdfAlgorithm::Argon2id => Kdf::Argon2id {
+                memory_kib: value.memory,
+                iterations: value.iterations.unwrap_or(ARGON2_ITERATIONS),
+                parallelism: value.parallelism,
+            },
+            KdfAlgorithm::Pbkdf2 => Kdf::Pbkdf2HmacSha256 {
+                iterations: value.iterations.unwrap_or(PBKDF2_ITERATIONS),
+            },
+        }
+    }
+}
+
+impl KdfArgs {
+    pub fn run(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let mut manager = PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+
+        match &self.command {
+            KdfCommand::Show => {
+                colour::green_ln!("{}", manager.header.kdf);
+            }
+            KdfCommand::Upgrade(args) => {
+                let kdf = Kdf::from(args);
+                colour::blue_ln!("Current: {}", manager.header.kdf);
+                colour::blue_ln!("New: {}", kdf);
+
+                // Parameters are checked before deriving anything by them, e.g. a typo in memory
+                kdf.validate()
+                    .map_err(PasswordStoreError::UnableToDeriveKey)?;
+                let is_confirmed = || {
+                    inquire::Confirm::new("New KDF is weaker than the current one, continue?")
+                        .with_default(false)
+                        .prompt()
+                        .map_err(|_| CliError::UnableToReadFromConsole)
+                };
+                if kdf.is_weaker_than(&manager.header.kdf) && !is_confirmed()? {
+                    colour::e_red_ln!("Aborted!!");
+                    return Ok(());
+                }
+
+                // Re-encrypt all entries under the key derived by new KDF
+                manager.change_kdf(kdf, &master_password)?;
+                manager.dump(PASS_ENTRY_STORE.to_path_buf())?;
+
+                // Make sure the vault is still readable before reporting success
+                let reloaded =
+                    PasswordStore::load(PASS_ENTRY_STORE.to_path_buf(), &master_password)?;
+                if reloaded.payload != manager.payload {
+                    return Err(PasswordStoreError::UnableToDecryptError(
+                        "Vault content changed after re-encryption".to_owned(),
+                    )
+                    .into());
+                }
+
+                colour::green_ln!("Vault re-encrypted using {}", kdf.name());
+            }
+        };
+
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct MigrateArgs {
+    /// Storage format to convert the vault into
+    #[arg(long, value_enum)]
+    to: StorageFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+pub enum StorageFormat {
+    /// All entries encrypted at once in a single file
+    File,
+
+    /// SQLite database having each entry encrypted in its own row
+    Sqlite,
+
+    /// Append-only log of encrypted entries, suited to large vaults
+    Log,
+}
+
+impl From<StorageFormat> for BackendKind {
+    fn from(value: StorageFormat) -> Self {
+        match value {
+            StorageFormat::File => BackendKind::File,
+            StorageFormat::Sqlite => BackendKind::Sqlite,
+            StorageFormat::Log => BackendKind::Log,
+        }
+    }
+}
+
+impl MigrateArgs {
+    pub fn run(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let kind = BackendKind::from(self.to);
+
+        let current = BackendKind::detect(PASS_ENTRY_STORE.as_path())?;
+        if current == kind {
+            colour::green_ln!("Vault is already stored as {}", kind.name());
+            return Ok(());
+        }
+
+        let store = PasswordStore::migrate(PASS_ENTRY_STORE.as_path(), &master_password, kind)?;
+
+        colour::green_ln!(
+            "Migrated {} entries from {} to {}",
+            store.payload.passwords.len(),
+            current.name(),
+            kind.name()
+        );
+        println!(
+            "Previous vault is kept at {}",
+            backup_path(PASS_ENTRY_STORE.as_path()).display()
+        );
+
+        Ok(())
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct VaultArgs {
+    #[command(subcommand)]
+    pub command: VaultCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum VaultCommand {
+    /// Create & initialise a new named vault
+    Create {
+        /// Name of the vault
+        name: String,
+    },
+
+    /// List all named vaults
+    List,
+
+    /// Use a vault whenever `--vault` is not given
+    Use {
+        /// Name or path of the vault
+        vault: String,
+    },
+
+    /// Delete a named vault along with all of its entries
+    Delete {
+        /// Name of the vault
+        name: String,
+    },
+}
+
+impl VaultArgs {
+    /// Vault which the subcommand operates on, instead of the vault in use
+    pub fn target(&self) -> Option<Result<Vault, VaultError>> {
+        match &self.command {
+            VaultCommand::Create { name } | VaultCommand::Delete { name } => {
+                Some(Vault::named(name))
+            }
+            VaultCommand::List | VaultCommand::Use { .. } => None,
+        }
+    }
+
+    pub fn list() -> anyhow::Result<()> {
+        let current = Vault::selected();
+        let mut vaults = Vault::list()?;
+        if !vaults.contains(current) {
+            vaults.push(current.clone());
+        }
+
+        for vault in vaults {
+            let marker = match vault == *current {
+                true => "*",
+                false => " ",
+            };
+            let status = match vault.exists() {
+                true => "",
+                false => " (not initialised)",
+            };
+
+            colour::green!("{marker} {}", vault.name());
+            colour::dark_grey_ln!("  {}{status}", vault.dir().display());
+        }
+
+        Ok(())
+    }
+
+    pub fn use_vault(vault: impl AsRef<str>) -> anyhow::Result<()> {
+        let vault = Vault::parse(vault)?;
+        vault.set_current()?;
+
+        colour::green_ln!("Using vault '{}'", vault.name());
+        Ok(())
+    }
+
+    pub fn delete(&self, _master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let vault = Vault::selected();
+
+        let confirm_for_removal = ask_for_confirm(format!(
+            "Do you really want to delete vault '{}' along with all of its entries?",
+            vault.name()
+        ))
+        .map_err(|_| CliError::UnableToReadFromConsole)?;
+
+        match confirm_for_removal {
+            true => {
+                vault.delete()?;
+                colour::green_ln!("Vault '{}' has been deleted", vault.name());
+            }
+            false => {
+                colour::e_red_ln!("Aborted!!");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+#[derive(Args, Debug)]
+pub struct AgentArgs {
+    /// Seconds of inactivity after which the agent forgets all keys & exits
+    #[arg(long, default_value_t = AGENT_TIMEOUT_SECS)]
+    timeout: u64,
+
+    /// Run the agent in foreground, used by the spawned agent process
+    #[arg(long, hide = true)]
+    pub serve: bool,
+}
+
+#[cfg(unix)]
+impl AgentArgs {
+    pub fn serve(&self) -> anyhow::Result<()> {
+        Agent::default().serve(Duration::from_secs(self.timeout))?;
+        Ok(())
+    }
+
+    /// Hand over the key of vault to agent, starting the agent if it is not running
+    pub fn start(&self, master_password: MasterPassword<Verified>) -> anyhow::Result<()> {
+        let header = PasswordStore::read_header(PASS_ENTRY_STORE.as_path())?
+            .ok_or_else(|| anyhow::anyhow!("Vault is not initialised"))?;
+        let key = master_password.derive_encryption_key(&header.kdf, &header.salt)?;
+
+        let agent = Agent::default();
+        if !agent.is_running() {
+            agent.spawn(Duration::from_secs(self.timeout))?;
+        }
+        agent.add_key(PASS_ENTRY_STORE.as_path(), key.expose())?;
+
+        colour::green_ln!(
+            "Vault '{}' unlocked until `pass_rs lock` or inactivity",
+            Vault::selected().name()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pass::header::VaultHeader;
+
+    fn examine(store: &Path, password: &str) -> Doctor {
+        let master = MasterPassword::from_pass(password).unwrap();
+        let mut doctor = Doctor::new(store, store.with_file_name("master.dat"));
+        doctor.check_files();
+        doctor.check_vault(&master);
+        doctor.check_backup(&master);
+        doctor
+    }
+
+    #[test]
+    fn test_doctor_exit_code() -> Result<(), PasswordStoreError> {
+        let dir = std::env::temp_dir().join(format!("pass_rs_exit_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = dir.join("passwords.db");
+
+        let master = MasterPassword::from_pass("Test123@").unwrap();
+        let header = VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1 });
+        let mut manager = PasswordStore::with_payload(VaultPayload::default(), &master, header)?;
+        manager.insert_entry(PasswordEntry::new(
+            "github".to_owned(),
+            None,
+            Some("pass"),
+            None,
+        ));
+        manager.dump(&store)?;
+        manager.dump(&store)?;
+        assert_eq!(exit_code(&examine(&store, "Test123@")), None);
+        assert_eq!(
+            exit_code(&examine(&store, "Wrong123@")),
+            Some(WRONG_MASTER_PASSWORD_EXIT_CODE)
+        );
+
+        manager.insert_entry(PasswordEntry::new("mail".to_owned(), None, Some(""), None));
+        manager.dump(&store)?;
+        assert_eq!(
+            exit_code(&examine(&store, "Test123@")),
+            Some(VAULT_ISSUES_EXIT_CODE)
+        );
+
+        let mut content = std::fs::read(&store).unwrap();
+        let last = content.len() - 1;
+        content[last] ^= 1;
+        std::fs::write(&store, content).unwrap();
+        assert_eq!(
+            exit_code(&examine(&store, "Test123@")),
+            Some(VAULT_DAMAGED_EXIT_CODE)
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+}
@@ -2,17 +2,37 @@
 pub mod args;
 
 use std::borrow::BorrowMut;
+use std::time::Duration;
 
 use clap::Parser;
 use colour::e_red_ln;
 
-use crate::pass::master::{Init, UnVerified, Verified};
+#[cfg(unix)]
+use crate::pass::agent::Agent;
+use crate::pass::entry::PasswordEntry;
+use crate::pass::kind::EntryKindError;
+use crate::pass::lock::{self, LockError, LockMode, VaultLock};
+use crate::pass::master::{Init, PasswordSource, UnVerified, Verified};
+use crate::pass::store::PasswordStoreError;
+use crate::pass::vault::{Vault, VaultError};
 use crate::{
     cli::args::{Cli, Command},
     pass::master::MasterPassword,
 };
 
-use self::args::list_entries;
+use self::args::{doctor, list_tags, VaultArgs, VaultCommand};
+
+/// Exit code when the master password given by a non-interactive source is wrong
+pub const WRONG_MASTER_PASSWORD_EXIT_CODE: i32 = 3;
+
+/// Exit code when the vault stays in use by another process till the lock timeout
+pub const VAULT_LOCKED_EXIT_CODE: i32 = 4;
+
+/// Exit code of `doctor` when the vault is readable, but has issues like duplicate entries
+pub const VAULT_ISSUES_EXIT_CODE: i32 = 5;
+
+/// Exit code of `doctor` when the vault (or a part of it) is unreadable
+pub const VAULT_DAMAGED_EXIT_CODE: i32 = 6;
 
 #[derive(Debug, thiserror::Error)]
 pub enum CliError {
@@ -28,16 +48,50 @@ pub enum CliError {
     #[error("Unable to read from console")]
     UnableToReadFromConsole,
 
-    #[error("Unable to reset the pass dir")]
-    UnableToResetPassDir(#[source] std::io::Error),
-
     #[error("Unable to reset the pass entry file")]
-    UnableToResetPassEntry(#[source] std::io::Error),
+    UnableToResetPassEntry(#[source] PasswordStoreError),
+
+    #[error("Unable to reset the vault")]
+    UnableToResetVault(#[source] VaultError),
+
+    #[error("Password can only be given to login entries")]
+    PasswordOfTypedEntry,
+
+    #[error("Invalid details of entry")]
+    InvalidEntry(#[source] EntryKindError),
+
+    #[error("Name of attachment is required for this file")]
+    NoAttachmentName,
+
+    #[error("Unable to read file: {0}")]
+    UnableToReadFile(#[source] std::io::Error),
+
+    #[error("Unable to write file: {0}")]
+    UnableToWriteFile(#[source] std::io::Error),
 }
 
 // Run the CLI
 pub fn run_cli(master_password: MasterPassword<Init>) -> anyhow::Result<()> {
     let args = Cli::parse();
+    let source = PasswordSource::new(args.password_stdin, args.password_command.clone());
+
+    // Every store path is resolved from the selected vault
+    let vault = match &args.command {
+        Some(Command::Vault(arg)) => arg.target().transpose()?,
+        _ => None,
+    };
+    match vault {
+        Some(vault) => vault.select()?,
+        None => Vault::resolve(args.vault.as_deref())?.select()?,
+    };
+
+    // Held till the command is done, so that no other process changes the vault meanwhile
+    let _lock = match args.command.as_ref().and_then(Command::lock_mode) {
+        Some(mode) if Vault::selected().dir().exists() => {
+            Some(lock_vault(mode, args.lock_timeout)?)
+        }
+        _ => None,
+    };
 
     match args.command {
         Some(Command::Init) => {
@@ -52,119 +106,159 @@ pub fn run_cli(master_password: MasterPassword<Init>) -> anyhow::Result<()> {
         }
 
         Some(Command::ChangeMaster) => {
-            let mut master = master_password.load()?;
-
-            // password_verification(master, |verified| verified.change())?;
-
-            for attempt in 0..3 {
-                master.prompt()?;
-
-                match master.verify() {
-                    Ok(Some(mut verified)) => {
-                        // Change the master-pass and store it in db
-                        verified.change()?;
-                        break;
-                    }
-                    Ok(None) => {
-                        if attempt < 2 {
-                            colour::e_red_ln!(
-                                "Incorrect master password, retry ({}):",
-                                2 - attempt
-                            );
-                        } else {
-                            colour::e_red_ln!("Wrong master password");
-                        }
-                    }
-                    Err(e) => {
-                        e_red_ln!("Unable to add password due to: {}", e.to_string());
-                        break;
-                    }
-                };
-            }
+            let master = master_password.load()?;
+
+            password_verification_and_do_sensitive_operation(master, &source, |mut verified| {
+                // Change the master-pass and store it in db
+                Ok(verified.change()?)
+            })?;
         }
 
         Some(Command::Add(mut arg)) => {
-            let mut master = master_password.load()?;
-
-            // password_verification(master, |verified| arg.add_entries(verified))?;
-
-            for attempt in 0..3 {
-                master.prompt()?;
-
-                match master.verify() {
-                    Ok(Some(verified)) => {
-                        arg.borrow_mut().add_entries(&verified)?;
-                        break;
-                    }
-                    Ok(None) => {
-                        if attempt < 2 {
-                            colour::e_red_ln!(
-                                "Incorrect master password, retry ({}):",
-                                2 - attempt
-                            );
-                        } else {
-                            colour::e_red_ln!("Wrong master password");
-                        }
-                    }
-                    Err(e) => {
-                        e_red_ln!("Unable to add password due to: {}", e.to_string());
-                        break;
-                    }
-                };
-            }
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| {
+                arg.add_entries(&verified)
+            })?;
         }
 
         Some(Command::Remove(mut arg)) => {
-            let mut master = master_password.load()?;
-
-            // password_verification(master, |verified| arg.remove_entries(verified))?;
-
-            for attempt in 0..3 {
-                master.borrow_mut().prompt()?;
-
-                match master.verify() {
-                    Ok(Some(verified)) => {
-                        arg.remove_entries(verified)?;
-                        break;
-                    }
-                    Ok(None) => {
-                        if attempt < 2 {
-                            colour::e_red_ln!(
-                                "Incorrect master password, retry ({}):",
-                                2 - attempt
-                            );
-                        } else {
-                            colour::e_red_ln!("Wrong master password");
-                        }
-                    }
-                    Err(e) => {
-                        e_red_ln!("Unable to add password due to: {}", e.to_string());
-                        break;
-                    }
-                };
-            }
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| {
+                arg.remove_entries(verified)
+            })?;
+        }
+
+        Some(Command::Update(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| {
+                arg.update_entries(verified)
+            })?;
         }
 
-        Some(Command::Update(_args)) => {
-            unimplemented!();
+        Some(Command::List(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| {
+                arg.list_entries(verified)
+            })?;
         }
 
-        Some(Command::List) => {
+        Some(Command::Tags) => {
             let master = master_password.load()?;
 
-            password_verification_and_do_operation(master, list_entries)?;
+            password_verification_and_do_operation(master, &source, list_tags)?;
         }
 
         Some(Command::Get(arg)) => {
             let master = master_password.load()?;
 
-            password_verification_and_do_operation(master, |verified| arg.get_entries(verified))?;
+            password_verification_and_do_operation(master, &source, |verified| {
+                arg.get_entries(verified)
+            })?;
         }
 
         Some(Command::Search(arg)) => {
             let master = master_password.load()?;
 
-            password_verification_and_do_operation(master, |verified| arg.fuzzy_search(verified))?;
+            password_verification_and_do_operation(master, &source, |verified| {
+                arg.fuzzy_search(verified)
+            })?;
+        }
+
+        Some(Command::Otp(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| {
+                arg.show_code(verified)
+            })?;
+        }
+
+        Some(Command::History(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| arg.run(verified))?;
+        }
+
+        Some(Command::Config(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| arg.run(verified))?;
+        }
+
+        Some(Command::Attach(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| arg.run(verified))?;
+        }
+
+        Some(Command::Attachments(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| arg.run(verified))?;
+        }
+
+        Some(Command::Extract(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_operation(master, &source, |verified| arg.run(verified))?;
+        }
+
+        Some(Command::Kdf(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_sensitive_operation(master, &source, |verified| {
+                arg.run(verified)
+            })?;
+        }
+
+        Some(Command::Migrate(arg)) => {
+            let master = master_password.load()?;
+
+            password_verification_and_do_sensitive_operation(master, &source, |verified| {
+                arg.run(verified)
+            })?;
+        }
+
+        Some(Command::Doctor) => doctor(&source)?,
+
+        Some(Command::Vault(arg)) => match &arg.command {
+            VaultCommand::Create { name } => {
+                Vault::selected().create()?;
+                master_password.initialise()?;
+
+                colour::green_ln!("Vault '{name}' created");
+                println!("Usage: pass_rs vault use {name}");
+            }
+            VaultCommand::List => VaultArgs::list()?,
+            VaultCommand::Use { vault } => VaultArgs::use_vault(vault)?,
+            VaultCommand::Delete { .. } => {
+                let master = master_password.load()?;
+
+                password_verification_and_do_sensitive_operation(master, &source, |verified| {
+                    arg.delete(verified)
+                })?;
+            }
+        },
+
+        #[cfg(unix)]
+        Some(Command::Agent(arg)) => match arg.serve {
+            true => arg.serve()?,
+            false => {
+                let master = master_password.load()?;
+
+                password_verification_and_do_sensitive_operation(master, &source, |verified| {
+                    arg.start(verified)
+                })?;
+            }
+        },
+
+        #[cfg(unix)]
+        Some(Command::Lock) => {
+            Agent::default().lock()?;
+            colour::green_ln!("Agent locked");
         }
 
         Some(Command::Gen(args)) => {
@@ -172,34 +266,11 @@ pub fn run_cli(master_password: MasterPassword<Init>) -> anyhow::Result<()> {
         }
 
         Some(Command::Reset(arg)) => {
-            let mut master = master_password.load()?;
-
-            // password_verification(master, |verified| arg.reset())?;
-
-            for attempt in 0..3 {
-                master.borrow_mut().prompt()?;
-
-                match master.verify() {
-                    Ok(Some(_)) => {
-                        arg.reset()?;
-                        break;
-                    }
-                    Ok(None) => {
-                        if attempt < 2 {
-                            colour::e_red_ln!(
-                                "Incorrect master password, retry ({}):",
-                                2 - attempt
-                            );
-                        } else {
-                            colour::e_red_ln!("Wrong master password");
-                        }
-                    }
-                    Err(e) => {
-                        e_red_ln!("Unable to add password due to: {}", e.to_string());
-                        break;
-                    }
-                };
-            }
+            let master = master_password.load()?;
+
+            password_verification_and_do_sensitive_operation(master, &source, |verified| {
+                Ok(arg.reset(verified)?)
+            })?;
         }
 
         None => {
@@ -226,21 +297,105 @@ Type $ pass_rs init for setting up your master password.";
     Ok(())
 }
 
+/// Lock the selected vault, exiting with a distinct code if it stays in use by another process
+fn lock_vault(mode: LockMode, timeout_secs: u64) -> anyhow::Result<VaultLock> {
+    let timeout = Duration::from_secs(timeout_secs);
+
+    match VaultLock::acquire(Vault::selected().lock_path(), mode, timeout) {
+        Ok(lock) => {
+            if let Some(holder) = lock.stale() {
+                colour::e_yellow_ln!("Vault was left locked by {holder}, which exited early");
+            }
+            Ok(lock.hold())
+        }
+        Err(err @ LockError::Locked(_)) => {
+            e_red_ln!("{err}");
+            std::process::exit(VAULT_LOCKED_EXIT_CODE);
+        }
+        Err(err) => Err(err)?,
+    }
+}
+
+/// Verify master password (or unlock by agent) & do the operation
 fn password_verification_and_do_operation<F>(
-    mut master: MasterPassword<UnVerified>,
+    master: MasterPassword<UnVerified>,
+    source: &PasswordSource,
+    operation: F,
+) -> anyhow::Result<()>
+where
+    F: FnOnce(MasterPassword<Verified>) -> anyhow::Result<()>,
+{
+    match verify_master_password(master, source, true)? {
+        Some(verified) => operation(verified),
+        None => Ok(()),
+    }
+}
+
+/// Verify master password & do the operation, which always requires the master password
+fn password_verification_and_do_sensitive_operation<F>(
+    master: MasterPassword<UnVerified>,
+    source: &PasswordSource,
     operation: F,
 ) -> anyhow::Result<()>
 where
-    F: Fn(MasterPassword<Verified>) -> anyhow::Result<()>,
+    F: FnOnce(MasterPassword<Verified>) -> anyhow::Result<()>,
 {
+    match verify_master_password(master, source, false)? {
+        Some(verified) => operation(verified),
+        None => Ok(()),
+    }
+}
+
+/// Prompt for master password again before revealing or changing a protected entry,
+/// even if the vault is unlocked by agent or a non-interactive source
+fn reprompt_master_password(entry: &PasswordEntry) -> anyhow::Result<()> {
+    if !entry.reprompt {
+        return Ok(());
+    }
+
+    colour::yellow_ln!("Entry of {} is protected by master password", entry.service);
+
+    let master = MasterPassword::new().load()?;
+    match verify_master_password(master, &PasswordSource::Prompt, false)? {
+        Some(_) => Ok(()),
+        None => Err(CliError::UnableToUnlockMaster)?,
+    }
+}
+
+/// Take master password from the source & verify it, retrying only if it is prompted
+fn verify_master_password(
+    mut master: MasterPassword<UnVerified>,
+    source: &PasswordSource,
+    use_agent: bool,
+) -> anyhow::Result<Option<MasterPassword<Verified>>> {
+    // Prompting is not needed if agent holds the key of vault
+    #[cfg(unix)]
+    if use_agent && source.is_interactive() {
+        if let Some(verified) = master.unlock_by_agent() {
+            return Ok(Some(verified));
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = use_agent;
+
+    // Scripts get a distinct exit code instead of retrying
+    if !source.is_interactive() {
+        master.read_from(source)?;
+
+        return match master.verify()? {
+            Some(verified) => Ok(Some(verified)),
+            None => {
+                colour::e_red_ln!("Wrong master password");
+                lock::exit(WRONG_MASTER_PASSWORD_EXIT_CODE);
+            }
+        };
+    }
+
     for attempt in 0..3 {
         master.borrow_mut().prompt()?;
 
         match master.verify() {
-            Ok(Some(verified)) => {
-                operation(verified)?;
-                break;
-            }
+            Ok(Some(verified)) => return Ok(Some(verified)),
             Ok(None) => {
                 if attempt < 2 {
                     colour::e_red_ln!("Incorrect master password, retry ({}):", 2 - attempt);
@@ -249,11 +404,11 @@ where
                 }
             }
             Err(e) => {
-                e_red_ln!("Unable to add password due to: {}", e.to_string());
+                e_red_ln!("Unable to verify master password due to: {}", e.to_string());
                 break;
             }
         };
     }
 
-    Ok(())
+    Ok(None)
 }
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use once_cell::sync::Lazy;
+use zeroize::Zeroize;
+
+use super::secret::SecretKey;
+use super::util::XDG_BASE;
+
+/// Idle time after which the agent forgets all keys & exits
+pub const AGENT_TIMEOUT_SECS: u64 = 15 * 60;
+
+/// Env variable to override the socket path of agent
+pub const AGENT_SOCKET_ENV: &str = "PASS_RS_AGENT_SOCKET";
+
+// $XDG_RUNTIME_DIR/pass/agent.sock, or $HOME/.local/state/pass/agent/agent.sock without runtime dir
+pub static AGENT_SOCKET: Lazy<PathBuf> = Lazy::new(|| {
+    if let Some(socket) = std::env::var_os(AGENT_SOCKET_ENV) {
+        return PathBuf::from(socket);
+    }
+
+    let dir = match XDG_BASE.get_runtime_directory() {
+        Ok(runtime) => runtime.join(super::util::APP_NAME),
+        Err(_) => XDG_BASE.get_state_home().join("agent"),
+    };
+    dir.join("agent.sock")
+});
+
+#[derive(Debug, thiserror::Error)]
+pub enum AgentError {
+    #[error("Agent is not running")]
+    NotRunning,
+
+    #[error("Agent is already running")]
+    AlreadyRunning,
+
+    #[error("Unable to start agent: {0}")]
+    UnableToStart(#[source] std::io::Error),
+
+    #[error("Unable to communicate with agent: {0}")]
+    UnableToCommunicate(#[source] std::io::Error),
+
+    #[error("Agent refused the request: {0}")]
+    Refused(String),
+
+    #[error("Directory '{}' of agent socket must be owned by you & accessible only by you", .0.display())]
+    InsecureSocketDir(PathBuf),
+}
+
+/// Background process holding derived vault keys behind a Unix socket, so that the
+/// master password is not prompted (and the key not re-derived) by every command
+pub struct Agent {
+    socket: PathBuf,
+}
+
+impl Default for Agent {
+    fn default() -> Self {
+        Agent::at(AGENT_SOCKET.as_path())
+    }
+}
+
+impl Agent {
+    pub fn at(socket: impl AsRef<Path>) -> Self {
+        Agent {
+            socket: socket.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        UnixStream::connect(&self.socket).is_ok()
+    }
+
+    /// Start the agent as a detached process of current executable
+    pub fn spawn(&self, timeout: Duration) -> Result<(), AgentError> {
+        if self.is_running() {
+            return Err(AgentError::AlreadyRunning);
+        }
+        self.prepare_dir()?;
+
+        let executable = std::env::current_exe().map_err(AgentError::UnableToStart)?;
+        std::process::Command::new(executable)
+            .args(["agent", "--serve", "--timeout"])
+            .arg(timeout.as_secs().to_string())
+            .env(AGENT_SOCKET_ENV, &self.socket)
+            .stdin(std::process::Stdio::null())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .process_group(0)
+            .spawn()
+            .map_err(AgentError::UnableToStart)?;
+
+        // Wait for agent to listen on socket
+        for _ in 0..50 {
+            if self.is_running() {
+                return Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+
+        Err(AgentError::NotRunning)
+    }
+
+    /// Key of vault held by agent, `None` if agent is not running or has no key of vault
+    pub fn get_key(&self, vault: impl AsRef<Path>) -> Result<Option<SecretKey>, AgentError> {
+        if !self.is_running() {
+            return Ok(None);
+        }
+
+        match self.request(format!("GET {}", vault.as_ref().display())) {
+            Ok(mut response) => {
+                let key = decode_key(&response);
+                response.zeroize();
+                Ok(key)
+            }
+            Err(AgentError::Refused(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Hand over the key of vault to agent
+    pub fn add_key(&self, vault: impl AsRef<Path>, key: &[u8; 32]) -> Result<(), AgentError> {
+        let mut request = format!("ADD {} {}", encode_key(key), vault.as_ref().display());
+        let result = self.request(&request).map(|_| ());
+        request.zeroize();
+
+        result
+    }
+
+    /// Make agent forget all keys & exit
+    pub fn lock(&self) -> Result<(), AgentError> {
+        if !self.is_running() {
+            return Err(AgentError::NotRunning);
+        }
+
+        self.request("LOCK").map(|_| ())
+    }
+
+    fn request(&self, request: impl AsRef<str>) -> Result<String, AgentError> {
+        let mut stream =
+            UnixStream::connect(&self.socket).map_err(AgentError::UnableToCommunicate)?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .map_err(AgentError::UnableToCommunicate)?;
+
+        writeln!(stream, "{}", request.as_ref()).map_err(AgentError::UnableToCommunicate)?;
+
+        let mut response = String::new();
+        BufReader::new(stream)
+            .read_line(&mut response)
+            .map_err(AgentError::UnableToCommunicate)?;
+
+        let response = response.trim_end();
+        match response.split_once(' ').unwrap_or((response, "")) {
+            ("OK", rest) => Ok(rest.to_owned()),
+            (_, message) => Err(AgentError::Refused(message.to_owned())),
+        }
+    }
+
+    /// Serve requests until locked or idle for `timeout`
+    pub fn serve(&self, timeout: Duration) -> Result<(), AgentError> {
+        if self.is_running() {
+            return Err(AgentError::AlreadyRunning);
+        }
+
+        self.prepare_dir()?;
+        if self.socket.exists() {
+            // Stale socket of an agent which did not exit cleanly
+            std::fs::remove_file(&self.socket).map_err(AgentError::UnableToStart)?;
+        }
+
+        // Socket is never reachable by others, not even between bind & chmod
+        // SAFETY: umask only changes the file mode creation mask of this process
+        let umask = unsafe { libc::umask(0o077) };
+        let listener = UnixListener::bind(&self.socket);
+        unsafe { libc::umask(umask) };
+
+        let listener = listener.map_err(AgentError::UnableToStart)?;
+        std::fs::set_permissions(&self.socket, std::fs::Permissions::from_mode(0o600))
+            .map_err(AgentError::UnableToStart)?;
+        listener
+            .set_nonblocking(true)
+            .map_err(AgentError::UnableToStart)?;
+
+        let mut keys: HashMap<PathBuf, SecretKey> = HashMap::new();
+        let mut last_used = Instant::now();
+
+        loop {
+            // Errors other than no pending connection (e.g. out of file descriptors) are
+            // retried after a pause too, the agent still exits once idle for `timeout`
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(_) => {
+                    if last_used.elapsed() >= timeout {
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(100));
+                    continue;
+                }
+            };
+
+            // Only processes of the same user may talk to the agent
+            if peer_uid(&stream).ok() != Some(current_uid()) {
+                continue;
+            }
+            last_used = Instant::now();
+
+            if let Ok(true) = Agent::handle(stream, &mut keys) {
+                break;
+            }
+        }
+
+        // Keys are zeroed on drop
+        drop(keys);
+        let _ = std::fs::remove_file(&self.socket);
+
+        Ok(())
+    }
+
+    /// Create the socket directory accessible only by owner, refusing an existing one
+    /// which is owned by someone else or accessible by others
+    fn prepare_dir(&self) -> Result<(), AgentError> {
+        let Some(dir) = self.socket.parent() else {
+            return Ok(());
+        };
+
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)
+            .map_err(AgentError::UnableToStart)?;
+
+        let metadata = std::fs::symlink_metadata(dir).map_err(AgentError::UnableToStart)?;
+        if !metadata.is_dir() || metadata.uid() != current_uid() || metadata.mode() & 0o077 != 0 {
+            return Err(AgentError::InsecureSocketDir(dir.to_path_buf()));
+        }
+
+        Ok(())
+    }
+
+    /// Respond to a single request, returns whether the agent is locked
+    fn handle(stream: UnixStream, keys: &mut HashMap<PathBuf, SecretKey>) -> std::io::Result<bool> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+        let mut request = String::new();
+        let mut reader = BufReader::new(stream);
+        reader.read_line(&mut request)?;
+        let mut stream = reader.into_inner();
+
+        let request_line = request.trim_end();
+        let (command, argument) = request_line.split_once(' ').unwrap_or((request_line, ""));
+
+        let (mut response, is_locked) = match command {
+            "GET" => match keys.get(Path::new(argument)) {
+                Some(key) => (format!("OK {}", encode_key(key.expose())), false),
+                None => ("ERR No key of vault".to_owned(), false),
+            },
+            "ADD" => match argument.split_once(' ') {
+                Some((key, vault)) => match decode_key(key) {
+                    Some(key) => {
+                        keys.insert(PathBuf::from(vault), key);
+                        ("OK".to_owned(), false)
+                    }
+                    None => ("ERR Invalid key".to_owned(), false),
+                },
+                None => ("ERR Invalid request".to_owned(), false),
+            },
+            "LOCK" => ("OK".to_owned(), true),
+            _ => ("ERR Unknown request".to_owned(), false),
+        };
+        request.zeroize();
+
+        let result = writeln!(stream, "{response}");
+        response.zeroize();
+        result?;
+
+        Ok(is_locked)
+    }
+}
+
+fn encode_key(key: &[u8; 32]) -> String {
+    key.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn decode_key(hex: &str) -> Option<SecretKey> {
+    if hex.len() != 64 {
+        return None;
+    }
+
+    let mut key = SecretKey::zeroed();
+    for (byte, chunk) in key.expose_mut().iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(chunk).ok()?, 16).ok()?;
+    }
+
+    Some(key)
+}
+
+fn current_uid() -> u32 {
+    // SAFETY: getuid never fails
+    unsafe { libc::getuid() }
+}
+
+/// User id of process at the other end of the socket
+#[cfg(target_os = "linux")]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+
+    // SAFETY: `cred` & `len` are valid for writes of the size given by `len`
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+
+    match result {
+        0 => Ok(cred.uid),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// User id of process at the other end of the socket
+#[cfg(not(target_os = "linux"))]
+fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let (mut uid, mut gid) = (0, 0);
+
+    // SAFETY: `uid` & `gid` are valid for writes
+    match unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } {
+        0 => Ok(uid),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn agent_holds_keys() -> Result<(), AgentError> {
+        let socket = std::env::temp_dir()
+            .join(format!("pass_rs_agent_{}", std::process::id()))
+            .join("agent.sock");
+        let agent = Agent::at(&socket);
+        assert!(agent.get_key("vault")?.is_none());
+
+        let server = std::thread::spawn({
+            let socket = socket.clone();
+            move || Agent::at(socket).serve(Duration::from_secs(30))
+        });
+        while !agent.is_running() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+
+        let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let mode = std::fs::metadata(socket.parent().unwrap()).unwrap().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        agent.add_key("/vaults/work", &[7; 32])?;
+        assert_eq!(
+            agent.get_key("/vaults/work")?.map(|key| *key.expose()),
+            Some([7; 32])
+        );
+        assert!(agent.get_key("/vaults/personal")?.is_none());
+
+        agent.lock()?;
+        server.join().unwrap()?;
+        assert!(!agent.is_running());
+        assert!(!socket.exists());
+        std::fs::remove_dir(socket.parent().unwrap()).unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn agent_idle_timeout() -> Result<(), AgentError> {
+        let socket = std::env::temp_dir()
+            .join(format!("pass_rs_agent_idle_{}", std::process::id()))
+            .join("agent.sock");
+
+        let started = Instant::now();
+        Agent::at(&socket).serve(Duration::from_millis(200))?;
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(!socket.exists());
+        std::fs::remove_dir(socket.parent().unwrap()).unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn agent_refuses_shared_dir() {
+        let dir = std::env::temp_dir().join(format!("pass_rs_agent_shared_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let result = Agent::at(dir.join("agent.sock")).serve(Duration::from_millis(100));
+        assert!(matches!(result, Err(AgentError::InsecureSocketDir(_))));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
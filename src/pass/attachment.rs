@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use data_encoding::HEXLOWER;
+use ring::digest::{digest, SHA256};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use serde_encrypt::{
+    serialize::impls::BincodeSerializer, shared_key::SharedKey, traits::SerdeEncryptSharedKey,
+    EncryptedMessage,
+};
+use zeroize::Zeroize;
+
+use crate::pass::secret::{as_byte_string, Secret, SecretBytes};
+use crate::pass::util::{sync_parent_dir, write_staging_file};
+
+/// Largest attachment (in bytes) accepted unless configured
+pub const ATTACHMENT_LIMIT: u64 = 10 * 1024 * 1024;
+
+/// Attachments up to this size (in bytes) are kept inside the payload, larger ones in blobs
+pub const INLINE_ATTACHMENT_SIZE: u64 = 64 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum AttachmentError {
+    #[error("Attachment of {size} bytes is larger than the limit of {limit} bytes")]
+    TooLarge { size: u64, limit: u64 },
+
+    #[error("Unable to read attachment: {0}")]
+    UnableToRead(#[source] std::io::Error),
+
+    #[error("Unable to write attachment: {0}")]
+    UnableToWrite(#[source] std::io::Error),
+
+    #[error("Unable to encrypt attachment")]
+    UnableToEncrypt,
+
+    #[error("Unable to decrypt attachment")]
+    UnableToDecrypt,
+
+    #[error("Blob '{0}' of attachment is corrupted")]
+    Corrupted(String),
+}
+
+/// File kept along with an entry
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Attachment {
+    pub(crate) name: String,
+
+    /// Size of the (decrypted) content in bytes
+    pub(crate) size: u64,
+    pub(crate) added_at: DateTime<Utc>,
+    content: AttachmentContent,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum AttachmentContent {
+    /// Content inside the payload, encrypted along with the entries
+    Inline(#[serde(with = "as_byte_string")] SecretBytes),
+
+    /// Content in an encrypted blob named by the SHA-256 of it. Its random key is kept in the
+    /// payload, so blobs stay untouched when the vault is re-encrypted
+    Blob { hash: String, key: Secret<[u8; 32]> },
+}
+
+/// Content of a blob file before encryption
+#[derive(Serialize, Deserialize)]
+struct BlobContent {
+    #[serde(with = "serde_bytes")]
+    bytes: Vec<u8>,
+}
+
+impl SerdeEncryptSharedKey for BlobContent {
+    type S = BincodeSerializer<Self>;
+}
+
+/// Directory of the attachment blobs of a vault, next to its `passwords.db`
+pub fn attachments_dir(store_path: impl AsRef<Path>) -> PathBuf {
+    store_path.as_ref().with_file_name("attachments")
+}
+
+impl Attachment {
+    /// Keep small content inline, larger content is encrypted into a blob of `blob_dir`
+    pub fn new(
+        name: impl Into<String>,
+        content: Vec<u8>,
+        blob_dir: impl AsRef<Path>,
+    ) -> Result<Self, AttachmentError> {
+        let size = content.len() as u64;
+        let content = match size <= INLINE_ATTACHMENT_SIZE {
+            true => AttachmentContent::Inline(content.into()),
+            false => write_blob(blob_dir.as_ref(), content)?,
+        };
+
+        Ok(Attachment {
+            name: name.into(),
+            size,
+            added_at: Utc::now(),
+            content,
+        })
+    }
+
+    /// Decrypted content, checking that its blob (if any) is not tampered
+    pub fn content(&self, blob_dir: impl AsRef<Path>) -> Result<Vec<u8>, AttachmentError> {
+        let (hash, key) = match &self.content {
+            AttachmentContent::Inline(content) => return Ok(content.expose().to_vec()),
+            AttachmentContent::Blob { hash, key } => (hash, key),
+        };
+
+        let encrypted =
+            std::fs::read(blob_dir.as_ref().join(hash)).map_err(AttachmentError::UnableToRead)?;
+        if blob_hash(&encrypted) != *hash {
+            return Err(AttachmentError::Corrupted(hash.clone()));
+        }
+
+        let message = EncryptedMessage::deserialize(encrypted)
+            .map_err(|_| AttachmentError::UnableToDecrypt)?;
+        BlobContent::decrypt_owned(&message, &SharedKey::new(*key.expose()))
+            .map(|blob| blob.bytes)
+            .map_err(|_| AttachmentError::UnableToDecrypt)
+    }
+
+    /// Name of its blob file, `None` if the content is inline
+    pub fn blob(&self) -> Option<&str> {
+        match &self.content {
+            AttachmentContent::Inline(_) => None,
+            AttachmentContent::Blob { hash, .. } => Some(hash),
+        }
+    }
+}
+
+fn blob_hash(content: impl AsRef<[u8]>) -> String {
+    HEXLOWER.encode(digest(&SHA256, content.as_ref()).as_ref())
+}
+
+/// Encrypt content under a fresh key & atomically write it as a blob
+fn write_blob(blob_dir: &Path, content: Vec<u8>) -> Result<AttachmentContent, AttachmentError> {
+    let mut key = [0u8; 32];
+    SystemRandom::new()
+        .fill(&mut key)
+        .map_err(|_| AttachmentError::UnableToEncrypt)?;
+
+    let encrypted = BlobContent { bytes: content }
+        .encrypt(&SharedKey::new(key))
+        .map_err(|_| AttachmentError::UnableToEncrypt)?
+        .serialize();
+    let hash = blob_hash(&encrypted);
+
+    let path = blob_dir.join(&hash);
+    std::fs::create_dir_all(blob_dir)
+        .and_then(|_| write_staging_file(&path, encrypted))
+        .and_then(|staging| std::fs::rename(staging, &path))
+        .and_then(|_| sync_parent_dir(&path))
+        .map_err(AttachmentError::UnableToWrite)?;
+
+    // Key is copied into the secret, so the copy on stack is zeroed
+    let content = AttachmentContent::Blob {
+        hash,
+        key: key.into(),
+    };
+    key.zeroize();
+
+    Ok(content)
+}
+
+/// Delete the blobs of `blob_dir` not referenced by any of `attachments`, returns their count
+pub fn collect_garbage<'a>(
+    blob_dir: impl AsRef<Path>,
+    attachments: impl IntoIterator<Item = &'a Attachment>,
+) -> Result<usize, AttachmentError> {
+    let blob_dir = blob_dir.as_ref();
+    if !blob_dir.exists() {
+        return Ok(0);
+    }
+
+    let referenced = attachments
+        .into_iter()
+        .filter_map(Attachment::blob)
+        .collect::<HashSet<_>>();
+
+    let mut count = 0;
+    for file in std::fs::read_dir(blob_dir).map_err(AttachmentError::UnableToRead)? {
+        let name = file
+            .map_err(AttachmentError::UnableToRead)?
+            .file_name()
+            .to_string_lossy()
+            .into_owned();
+
+        // Only blobs are collected, e.g. staging files of blobs being written are left alone
+        let is_blob = name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit());
+        if is_blob && !referenced.contains(name.as_str()) {
+            std::fs::remove_file(blob_dir.join(&name)).map_err(AttachmentError::UnableToWrite)?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
+/// Delete the blobs by name, e.g. of removed entries. Returns the count of deleted blobs
+pub fn remove_blobs<'a>(
+    blob_dir: impl AsRef<Path>,
+    blobs: impl IntoIterator<Item = &'a String>,
+) -> Result<usize, AttachmentError> {
+    let mut count = 0;
+    for blob in blobs {
+        match std::fs::remove_file(blob_dir.as_ref().join(blob)) {
+            Ok(()) => count += 1,
+            // Blob may be deleted already, e.g. by an earlier dump of the same run
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(AttachmentError::UnableToWrite(err)),
+        }
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn blob_attachments() -> Result<(), AttachmentError> {
+        let blob_dir = std::env::temp_dir().join(format!("pass_rs_blobs_{}", std::process::id()));
+
+        let small = Attachment::new("codes.txt", b"recovery codes".to_vec(), &blob_dir)?;
+        assert_eq!(small.blob(), None);
+        assert_eq!(small.content(&blob_dir)?, b"recovery codes");
+
+        let content = vec![7u8; INLINE_ATTACHMENT_SIZE as usize + 1];
+        let large = Attachment::new("kubeconfig", content.clone(), &blob_dir)?;
+        let hash = large
+            .blob()
+            .expect("Large attachment is kept in a blob")
+            .to_owned();
+        assert_eq!(large.content(&blob_dir)?, content);
+
+        // Blob is encrypted & tampering is detected
+        let mut encrypted =
+            std::fs::read(blob_dir.join(&hash)).map_err(AttachmentError::UnableToRead)?;
+        assert!(!encrypted.windows(64).any(|window| window == [7u8; 64]));
+        encrypted[0] ^= 1;
+        std::fs::write(blob_dir.join(&hash), encrypted).map_err(AttachmentError::UnableToWrite)?;
+        assert!(matches!(
+            large.content(&blob_dir),
+            Err(AttachmentError::Corrupted(_))
+        ));
+
+        // Only unreferenced blobs are collected
+        assert_eq!(collect_garbage(&blob_dir, [&large, &small])?, 0);
+        assert_eq!(collect_garbage(&blob_dir, [&small])?, 1);
+        assert!(!blob_dir.join(&hash).exists());
+
+        std::fs::remove_dir_all(&blob_dir).map_err(AttachmentError::UnableToWrite)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_encrypt::{serialize::impls::BincodeSerializer, traits::SerdeEncryptSharedKey};
+use uuid::Uuid;
+
+use super::sealed::{metadata_key, SealedEntry, SealedPayload};
+use super::{decrypt, encrypt, BackendError, MemoryBackend, VaultBackend};
+use crate::pass::entry::{LegacyPasswordEntry, PasswordEntry};
+use crate::pass::header::VaultHeader;
+use crate::pass::secret::{SecretBytes, SecretKey};
+use crate::pass::store::{VaultPayload, VaultSettings};
+use crate::pass::util::atomic_write;
+
+/// Payload of vaults before version 2, which also persisted the master password
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct LegacyVaultPayload {
+    pub(crate) passwords: Vec<LegacyPasswordEntry>,
+    pub(crate) master_password: LegacyMasterPassword,
+}
+
+/// Layout of master password persisted in [LegacyVaultPayload]
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct LegacyMasterPassword {
+    pub(crate) master_pass: Option<SecretBytes>,
+    pub(crate) hash: Option<String>,
+}
+
+impl SerdeEncryptSharedKey for LegacyVaultPayload {
+    type S = BincodeSerializer<Self>;
+}
+
+/// Whole vault in a single file, i.e. the header followed by all entries encrypted at once.
+/// Since version 4, the secrets of each entry are encrypted again on their own.
+///
+/// The file is atomically rewritten on every commit, keeping the previous generation as backup.
+/// As entries are not stored one by one, the commit only keeps the entries read or upserted
+/// since the backend is opened.
+#[derive(Debug)]
+pub struct FileBackend {
+    path: PathBuf,
+
+    /// Content of file read by [VaultBackend::open]
+    content: Vec<u8>,
+
+    /// Entries to be written on commit
+    staged: MemoryBackend,
+}
+
+impl FileBackend {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        FileBackend {
+            path: path.as_ref().to_path_buf(),
+            content: Vec::new(),
+            staged: MemoryBackend::default(),
+        }
+    }
+
+    /// Split the content into header & encrypted entries
+    fn decode(&self) -> Result<Option<(VaultHeader, &[u8])>, BackendError> {
+        if self.content.is_empty() {
+            return Ok(None);
+        }
+
+        match VaultHeader::decode(&self.content).map_err(BackendError::InvalidHeader)? {
+            Some(decoded) => Ok(Some(decoded)),
+
+            // Legacy vault without header, encrypted using the constant salt
+            None => Ok(Some((VaultHeader::legacy(), self.content.as_slice()))),
+        }
+    }
+}
+
+impl VaultBackend for FileBackend {
+    fn open(&mut self) -> Result<Option<VaultHeader>, BackendError> {
+        self.content = match std::fs::read(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(BackendError::UnableToRead(err)),
+        };
+
+        Ok(self.decode()?.map(|(header, _)| header))
+    }
+
+    fn read_entries(
+        &mut self,
+        header: &VaultHeader,
+        key: &SecretKey,
+    ) -> Result<VaultPayload, BackendError> {
+        let Some((_, encrypted)) = self.decode()? else {
+            return Ok(VaultPayload::default());
+        };
+
+        let payload = if header.has_legacy_payload() {
+            // Only keep the entries, master password is never loaded from db
+            let legacy: LegacyVaultPayload = decrypt(encrypted, key, "entries")?;
+            VaultPayload {
+                passwords: legacy.passwords.into_iter().map(Into::into).collect(),
+                ..Default::default()
+            }
+        } else if header.has_sealed_entries() {
+            // Secrets of entries are left sealed
+            let sealed: SealedPayload = decrypt(encrypted, &metadata_key(key), "entries")?;
+            VaultPayload {
+                passwords: sealed
+                    .entries
+                    .into_iter()
+                    .map(SealedEntry::into_entry)
+                    .collect(),
+                settings: sealed.settings,
+            }
+        } else {
+            decrypt(encrypted, key, "entries")?
+        };
+
+        self.staged = MemoryBackend::default();
+        for entry in payload.passwords.iter() {
+            self.staged.upsert(entry, key)?;
+        }
+
+        Ok(payload)
+    }
+
+    fn ids(&mut self) -> Result<Vec<Uuid>, BackendError> {
+        self.staged.ids()
+    }
+
+    fn upsert(&mut self, entry: &PasswordEntry, key: &SecretKey) -> Result<(), BackendError> {
+        self.staged.upsert(entry, key)
+    }
+
+    fn delete(&mut self, id: Uuid) -> Result<(), BackendError> {
+        self.staged.delete(id)
+    }
+
+    fn commit(
+        &mut self,
+        header: &VaultHeader,
+        settings: &VaultSettings,
+        key: &SecretKey,
+    ) -> Result<(), BackendError> {
+        self.staged.commit(header, settings, key)?;
+
+        let mut header = header.clone();
+        header.set_key_check(key.expose());
+
+        let payload = self.staged.read_entries(&header, key)?;
+        let sealed = SealedPayload {
+            entries: payload
+                .passwords
+                .iter()
+                .map(|entry| SealedEntry::new(entry, key))
+                .collect::<Result<_, _>>()?,
+            settings: payload.settings,
+        };
+        let content = header
+            .encode(encrypt(&sealed, &metadata_key(key), "entries")?)
+            .map_err(BackendError::InvalidHeader)?;
+
+        // Previous generation is kept as backup
+        atomic_write(&self.path, &content).map_err(BackendError::UnableToWrite)?;
+        self.content = content;
+
+        Ok(())
+    }
+}
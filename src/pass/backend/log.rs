@@ -0,0 +1,686 @@
+use std::collections::HashMap;
+use std::io::{Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use ring::hmac;
+use uuid::Uuid;
+
+use super::sealed::{digest, metadata_key, SealedEntry};
+use super::{decrypt, encrypt, BackendError, VaultBackend};
+use crate::pass::entry::PasswordEntry;
+use crate::pass::header::{VaultHeader, VaultHeaderError};
+use crate::pass::secret::SecretKey;
+use crate::pass::store::{VaultPayload, VaultSettings};
+use crate::pass::util::atomic_write;
+
+/// Magic bytes at the start of every log, followed by its tip, the vault header & the records
+pub const LOG_MAGIC: &[u8; 8] = b"PASSLOG\0";
+
+/// Length of [digest] of entries, which is also the length of MAC of commits
+const DIGEST_LEN: usize = 32;
+
+/// Purpose of the subkey chaining the MACs of commits
+const CHAIN_PURPOSE: &[u8] = b"pass_rs log chain";
+
+/// Layout of frame: | tag (u8) | id (16 bytes) | digest | body length (u32 LE) | body |
+const FRAME_HEADER_LEN: usize = 1 + 16 + DIGEST_LEN + 4;
+
+/// Log is compacted once it has more stale records than live entries, but not before this
+const MIN_STALE_RECORDS: usize = 256;
+
+/// Type of record, stored unencrypted along with the id & digest of its entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Tag {
+    /// Entry added or changed, its body is the [SealedEntry] encrypted by metadata subkey
+    Entry = 0,
+
+    /// Entry removed, without body
+    Delete = 1,
+
+    /// End of records appended at once, its body is the encrypted [VaultSettings] & its digest
+    /// is the MAC of the records since the previous commit, chained to the MAC of that commit
+    Commit = 2,
+}
+
+impl Tag {
+    fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0 => Some(Tag::Entry),
+            1 => Some(Tag::Delete),
+            2 => Some(Tag::Commit),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    tag: Tag,
+    id: Uuid,
+    digest: Vec<u8>,
+
+    /// Whole frame & its body within the records
+    range: Range<usize>,
+    body: Range<usize>,
+}
+
+fn encode_frame(tag: Tag, id: Uuid, digest: &[u8], body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(FRAME_HEADER_LEN + body.len());
+    frame.push(tag as u8);
+    frame.extend_from_slice(id.as_bytes());
+    frame.extend_from_slice(digest);
+    frame.resize(1 + 16 + DIGEST_LEN, 0);
+    frame.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    frame.extend_from_slice(body);
+    frame
+}
+
+/// Key of the MACs of commits, derived from the vault key
+fn chain_key(key: &SecretKey) -> hmac::Key {
+    hmac::Key::new(hmac::HMAC_SHA256, key.subkey(CHAIN_PURPOSE).expose())
+}
+
+/// MAC of the frames appended along with the commit frame ending them, chained to the MAC of
+/// the previous commit. Digest of the commit frame is left out, as it is the MAC itself.
+fn commit_mac(key: &hmac::Key, chain: &[u8], frames: &[u8], commit: &[u8]) -> Vec<u8> {
+    let mut context = hmac::Context::with_key(key);
+    context.update(chain);
+    context.update(frames);
+    context.update(&commit[..1 + 16]);
+    context.update(&commit[1 + 16 + DIGEST_LEN..]);
+    context.sign().as_ref().to_vec()
+}
+
+/// Commit frame having the settings, along with its MAC chained to the previous commit
+fn seal_commit(
+    key: &hmac::Key,
+    chain: &[u8],
+    frames: &[u8],
+    settings: &[u8],
+) -> (Vec<u8>, Vec<u8>) {
+    let mut commit = encode_frame(Tag::Commit, Uuid::nil(), &[], settings);
+    let mac = commit_mac(key, chain, frames, &commit);
+    commit[1 + 16..1 + 16 + DIGEST_LEN].copy_from_slice(&mac);
+    (commit, mac)
+}
+
+/// Tip of the log committed last by the MAC, which can't be told from the commit without the
+/// vault key, so a tip of an earlier commit can't be written into the log
+fn tip_mac(key: &hmac::Key, chain: &[u8]) -> Vec<u8> {
+    let mut context = hmac::Context::with_key(key);
+    context.update(b"tip");
+    context.update(chain);
+    context.sign().as_ref().to_vec()
+}
+
+/// Frame starting at offset, `None` if the records end (maybe torn) before the whole frame
+fn decode_frame(records: &[u8], offset: usize) -> Result<Option<Frame>, BackendError> {
+    let Some(header) = records.get(offset..offset + FRAME_HEADER_LEN) else {
+        return Ok(None);
+    };
+
+    let tag = Tag::from_byte(header[0]).ok_or(BackendError::InvalidRecord(offset))?;
+    let id = Uuid::from_slice(&header[1..17]).map_err(|_| BackendError::InvalidRecord(offset))?;
+    let digest = header[17..17 + DIGEST_LEN].to_vec();
+    let len = u32::from_le_bytes(
+        header[17 + DIGEST_LEN..]
+            .try_into()
+            .map_err(|_| BackendError::InvalidRecord(offset))?,
+    ) as usize;
+
+    let body = offset + FRAME_HEADER_LEN..offset + FRAME_HEADER_LEN + len;
+    match body.end <= records.len() {
+        true => Ok(Some(Frame {
+            tag,
+            id,
+            digest,
+            range: offset..body.end,
+            body,
+        })),
+        false => Ok(None),
+    }
+}
+
+/// Entries & settings of the log, replayed from its committed records
+#[derive(Debug, Default)]
+struct LogState {
+    /// Latest frame of each entry, along with the sequence of its first frame
+    entries: HashMap<Uuid, (u64, Frame)>,
+
+    /// Ids of entries in order of their first frame
+    order: Vec<Uuid>,
+
+    /// Latest commit, having the settings
+    commit: Option<Frame>,
+
+    /// Number of committed frames
+    frames: usize,
+
+    /// Length of committed records, anything after it is a torn (or failed) append
+    committed: usize,
+
+    /// Sequence of the next added entry
+    sequence: u64,
+}
+
+impl LogState {
+    /// Apply the frames after committed records, up to the last commit
+    fn replay(&mut self, records: &[u8]) -> Result<(), BackendError> {
+        let mut batch = Vec::new();
+        let mut offset = self.committed;
+
+        while let Some(frame) = decode_frame(records, offset)? {
+            offset = frame.range.end;
+            if frame.tag != Tag::Commit {
+                batch.push(frame);
+                continue;
+            }
+
+            self.frames += batch.len() + 1;
+            for frame in batch.drain(..) {
+                match frame.tag {
+                    Tag::Entry => {
+                        let sequence = match self.entries.remove(&frame.id) {
+                            Some((sequence, _)) => sequence,
+                            None => {
+                                self.sequence += 1;
+                                self.sequence
+                            }
+                        };
+                        self.entries.insert(frame.id, (sequence, frame));
+                    }
+                    Tag::Delete => {
+                        self.entries.remove(&frame.id);
+                    }
+                    Tag::Commit => unreachable!("Commit ends the batch"),
+                }
+            }
+            self.committed = offset;
+            self.commit = Some(frame);
+        }
+
+        let mut order = self
+            .entries
+            .iter()
+            .map(|(id, (sequence, _))| (*sequence, *id))
+            .collect::<Vec<_>>();
+        order.sort_unstable();
+        self.order = order.into_iter().map(|(_, id)| id).collect();
+
+        Ok(())
+    }
+
+    /// Number of committed frames superseded by later ones
+    fn stale(&self) -> usize {
+        self.frames
+            .saturating_sub(self.entries.len() + usize::from(self.commit.is_some()))
+    }
+}
+
+/// Vault as an append-only log of encrypted records, so that a commit only appends the
+/// changed entries. Log is compacted into its live entries once most of it is stale.
+///
+/// Commits are chained by their MACs under the vault key & the tip (rewritten in place after
+/// each append) is derived from the MAC of the latest one. So records are authenticated as a
+/// whole & a log cut back to an earlier commit is refused.
+///
+/// Layout of file: | LOG_MAGIC | tip | vault header, as in `passwords.db` | records |
+#[derive(Debug)]
+pub struct LogBackend {
+    path: PathBuf,
+
+    /// Header & records read by [VaultBackend::open], `None` if nothing is stored yet
+    header: Option<VaultHeader>,
+    records: Vec<u8>,
+    state: LogState,
+
+    /// Tip stored in the log, see [tip_mac]
+    tip: Vec<u8>,
+
+    /// Length of records whose commits are checked by the vault key, along with the MAC of
+    /// the last of them
+    verified: usize,
+    chain: Vec<u8>,
+
+    /// Offset of records in the file, i.e. length of magic & header
+    offset: u64,
+    is_open: bool,
+
+    /// Frames to be appended on commit
+    pending: Vec<u8>,
+}
+
+impl LogBackend {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        LogBackend {
+            path: path.as_ref().to_path_buf(),
+            header: None,
+            records: Vec::new(),
+            state: LogState::default(),
+            tip: Vec::new(),
+            verified: 0,
+            chain: Vec::new(),
+            offset: 0,
+            is_open: false,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Read the log if it is not read yet, as changes are compared against the stored entries
+    fn ensure_open(&mut self) -> Result<(), BackendError> {
+        match self.is_open {
+            true => Ok(()),
+            false => self.open().map(|_| ()),
+        }
+    }
+
+    /// Check the MACs of commits in the committed records, from where they are checked last,
+    /// & that the tip is of one of them
+    fn verify(&mut self, key: &SecretKey) -> Result<(), BackendError> {
+        // Nothing is stored yet
+        if self.header.is_none() {
+            return Ok(());
+        }
+
+        let key = chain_key(key);
+        let mut offset = self.verified;
+        let mut has_tip = self.verified > 0;
+
+        while offset < self.state.committed {
+            let frame =
+                decode_frame(&self.records, offset)?.ok_or(BackendError::InvalidRecord(offset))?;
+            offset = frame.range.end;
+            if frame.tag != Tag::Commit {
+                continue;
+            }
+
+            let mac = commit_mac(
+                &key,
+                &self.chain,
+                &self.records[self.verified..frame.range.start],
+                &self.records[frame.range.clone()],
+            );
+            if mac != frame.digest {
+                return Err(BackendError::UnauthenticatedRecord(self.verified));
+            }
+            has_tip |= tip_mac(&key, &mac) == self.tip;
+            self.chain = mac;
+            self.verified = offset;
+        }
+
+        // Commits after the tip are authenticated as well, as a run may crash before moving the
+        // tip, but a log ending before the commit of its tip is truncated or rolled back
+        match has_tip {
+            true => Ok(()),
+            false => Err(BackendError::TruncatedLog),
+        }
+    }
+
+    /// Rewrite the log having only the live entries & latest commit
+    fn compact(&mut self, header: &VaultHeader, key: &SecretKey) -> Result<(), BackendError> {
+        let mut records = Vec::with_capacity(self.state.committed);
+        self.state
+            .order
+            .iter()
+            .filter_map(|id| self.state.entries.get(id))
+            .for_each(|(_, frame)| records.extend_from_slice(&self.records[frame.range.clone()]));
+
+        // Rewritten records start a new chain of commits
+        let settings = match &self.state.commit {
+            Some(commit) => &self.records[commit.body.clone()],
+            None => &[],
+        };
+        let chain_key = chain_key(key);
+        let (commit, mac) = seal_commit(&chain_key, &[], &records, settings);
+        let tip = tip_mac(&chain_key, &mac);
+        records.extend(commit);
+
+        let mut content = LOG_MAGIC.to_vec();
+        content.extend_from_slice(&tip);
+        content.extend(
+            header
+                .encode(&records)
+                .map_err(BackendError::InvalidHeader)?,
+        );
+
+        // Previous generation is kept as backup
+        atomic_write(&self.path, &content).map_err(BackendError::UnableToWrite)?;
+
+        self.offset = (content.len() - records.len()) as u64;
+        self.header = Some(header.clone());
+        self.records = records;
+        self.state = LogState::default();
+        self.state.replay(&self.records)?;
+        self.tip = tip;
+        self.verified = self.state.committed;
+        self.chain = mac;
+
+        Ok(())
+    }
+
+    /// Append the frames to the log, dropping a torn append after committed records, then move
+    /// the tip to the commit ending them
+    fn append(&self, frames: &[u8], committed: usize, tip: &[u8]) -> Result<(), BackendError> {
+        let end = self.offset + committed as u64;
+
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(&self.path)
+            .map_err(BackendError::UnableToWrite)?;
+        file.set_len(end)
+            .and_then(|_| file.seek(SeekFrom::Start(end)))
+            .and_then(|_| file.write_all(frames))
+            .and_then(|_| file.sync_data())
+            .and_then(|_| file.seek(SeekFrom::Start(LOG_MAGIC.len() as u64)))
+            .and_then(|_| file.write_all(tip))
+            .and_then(|_| file.sync_data())
+            .map_err(BackendError::UnableToWrite)
+    }
+}
+
+impl VaultBackend for LogBackend {
+    fn open(&mut self) -> Result<Option<VaultHeader>, BackendError> {
+        let content = match std::fs::read(&self.path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(BackendError::UnableToRead(err)),
+        };
+
+        self.is_open = true;
+        self.pending.clear();
+        self.state = LogState::default();
+        self.verified = 0;
+        self.chain.clear();
+
+        if content.is_empty() {
+            self.header = None;
+            self.records.clear();
+            return Ok(None);
+        }
+
+        let (tip, header) = content
+            .strip_prefix(LOG_MAGIC.as_slice())
+            .filter(|content| content.len() >= DIGEST_LEN)
+            .map(|content| content.split_at(DIGEST_LEN))
+            .ok_or(BackendError::InvalidHeader(VaultHeaderError::Truncated))?;
+        let (header, records) = VaultHeader::decode(header)
+            .map_err(BackendError::InvalidHeader)?
+            .ok_or(BackendError::InvalidHeader(VaultHeaderError::Truncated))?;
+
+        self.offset = (content.len() - records.len()) as u64;
+        self.records = records.to_vec();
+        self.state.replay(&self.records)?;
+        self.tip = tip.to_vec();
+        self.header = Some(header.clone());
+
+        Ok(Some(header))
+    }
+
+    fn read_entries(
+        &mut self,
+        _header: &VaultHeader,
+        key: &SecretKey,
+    ) -> Result<VaultPayload, BackendError> {
+        self.ensure_open()?;
+        self.verify(key)?;
+        let metadata_key = metadata_key(key);
+
+        let settings = match &self.state.commit {
+            Some(commit) => decrypt(
+                &self.records[commit.body.clone()],
+                &metadata_key,
+                "settings",
+            )?,
+            None => VaultSettings::default(),
+        };
+
+        // Secrets of entries are left sealed
+        let passwords = self
+            .state
+            .order
+            .iter()
+            .filter_map(|id| self.state.entries.get(id))
+            .map(|(_, frame)| {
+                let entry = decrypt::<SealedEntry>(
+                    &self.records[frame.body.clone()],
+                    &metadata_key,
+                    "entry",
+                )?
+                .into_entry();
+                match entry.id == frame.id {
+                    true => Ok(entry),
+                    false => Err(BackendError::InvalidRecord(frame.range.start)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(VaultPayload {
+            passwords,
+            settings,
+        })
+    }
+
+    fn ids(&mut self) -> Result<Vec<Uuid>, BackendError> {
+        self.ensure_open()?;
+        Ok(self.state.order.clone())
+    }
+
+    fn upsert(&mut self, entry: &PasswordEntry, key: &SecretKey) -> Result<(), BackendError> {
+        self.ensure_open()?;
+
+        // Unchanged entry under the same key is already stored as it is
+        let digest = digest(entry, key)?;
+        let is_stored = self
+            .state
+            .entries
+            .get(&entry.id)
+            .is_some_and(|(_, frame)| frame.digest == digest);
+        if is_stored {
+            return Ok(());
+        }
+
+        let sealed = SealedEntry::new(entry, key)?;
+        let body = encrypt(&sealed, &metadata_key(key), "entry")?;
+        self.pending
+            .extend(encode_frame(Tag::Entry, entry.id, &digest, &body));
+
+        Ok(())
+    }
+
+    fn delete(&mut self, id: Uuid) -> Result<(), BackendError> {
+        self.ensure_open()?;
+        self.pending.extend(encode_frame(Tag::Delete, id, &[], &[]));
+
+        Ok(())
+    }
+
+    fn commit(
+        &mut self,
+        header: &VaultHeader,
+        settings: &VaultSettings,
+        key: &SecretKey,
+    ) -> Result<(), BackendError> {
+        self.ensure_open()?;
+
+        let mut header = header.clone();
+        header.set_key_check(key.expose());
+
+        // Entries are re-encrypted by a changed key, so the old records are all stale
+        let is_rekeyed = self.header.as_ref() != Some(&header);
+
+        // Frames are only chained to commits made by the same key
+        if !is_rekeyed {
+            self.verify(key)?;
+        }
+
+        let settings = encrypt(settings, &metadata_key(key), "settings")?;
+        let mut frames = std::mem::take(&mut self.pending);
+        let chain_key = chain_key(key);
+        let (commit, mac) = seal_commit(&chain_key, &self.chain, &frames, &settings);
+        let tip = tip_mac(&chain_key, &mac);
+        frames.extend(commit);
+
+        // Appended frames are replayed first, to know whether the log is worth compacting
+        let committed = self.state.committed;
+        self.records.truncate(committed);
+        self.records.extend_from_slice(&frames);
+        self.state.replay(&self.records)?;
+
+        let is_stale = self.state.stale() > self.state.entries.len().max(MIN_STALE_RECORDS);
+        if is_rekeyed || is_stale {
+            return self.compact(&header, key);
+        }
+
+        self.append(&frames, committed, &tip)?;
+        self.tip = tip;
+        self.verified = self.state.committed;
+        self.chain = mac;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pass::backend::unseal;
+    use crate::pass::kdf::Kdf;
+    use crate::pass::util::backup_path;
+
+    #[test]
+    fn log_appends_and_compacts() -> Result<(), BackendError> {
+        let path = std::env::temp_dir().join(format!("pass_rs_log_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let header = VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1 });
+        let key = header
+            .kdf
+            .derive_key("Test123@", &header.salt)
+            .expect("Unable to derive key");
+        let settings = VaultSettings::default();
+        let mut entries = ["github", "gitlab", "mail"]
+            .map(|service| PasswordEntry::new(service.to_owned(), None, Some("pass"), None));
+
+        let mut backend = LogBackend::new(&path);
+        entries
+            .iter()
+            .try_for_each(|entry| backend.upsert(entry, &key))?;
+        backend.commit(&header, &settings, &key)?;
+        let written = std::fs::read(&path).map_err(BackendError::UnableToRead)?;
+
+        // Only the changed entry is appended, earlier records are untouched
+        entries[1].change_password("changed");
+        let mut backend = LogBackend::new(&path);
+        entries
+            .iter()
+            .try_for_each(|entry| backend.upsert(entry, &key))?;
+        backend.commit(&header, &settings, &key)?;
+        let appended = std::fs::read(&path).map_err(BackendError::UnableToRead)?;
+        let records = LOG_MAGIC.len() + DIGEST_LEN;
+        assert!(appended[records..].starts_with(&written[records..]));
+        assert_eq!(backend.state.frames, 4 + 2);
+
+        // Torn append is ignored & dropped by the next commit
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .map_err(BackendError::UnableToWrite)?;
+        file.write_all(&[Tag::Entry as u8; 10])
+            .map_err(BackendError::UnableToWrite)?;
+        let mut backend = LogBackend::new(&path);
+        let stored = backend.open()?.expect("Header is committed");
+        let payload = backend.read_entries(&stored, &key)?;
+        assert_eq!(payload.passwords.len(), 3);
+        backend.delete(entries[2].id)?;
+        backend.commit(&header, &settings, &key)?;
+        assert_eq!(backend.state.committed, backend.records.len());
+
+        // Stale records are compacted away, keeping the entries & their order
+        for _ in 0..MIN_STALE_RECORDS {
+            entries[0].mark_accessed();
+            backend.upsert(&entries[0], &key)?;
+            backend.commit(&header, &settings, &key)?;
+        }
+        assert!(backend.state.stale() <= MIN_STALE_RECORDS);
+        assert!(backend.state.frames < MIN_STALE_RECORDS);
+
+        let mut backend = LogBackend::new(&path);
+        let payload = backend.read_entries(&header, &key)?;
+        let mut unsealed = payload.passwords;
+        unsealed
+            .iter_mut()
+            .try_for_each(|entry| unseal(entry, &key))?;
+        assert_eq!(unsealed, entries[..2]);
+
+        std::fs::remove_file(&path).map_err(BackendError::UnableToWrite)?;
+        let _ = std::fs::remove_file(backup_path(&path));
+        Ok(())
+    }
+
+    #[test]
+    fn log_refuses_tampering() -> Result<(), BackendError> {
+        let path = std::env::temp_dir().join(format!("pass_rs_tamper_{}.log", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let header = VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1 });
+        let key = header
+            .kdf
+            .derive_key("Test123@", &header.salt)
+            .expect("Unable to derive key");
+        let settings = VaultSettings::default();
+        let mut entries = ["github", "gitlab"]
+            .map(|service| PasswordEntry::new(service.to_owned(), None, Some("pass"), None));
+        let read = |content: &[u8]| {
+            std::fs::write(&path, content).map_err(BackendError::UnableToWrite)?;
+            let mut backend = LogBackend::new(&path);
+            backend.read_entries(&header, &key)
+        };
+
+        let mut backend = LogBackend::new(&path);
+        entries
+            .iter()
+            .try_for_each(|entry| backend.upsert(entry, &key))?;
+        backend.commit(&header, &settings, &key)?;
+        let written = std::fs::read(&path).map_err(BackendError::UnableToRead)?;
+
+        entries[1].change_password("changed");
+        backend.upsert(&entries[1], &key)?;
+        backend.commit(&header, &settings, &key)?;
+        let appended = std::fs::read(&path).map_err(BackendError::UnableToRead)?;
+        let tip = LOG_MAGIC.len()..LOG_MAGIC.len() + DIGEST_LEN;
+
+        // Forged frame breaks the chain of commits
+        let commit = backend.offset as usize
+            + backend
+                .state
+                .commit
+                .as_ref()
+                .expect("Log is committed")
+                .range
+                .start;
+        let mut forged = appended[..commit].to_vec();
+        forged.extend(encode_frame(Tag::Delete, entries[0].id, &[], &[]));
+        forged.extend_from_slice(&appended[commit..]);
+        assert!(matches!(
+            read(&forged),
+            Err(BackendError::UnauthenticatedRecord(_))
+        ));
+
+        // Log cut back to an earlier commit is refused, unless the whole log is rolled back
+        let mut truncated = appended[..written.len()].to_vec();
+        assert!(matches!(read(&truncated), Err(BackendError::TruncatedLog)));
+        truncated[tip.clone()].copy_from_slice(&written[tip.clone()]);
+        assert_eq!(read(&truncated)?.passwords.len(), 2);
+
+        // Commit appended by a run which crashed before moving the tip is kept
+        let mut crashed = appended.clone();
+        crashed[tip.clone()].copy_from_slice(&written[tip]);
+        let mut stored = read(&crashed)?.passwords;
+        unseal(&mut stored[1], &key)?;
+        assert_eq!(stored[1], entries[1]);
+
+        std::fs::remove_file(&path).map_err(BackendError::UnableToWrite)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use super::{BackendError, VaultBackend};
+use crate::pass::entry::PasswordEntry;
+use crate::pass::header::VaultHeader;
+use crate::pass::secret::SecretKey;
+use crate::pass::store::{VaultPayload, VaultSettings};
+
+/// Vault kept unencrypted in memory & never persisted, e.g. for tests.
+/// Changes are applied right away, as there is nothing to commit them to.
+#[derive(Debug, Default)]
+pub struct MemoryBackend {
+    header: Option<VaultHeader>,
+    payload: VaultPayload,
+
+    /// Positions of entries by their id, except of the entries having nil id
+    positions: HashMap<Uuid, usize>,
+}
+
+impl VaultBackend for MemoryBackend {
+    fn open(&mut self) -> Result<Option<VaultHeader>, BackendError> {
+        Ok(self.header.clone())
+    }
+
+    fn read_entries(
+        &mut self,
+        _header: &VaultHeader,
+        _key: &SecretKey,
+    ) -> Result<VaultPayload, BackendError> {
+        Ok(self.payload.clone())
+    }
+
+    fn ids(&mut self) -> Result<Vec<Uuid>, BackendError> {
+        Ok(self
+            .payload
+            .passwords
+            .iter()
+            .map(|entry| entry.id)
+            .collect())
+    }
+
+    fn upsert(&mut self, entry: &PasswordEntry, _key: &SecretKey) -> Result<(), BackendError> {
+        let passwords = &mut self.payload.passwords;
+        match self.positions.get(&entry.id) {
+            Some(&position) => passwords[position] = entry.clone(),
+            None => {
+                // Entries of vaults before ids all have the nil id, so they are never the same
+                if !entry.id.is_nil() {
+                    self.positions.insert(entry.id, passwords.len());
+                }
+                passwords.push(entry.clone());
+            }
+        };
+
+        Ok(())
+    }
+
+    fn delete(&mut self, id: Uuid) -> Result<(), BackendError> {
+        // Nil id removes all entries of vaults before ids, e.g. once their ids are backfilled
+        if self.positions.remove(&id).is_none() && !id.is_nil() {
+            return Ok(());
+        }
+
+        self.payload.passwords.retain(|entry| entry.id != id);
+        self.positions = self
+            .payload
+            .passwords
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| !entry.id.is_nil())
+            .map(|(position, entry)| (entry.id, position))
+            .collect();
+        Ok(())
+    }
+
+    fn commit(
+        &mut self,
+        header: &VaultHeader,
+        settings: &VaultSettings,
+        key: &SecretKey,
+    ) -> Result<(), BackendError> {
+        let mut header = header.clone();
+        header.set_key_check(key.expose());
+
+        self.header = Some(header);
+        self.payload.settings = settings.clone();
+
+        Ok(())
+    }
+}
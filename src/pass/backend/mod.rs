@@ -0,0 +1,289 @@
+mod file;
+mod log;
+mod memory;
+mod sealed;
+mod sqlite;
+
+use std::io::Read;
+use std::path::Path;
+
+use serde::{de::DeserializeOwned, Serialize};
+use serde_encrypt::{
+    serialize::TypedSerialized, shared_key::SharedKey, traits::SerdeEncryptSharedKey,
+    EncryptedMessage,
+};
+use uuid::Uuid;
+
+use crate::pass::entry::PasswordEntry;
+use crate::pass::header::{VaultHeader, VaultHeaderError};
+use crate::pass::secret::SecretKey;
+use crate::pass::store::{VaultPayload, VaultSettings};
+
+pub use file::FileBackend;
+#[cfg(test)]
+pub(crate) use file::{LegacyMasterPassword, LegacyVaultPayload};
+pub use log::LogBackend;
+pub use memory::MemoryBackend;
+#[cfg(test)]
+pub(crate) use sealed::{metadata_key, SealedPayload};
+pub use sealed::{unseal, EntrySecrets, SealedSecrets};
+pub use sqlite::SqliteBackend;
+
+/// Magic bytes at the start of every SQLite database
+const SQLITE_MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackendError {
+    #[error("Unable to read vault: {0}")]
+    UnableToRead(#[source] std::io::Error),
+
+    #[error("Unable to write vault: {0}")]
+    UnableToWrite(#[source] std::io::Error),
+
+    #[error("Invalid vault header: {0}")]
+    InvalidHeader(#[source] VaultHeaderError),
+
+    #[error("Failed to encrypt {0}")]
+    UnableToEncrypt(&'static str),
+
+    #[error("Failed to decrypt {0}")]
+    UnableToDecrypt(&'static str),
+
+    #[error("Unable to deserialize decrypted {0}")]
+    UnableToDeserialize(&'static str),
+
+    #[error("Invalid id of entry '{0}'")]
+    InvalidId(String),
+
+    #[error("Row of entry '{0}' has another entry")]
+    MismatchedId(String),
+
+    #[error("Database error: {0}")]
+    Database(#[source] rusqlite::Error),
+
+    #[error("Invalid record at byte {0} of vault log")]
+    InvalidRecord(usize),
+
+    #[error("Records from byte {0} of vault log are not authenticated by the vault key")]
+    UnauthenticatedRecord(usize),
+
+    #[error("Vault log ends before its latest commit, it is truncated or rolled back")]
+    TruncatedLog,
+}
+
+/// Storage of a vault: the plain-text header along with the encrypted settings & entries.
+///
+/// Changes by [VaultBackend::upsert] & [VaultBackend::delete] are persisted all at once by
+/// [VaultBackend::commit], dropping the backend before it discards them.
+pub trait VaultBackend {
+    /// Read the header of vault, `None` if nothing is stored yet
+    fn open(&mut self) -> Result<Option<VaultHeader>, BackendError>;
+
+    /// Decrypt the settings & all entries by the key derived for the header
+    fn read_entries(
+        &mut self,
+        header: &VaultHeader,
+        key: &SecretKey,
+    ) -> Result<VaultPayload, BackendError>;
+
+    /// Ids of the stored entries
+    fn ids(&mut self) -> Result<Vec<Uuid>, BackendError>;
+
+    /// Add the entry, or replace the stored entry having same id
+    fn upsert(&mut self, entry: &PasswordEntry, key: &SecretKey) -> Result<(), BackendError>;
+
+    /// Remove the entry having the id, if it is stored
+    fn delete(&mut self, id: Uuid) -> Result<(), BackendError>;
+
+    /// Persist the changes along with header (having key check of `key`) & settings
+    fn commit(
+        &mut self,
+        header: &VaultHeader,
+        settings: &VaultSettings,
+        key: &SecretKey,
+    ) -> Result<(), BackendError>;
+}
+
+/// On-disk format of `passwords.db`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackendKind {
+    /// All entries encrypted at once in a single file, see [FileBackend]
+    File,
+
+    /// SQLite database having each entry encrypted in its own row, see [SqliteBackend]
+    Sqlite,
+
+    /// Append-only log of encrypted entries, see [LogBackend]
+    Log,
+}
+
+impl BackendKind {
+    /// Detect the format of vault by its first bytes, vaults not created yet are single files
+    pub fn detect(path: impl AsRef<Path>) -> Result<Self, BackendError> {
+        let mut file = match std::fs::File::open(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(BackendKind::File),
+            Err(err) => return Err(BackendError::UnableToRead(err)),
+        };
+
+        let mut magic = Vec::with_capacity(SQLITE_MAGIC.len());
+        file.by_ref()
+            .take(SQLITE_MAGIC.len() as u64)
+            .read_to_end(&mut magic)
+            .map_err(BackendError::UnableToRead)?;
+
+        if magic == SQLITE_MAGIC {
+            Ok(BackendKind::Sqlite)
+        } else if magic.starts_with(log::LOG_MAGIC) {
+            Ok(BackendKind::Log)
+        } else {
+            Ok(BackendKind::File)
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackendKind::File => "single file",
+            BackendKind::Sqlite => "SQLite",
+            BackendKind::Log => "append-only log",
+        }
+    }
+
+    /// Open the vault at path in this format
+    pub fn open(&self, path: impl AsRef<Path>) -> Result<Box<dyn VaultBackend>, BackendError> {
+        match self {
+            BackendKind::File => Ok(Box::new(FileBackend::new(path))),
+            BackendKind::Sqlite => Ok(Box::new(SqliteBackend::new(path)?)),
+            BackendKind::Log => Ok(Box::new(LogBackend::new(path))),
+        }
+    }
+
+    /// Open an empty vault at path in this format, replacing anything stored there
+    pub fn create(&self, path: impl AsRef<Path>) -> Result<Box<dyn VaultBackend>, BackendError> {
+        match std::fs::remove_file(path.as_ref()) {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                Err(BackendError::UnableToWrite(err))
+            }
+            _ => self.open(path),
+        }
+    }
+}
+
+/// Open the vault at path in its detected format
+pub fn open_backend(path: impl AsRef<Path>) -> Result<Box<dyn VaultBackend>, BackendError> {
+    BackendKind::detect(path.as_ref())?.open(path)
+}
+
+fn encrypt<T>(value: &T, key: &SecretKey, name: &'static str) -> Result<Vec<u8>, BackendError>
+where
+    T: SerdeEncryptSharedKey + Serialize,
+{
+    value
+        .encrypt(&SharedKey::new(*key.expose()))
+        .map(|encrypted| encrypted.serialize())
+        .map_err(|_| BackendError::UnableToEncrypt(name))
+}
+
+fn decrypt<T>(content: &[u8], key: &SecretKey, name: &'static str) -> Result<T, BackendError>
+where
+    T: SerdeEncryptSharedKey + DeserializeOwned,
+{
+    let encrypted = EncryptedMessage::deserialize(content.to_vec())
+        .map_err(|_| BackendError::UnableToDecrypt(name))?;
+    T::decrypt_ref(&encrypted, &SharedKey::new(*key.expose()))
+        .map_err(|_| BackendError::UnableToDecrypt(name))?
+        .deserialize()
+        .map_err(|_| BackendError::UnableToDeserialize(name))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pass::kdf::Kdf;
+
+    fn test_key(header: &VaultHeader) -> SecretKey {
+        Kdf::legacy()
+            .derive_key("Test123@", &header.salt)
+            .expect("Unable to derive key")
+    }
+
+    /// Same changes give same entries by every backend, including after reopening
+    #[test]
+    fn backends_are_interchangeable() -> Result<(), BackendError> {
+        let dir = std::env::temp_dir().join(format!("pass_rs_backends_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).map_err(BackendError::UnableToWrite)?;
+
+        let header = VaultHeader::new();
+        let key = test_key(&header);
+        let settings = VaultSettings {
+            history_depth: 2,
+            ..Default::default()
+        };
+        let entries = ["github", "gitlab", "mail"]
+            .map(|service| PasswordEntry::new(service.to_owned(), None, Some("pass"), None));
+        let mut updated = entries[0].clone();
+        updated.username = Some("ishan".to_owned());
+
+        let memory: Box<dyn VaultBackend> = Box::new(MemoryBackend::default());
+        let file = BackendKind::File.create(dir.join("passwords.db"))?;
+        let sqlite = BackendKind::Sqlite.create(dir.join("passwords.sqlite"))?;
+        let log = BackendKind::Log.create(dir.join("passwords.log"))?;
+
+        for mut backend in [memory, file, sqlite, log] {
+            assert!(backend.open()?.is_none());
+
+            entries
+                .iter()
+                .try_for_each(|entry| backend.upsert(entry, &key))?;
+            backend.upsert(&updated, &key)?;
+            backend.delete(entries[1].id)?;
+            backend.commit(&header, &settings, &key)?;
+
+            let mut stored = backend.open()?.expect("Header is committed");
+            assert!(stored.verify_key(key.expose()));
+            stored.key_check.clear();
+            assert_eq!(stored, header);
+
+            let mut payload = backend.read_entries(&header, &key)?;
+            payload
+                .passwords
+                .iter_mut()
+                .try_for_each(|entry| unseal(entry, &key))?;
+            assert_eq!(payload.passwords, vec![updated.clone(), entries[2].clone()]);
+            assert_eq!(payload.settings, settings);
+            assert_eq!(backend.ids()?, vec![updated.id, entries[2].id]);
+        }
+
+        // Persisted backends are detected & read back from disk
+        for (name, kind) in [
+            ("passwords.db", BackendKind::File),
+            ("passwords.sqlite", BackendKind::Sqlite),
+            ("passwords.log", BackendKind::Log),
+        ] {
+            let path = dir.join(name);
+            assert_eq!(BackendKind::detect(&path)?, kind);
+
+            let mut backend = open_backend(&path)?;
+            let header = backend.open()?.expect("Header is committed");
+            let mut payload = backend.read_entries(&header, &key)?;
+
+            // Secrets are read sealed, along with what is listed of them
+            assert!(payload.passwords.iter().all(PasswordEntry::is_sealed));
+            assert_eq!(payload.passwords[0].kind_name(), "login");
+
+            // Entry stays sealed if its secrets can't be decrypted
+            let wrong_key = test_key(&VaultHeader::new());
+            assert!(unseal(&mut payload.passwords[0], &wrong_key).is_err());
+            assert!(payload.passwords[0].is_sealed());
+
+            payload
+                .passwords
+                .iter_mut()
+                .try_for_each(|entry| unseal(entry, &key))?;
+            assert_eq!(payload.passwords, vec![updated.clone(), entries[2].clone()]);
+        }
+
+        std::fs::remove_dir_all(&dir).map_err(BackendError::UnableToWrite)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+use serde_encrypt::{serialize::impls::CborSerializer, traits::SerdeEncryptSharedKey};
+
+use super::{decrypt, encrypt, BackendError};
+use crate::pass::attachment::Attachment;
+use crate::pass::entry::{CustomField, Password, PasswordEntry, PasswordHistory};
+use crate::pass::kind::EntryKind;
+use crate::pass::otp::Otp;
+use crate::pass::secret::SecretKey;
+use crate::pass::store::VaultSettings;
+
+/// Purpose of the subkey encrypting service, username, notes & the rest of metadata
+const METADATA_PURPOSE: &[u8] = b"pass_rs metadata";
+
+/// Purpose of the subkey encrypting [EntrySecrets]
+const SECRETS_PURPOSE: &[u8] = b"pass_rs secrets";
+
+/// Key of the metadata of entries & settings, derived from the vault key
+pub fn metadata_key(key: &SecretKey) -> SecretKey {
+    key.subkey(METADATA_PURPOSE)
+}
+
+fn secrets_key(key: &SecretKey) -> SecretKey {
+    key.subkey(SECRETS_PURPOSE)
+}
+
+/// Parts of [PasswordEntry] which are only decrypted when they are used
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EntrySecrets {
+    pub(crate) password: Password,
+    pub(crate) history: Vec<PasswordHistory>,
+    pub(crate) otp: Option<Otp>,
+    pub(crate) fields: Vec<CustomField>,
+    pub(crate) kind: EntryKind,
+    pub(crate) attachments: Vec<Attachment>,
+}
+
+impl SerdeEncryptSharedKey for EntrySecrets {
+    type S = CborSerializer<Self>;
+}
+
+impl EntrySecrets {
+    /// MAC of the secrets by vault key, same for same secrets unlike their encryption
+    fn digest(&self, key: &SecretKey) -> Result<Vec<u8>, BackendError> {
+        let content =
+            bincode::serialize(self).map_err(|_| BackendError::UnableToEncrypt("secrets"))?;
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key.expose());
+        Ok(hmac::sign(&key, &content).as_ref().to_vec())
+    }
+}
+
+/// Encrypted [EntrySecrets] of an entry, along with what is shown of them without decrypting
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SealedSecrets {
+    /// Name of type of entry
+    pub(crate) kind: String,
+
+    /// Summary of type of entry, which never has the secret parts
+    pub(crate) summary: String,
+
+    /// [EntrySecrets::digest] of the encrypted secrets
+    digest: Vec<u8>,
+
+    /// Secrets encrypted by the secrets subkey
+    #[serde(with = "serde_bytes")]
+    content: Vec<u8>,
+}
+
+/// Entry as stored by vaults since version 4, its metadata having no secrets
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealedEntry {
+    metadata: PasswordEntry,
+    secrets: SealedSecrets,
+}
+
+impl SerdeEncryptSharedKey for SealedEntry {
+    type S = CborSerializer<Self>;
+}
+
+impl SealedEntry {
+    /// Encrypt the secrets of entry, entries still sealed are kept as they are
+    pub fn new(entry: &PasswordEntry, key: &SecretKey) -> Result<Self, BackendError> {
+        let mut metadata = entry.clone();
+        let secrets = match metadata.sealed.take() {
+            Some(sealed) => sealed,
+            None => {
+                let secrets = metadata.take_secrets();
+                SealedSecrets {
+                    kind: secrets.kind.name().to_owned(),
+                    summary: secrets.kind.summary(),
+                    digest: secrets.digest(key)?,
+                    content: encrypt(&secrets, &secrets_key(key), "secrets")?,
+                }
+            }
+        };
+
+        Ok(SealedEntry { metadata, secrets })
+    }
+
+    /// Metadata of entry, having its secrets still sealed
+    pub fn into_entry(self) -> PasswordEntry {
+        let mut entry = self.metadata;
+        entry.sealed = Some(self.secrets);
+        entry
+    }
+}
+
+/// Settings & entries of vaults since version 4, encrypted by the metadata subkey
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SealedPayload {
+    pub(crate) entries: Vec<SealedEntry>,
+    pub(crate) settings: VaultSettings,
+}
+
+impl SerdeEncryptSharedKey for SealedPayload {
+    type S = CborSerializer<Self>;
+}
+
+/// Decrypt the secrets of entry in place, if it is sealed
+pub fn unseal(entry: &mut PasswordEntry, key: &SecretKey) -> Result<(), BackendError> {
+    let Some(sealed) = entry.sealed.as_ref() else {
+        return Ok(());
+    };
+
+    // Entry stays sealed unless its secrets are decrypted
+    let secrets = decrypt(&sealed.content, &secrets_key(key), "secrets")?;
+    entry.put_secrets(secrets);
+    entry.sealed = None;
+
+    Ok(())
+}
+
+/// MAC of entry by vault key, which is same whether the entry is sealed or not
+pub fn digest(entry: &PasswordEntry, key: &SecretKey) -> Result<Vec<u8>, BackendError> {
+    let mut metadata = entry.clone();
+    let secrets = match metadata.sealed.take() {
+        Some(sealed) => sealed.digest,
+        None => metadata.take_secrets().digest(key)?,
+    };
+
+    let mut content =
+        bincode::serialize(&metadata).map_err(|_| BackendError::UnableToEncrypt("entry"))?;
+    content.extend_from_slice(&secrets);
+
+    let key = hmac::Key::new(hmac::HMAC_SHA256, key.expose());
+    Ok(hmac::sign(&key, &content).as_ref().to_vec())
+}
@@ -0,0 +1,295 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use rusqlite::{params, Connection, OptionalExtension};
+use serde_encrypt::{serialize::impls::CborSerializer, traits::SerdeEncryptSharedKey};
+use uuid::Uuid;
+
+use super::sealed::{digest, metadata_key, SealedEntry};
+use super::{decrypt, encrypt, BackendError, VaultBackend};
+use crate::pass::entry::PasswordEntry;
+use crate::pass::header::{VaultHeader, VaultHeaderError};
+use crate::pass::secret::SecretKey;
+use crate::pass::store::{VaultPayload, VaultSettings};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS vault (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        header BLOB NOT NULL,
+        settings BLOB NOT NULL
+    );
+
+    CREATE TABLE IF NOT EXISTS entries (
+        id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        entry BLOB NOT NULL,
+        digest BLOB
+    );
+
+    CREATE INDEX IF NOT EXISTS entries_position ON entries (position);
+";
+
+impl SerdeEncryptSharedKey for PasswordEntry {
+    type S = CborSerializer<Self>;
+}
+
+impl SerdeEncryptSharedKey for VaultSettings {
+    type S = CborSerializer<Self>;
+}
+
+/// SQLite database having each entry encrypted in its own row, so that a change of an entry
+/// only rewrites its row. Header is stored as in `passwords.db` & the settings are encrypted.
+/// Since version 4, rows & settings are encrypted by the metadata subkey, each row having the
+/// secrets of its entry sealed on their own, along with the digest of its entry.
+#[derive(Debug)]
+pub struct SqliteBackend {
+    connection: Connection,
+
+    /// Digest of the entries as they are stored, to skip rewriting unchanged ones
+    stored: HashMap<Uuid, Vec<u8>>,
+
+    /// Whether the entries table has the digest column, added on the first write otherwise
+    has_digest: bool,
+}
+
+impl SqliteBackend {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, BackendError> {
+        let path = path.as_ref();
+
+        // Database & its journal are private to the user
+        #[cfg(unix)]
+        if !path.exists() {
+            use std::os::unix::fs::OpenOptionsExt;
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)
+                .map_err(BackendError::UnableToWrite)?;
+        }
+
+        let connection = Connection::open(path).map_err(BackendError::Database)?;
+        connection
+            .execute_batch(SCHEMA)
+            .map_err(BackendError::Database)?;
+
+        let mut backend = SqliteBackend {
+            connection,
+            stored: HashMap::new(),
+            has_digest: false,
+        };
+        backend.has_digest = backend.has_digest_column()?;
+        if backend.has_digest {
+            backend.stored = backend.read_digests()?;
+        }
+
+        Ok(backend)
+    }
+
+    /// Whether the entries table is created with the digest column, i.e. not by an older version
+    fn has_digest_column(&self) -> Result<bool, BackendError> {
+        self.connection
+            .prepare("SELECT 1 FROM pragma_table_info('entries') WHERE name = 'digest'")
+            .and_then(|mut statement| statement.exists([]))
+            .map_err(BackendError::Database)
+    }
+
+    /// Digest of every row as it was written, rows of older versions have none
+    fn read_digests(&self) -> Result<HashMap<Uuid, Vec<u8>>, BackendError> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, digest FROM entries WHERE digest IS NOT NULL")
+            .map_err(BackendError::Database)?;
+        let digests = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(BackendError::Database)?
+            .map(|row| {
+                let (id, digest) = row.map_err(BackendError::Database)?;
+                let id = Uuid::parse_str(&id).map_err(|_| BackendError::InvalidId(id))?;
+                Ok((id, digest))
+            })
+            .collect();
+
+        digests
+    }
+
+    /// Start a transaction (if not started yet) to be finished by [VaultBackend::commit]
+    fn begin(&mut self) -> Result<(), BackendError> {
+        if !self.connection.is_autocommit() {
+            return Ok(());
+        }
+
+        self.connection
+            .execute_batch("BEGIN IMMEDIATE")
+            .map_err(BackendError::Database)?;
+
+        // Databases of older versions get the digest column along with the first change
+        if !self.has_digest && !self.has_digest_column()? {
+            self.connection
+                .execute_batch("ALTER TABLE entries ADD COLUMN digest BLOB")
+                .map_err(BackendError::Database)?;
+        }
+        self.has_digest = true;
+
+        Ok(())
+    }
+}
+
+impl VaultBackend for SqliteBackend {
+    fn open(&mut self) -> Result<Option<VaultHeader>, BackendError> {
+        let header = self
+            .connection
+            .query_row("SELECT header FROM vault WHERE id = 0", [], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .optional()
+            .map_err(BackendError::Database)?;
+
+        let Some(header) = header else {
+            return Ok(None);
+        };
+
+        VaultHeader::decode(&header)
+            .map_err(BackendError::InvalidHeader)?
+            .map(|(header, _)| Some(header))
+            .ok_or(BackendError::InvalidHeader(VaultHeaderError::Truncated))
+    }
+
+    fn read_entries(
+        &mut self,
+        header: &VaultHeader,
+        key: &SecretKey,
+    ) -> Result<VaultPayload, BackendError> {
+        let sealed = header.has_sealed_entries();
+        let metadata_key = metadata_key(key);
+
+        let settings = self
+            .connection
+            .query_row("SELECT settings FROM vault WHERE id = 0", [], |row| {
+                row.get::<_, Vec<u8>>(0)
+            })
+            .optional()
+            .map_err(BackendError::Database)?;
+        let settings = match settings {
+            Some(settings) if sealed => decrypt(&settings, &metadata_key, "settings")?,
+            Some(settings) => decrypt(&settings, key, "settings")?,
+            None => VaultSettings::default(),
+        };
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, entry FROM entries ORDER BY position")
+            .map_err(BackendError::Database)?;
+        let passwords = statement
+            .query_map([], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })
+            .map_err(BackendError::Database)?
+            .map(|row| {
+                let (id, entry) = row.map_err(BackendError::Database)?;
+                let entry = match sealed {
+                    true => decrypt::<SealedEntry>(&entry, &metadata_key, "entry")
+                        .map(SealedEntry::into_entry),
+                    false => decrypt::<PasswordEntry>(&entry, key, "entry"),
+                }?;
+
+                // Encrypted entry may be moved into the row of another entry
+                match entry.id.to_string() == id {
+                    true => Ok(entry),
+                    false => Err(BackendError::MismatchedId(id)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(statement);
+
+        // Rows of older versions are rewritten in the latest format, even if unchanged
+        if !sealed {
+            self.stored.clear();
+        }
+
+        Ok(VaultPayload {
+            passwords,
+            settings,
+        })
+    }
+
+    fn ids(&mut self) -> Result<Vec<Uuid>, BackendError> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id FROM entries ORDER BY position")
+            .map_err(BackendError::Database)?;
+        let ids = statement
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(BackendError::Database)?
+            .map(|id| {
+                let id = id.map_err(BackendError::Database)?;
+                Uuid::parse_str(&id).map_err(|_| BackendError::InvalidId(id))
+            })
+            .collect();
+
+        ids
+    }
+
+    fn upsert(&mut self, entry: &PasswordEntry, key: &SecretKey) -> Result<(), BackendError> {
+        // Unchanged entry under the same key is already stored as it is
+        let digest = digest(entry, key)?;
+        if self.stored.get(&entry.id) == Some(&digest) {
+            return Ok(());
+        }
+
+        let sealed = SealedEntry::new(entry, key)?;
+
+        self.begin()?;
+        self.connection
+            .execute(
+                "INSERT INTO entries (id, position, entry, digest)
+                VALUES (?1, (SELECT COALESCE(MAX(position), 0) + 1 FROM entries), ?2, ?3)
+                ON CONFLICT (id) DO UPDATE SET entry = excluded.entry, digest = excluded.digest",
+                params![
+                    entry.id.to_string(),
+                    encrypt(&sealed, &metadata_key(key), "entry")?,
+                    digest
+                ],
+            )
+            .map_err(BackendError::Database)?;
+        self.stored.insert(entry.id, digest);
+
+        Ok(())
+    }
+
+    fn delete(&mut self, id: Uuid) -> Result<(), BackendError> {
+        self.begin()?;
+        self.connection
+            .execute("DELETE FROM entries WHERE id = ?1", [id.to_string()])
+            .map_err(BackendError::Database)?;
+        self.stored.remove(&id);
+
+        Ok(())
+    }
+
+    fn commit(
+        &mut self,
+        header: &VaultHeader,
+        settings: &VaultSettings,
+        key: &SecretKey,
+    ) -> Result<(), BackendError> {
+        let mut header = header.clone();
+        header.set_key_check(key.expose());
+        let header = header
+            .encode(Vec::new())
+            .map_err(BackendError::InvalidHeader)?;
+
+        self.begin()?;
+        self.connection
+            .execute(
+                "INSERT OR REPLACE INTO vault (id, header, settings) VALUES (0, ?1, ?2)",
+                params![header, encrypt(settings, &metadata_key(key), "settings")?],
+            )
+            .map_err(BackendError::Database)?;
+        self.connection
+            .execute_batch("COMMIT")
+            .map_err(BackendError::Database)
+    }
+}
@@ -0,0 +1,629 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+
+use crate::pass::backend::{open_backend, unseal, BackendError, BackendKind};
+use crate::pass::entry::PasswordEntry;
+use crate::pass::header::{VaultHeader, VAULT_VERSION};
+use crate::pass::master::{MasterPassword, Verified};
+use crate::pass::secret::SecretKey;
+use crate::pass::store::VaultPayload;
+use crate::pass::util::{backup_path, format_size, format_time, staging_path};
+
+/// Part of vault examined by [Doctor]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Check {
+    Files,
+    Permissions,
+    Header,
+    MasterPassword,
+    Storage,
+    Decryption,
+    Deserialization,
+    Entries,
+    Backup,
+}
+
+impl Check {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Check::Files => "files",
+            Check::Permissions => "permissions",
+            Check::Header => "header",
+            Check::MasterPassword => "master password",
+            Check::Storage => "storage",
+            Check::Decryption => "decryption",
+            Check::Deserialization => "deserialization",
+            Check::Entries => "entries",
+            Check::Backup => "backup",
+        }
+    }
+
+    /// Check failed by the error of reading a vault
+    fn of(err: &BackendError) -> Self {
+        match err {
+            BackendError::UnableToRead(_) | BackendError::UnableToWrite(_) => Check::Files,
+            BackendError::InvalidHeader(_) => Check::Header,
+            BackendError::UnableToEncrypt(_) | BackendError::UnableToDecrypt(_) => {
+                Check::Decryption
+            }
+            BackendError::UnableToDeserialize(_) => Check::Deserialization,
+            BackendError::InvalidId(_)
+            | BackendError::MismatchedId(_)
+            | BackendError::Database(_)
+            | BackendError::InvalidRecord(_)
+            | BackendError::UnauthenticatedRecord(_)
+            | BackendError::TruncatedLog => Check::Storage,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Outcome of a check, with what was found
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    pub check: Check,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Entries of a vault, along with the secrets which failed to decrypt
+type ReadVault = (VaultPayload, Vec<(PasswordEntry, BackendError)>);
+
+/// Integrity checks of a vault, from its files up to its entries.
+///
+/// Files & header are checked without master password, the rest needs it. A vault is
+/// restorable if it is unreadable while its backup is readable by the master password.
+#[derive(Debug)]
+pub struct Doctor {
+    store: PathBuf,
+    legacy_master: PathBuf,
+    header: Option<VaultHeader>,
+    findings: Vec<Finding>,
+    is_unreadable: bool,
+    is_wrong_password: bool,
+    is_backup_readable: bool,
+}
+
+impl Doctor {
+    /// Doctor of the vault stored at path, `legacy_master` is its `master.dat` (if any)
+    pub fn new(store: impl Into<PathBuf>, legacy_master: impl Into<PathBuf>) -> Self {
+        Doctor {
+            store: store.into(),
+            legacy_master: legacy_master.into(),
+            header: None,
+            findings: Vec::new(),
+            is_unreadable: false,
+            is_wrong_password: false,
+            is_backup_readable: false,
+        }
+    }
+
+    pub fn findings(&self) -> &[Finding] {
+        &self.findings
+    }
+
+    /// Worst severity of all findings
+    pub fn severity(&self) -> Severity {
+        self.findings
+            .iter()
+            .map(|finding| finding.severity)
+            .max()
+            .unwrap_or(Severity::Ok)
+    }
+
+    /// Whether the master password is verified to be not of the vault
+    pub fn is_wrong_password(&self) -> bool {
+        self.is_wrong_password
+    }
+
+    /// Whether the unreadable vault can be replaced by its readable backup
+    pub fn is_restorable(&self) -> bool {
+        self.is_unreadable && self.is_backup_readable
+    }
+
+    fn report(&mut self, check: Check, severity: Severity, message: impl Into<String>) {
+        self.findings.push(Finding {
+            check,
+            severity,
+            message: message.into(),
+        });
+    }
+
+    /// Check presence & permissions of the files of vault & its header
+    pub fn check_files(&mut self) {
+        match self.store.metadata() {
+            Ok(metadata) => self.report(
+                Check::Files,
+                Severity::Ok,
+                format!("{} ({})", self.store.display(), format_size(metadata.len())),
+            ),
+            Err(err) => {
+                self.is_unreadable = true;
+                self.report(
+                    Check::Files,
+                    Severity::Error,
+                    format!("Unable to read {}: {err}", self.store.display()),
+                );
+            }
+        }
+
+        let staging = staging_path(&self.store);
+        if staging.exists() {
+            self.report(
+                Check::Files,
+                Severity::Warning,
+                format!(
+                    "{} is left by an interrupted save, it is replaced by the next save",
+                    staging.display()
+                ),
+            );
+        }
+
+        #[cfg(unix)]
+        self.check_permissions(&[
+            self.store.clone(),
+            backup_path(&self.store),
+            self.legacy_master.clone(),
+        ]);
+
+        if !self.is_unreadable {
+            self.check_header();
+        }
+    }
+
+    /// Files of vault should only be accessible by their owner
+    #[cfg(unix)]
+    fn check_permissions(&mut self, paths: &[PathBuf]) {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut is_private = true;
+        for path in paths {
+            let Ok(metadata) = path.metadata() else {
+                continue;
+            };
+
+            let mode = metadata.permissions().mode() & 0o777;
+            if mode & 0o077 != 0 {
+                is_private = false;
+                self.report(
+                    Check::Permissions,
+                    Severity::Warning,
+                    format!(
+                        "{} is accessible by other users (mode {mode:o}), fix it by `chmod 600 {}`",
+                        path.display(),
+                        path.display()
+                    ),
+                );
+            }
+        }
+
+        if is_private {
+            self.report(
+                Check::Permissions,
+                Severity::Ok,
+                "Files are only accessible by their owner",
+            );
+        }
+    }
+
+    fn check_header(&mut self) {
+        let header = BackendKind::detect(&self.store).and_then(|kind| {
+            let header = kind.open(&self.store)?.open()?;
+            Ok((kind, header))
+        });
+
+        match header {
+            Ok((kind, Some(header))) => {
+                let message = format!(
+                    "Vault of version {} stored as {}, key derived by {}",
+                    header.version,
+                    kind.name(),
+                    header.kdf
+                );
+                match header.version < VAULT_VERSION {
+                    true => self.report(
+                        Check::Header,
+                        Severity::Ok,
+                        format!("{message}, upgraded to version {VAULT_VERSION} on next unlock"),
+                    ),
+                    false => self.report(Check::Header, Severity::Ok, message),
+                }
+                self.header = Some(header);
+            }
+            Ok((_, None)) => self.report(Check::Header, Severity::Ok, "Vault has no entries yet"),
+            Err(err) => {
+                self.is_unreadable = true;
+                self.report(Check::of(&err), Severity::Error, err.to_string());
+            }
+        }
+    }
+
+    /// Check master password against the vault, then decrypt & deserialize all of its entries
+    pub fn check_vault(&mut self, master: &MasterPassword<Verified>) {
+        let Some(header) = self.header.clone() else {
+            return;
+        };
+
+        let key = match master.derive_encryption_key(&header.kdf, &header.salt) {
+            Ok(key) => key,
+            Err(err) => {
+                return self.report(Check::MasterPassword, Severity::Error, err.to_string());
+            }
+        };
+        let vault = read_vault(&self.store, &header, &key);
+
+        // Key check tells a wrong master password apart from a corrupted vault. Vault is
+        // unusable if its entries decrypt but the master password is not verified
+        let legacy = self.verify_legacy_master(master);
+        let mut is_verified = true;
+        match (
+            header.has_key_check(),
+            header.verify_key(key.expose()),
+            legacy,
+        ) {
+            (true, true, _) => self.report(
+                Check::MasterPassword,
+                Severity::Ok,
+                "Master password matches the key check of vault",
+            ),
+            (true, false, _) if vault.is_ok() => {
+                self.is_unreadable = true;
+                self.report(
+                    Check::Header,
+                    Severity::Error,
+                    "Key check of header is damaged, though entries decrypt by the master password",
+                );
+            }
+            (false, _, Some(true)) => self.report(
+                Check::MasterPassword,
+                Severity::Ok,
+                "Master password matches master.dat",
+            ),
+            (false, _, Some(false)) if vault.is_ok() => {
+                self.is_unreadable = true;
+                self.report(
+                    Check::MasterPassword,
+                    Severity::Error,
+                    "master.dat does not match the master password, though entries decrypt by it",
+                );
+            }
+            (false, _, None) => {
+                is_verified = false;
+                self.report(
+                    Check::MasterPassword,
+                    Severity::Warning,
+                    "Vault has neither key check nor master.dat, master password is only verified by decrypting",
+                );
+            }
+            _ => {
+                self.is_wrong_password = true;
+                return self.report(
+                    Check::MasterPassword,
+                    Severity::Error,
+                    "Master password is wrong",
+                );
+            }
+        }
+
+        // Left over by an interrupted migration of legacy vault, it is no longer used
+        if header.has_key_check() {
+            match legacy {
+                Some(true) => self.report(
+                    Check::MasterPassword,
+                    Severity::Warning,
+                    "master.dat is left over, the vault is verified by its key check instead",
+                ),
+                Some(false) => self.report(
+                    Check::MasterPassword,
+                    Severity::Warning,
+                    "master.dat is left over & does not match the master password of vault",
+                ),
+                None => {}
+            }
+        }
+
+        match vault {
+            Ok((payload, failures)) => {
+                self.report(
+                    Check::Decryption,
+                    Severity::Ok,
+                    format!(
+                        "Settings & {} entries are authentic",
+                        payload.passwords.len() - failures.len()
+                    ),
+                );
+                for (entry, err) in failures {
+                    self.report(
+                        Check::of(&err),
+                        Severity::Error,
+                        format!(
+                            "Secrets of entry '{}' ({}): {}",
+                            entry.service,
+                            entry.id,
+                            describe(&err)
+                        ),
+                    );
+                }
+                self.check_entries(&payload.passwords);
+            }
+            Err(err) => {
+                self.is_unreadable = true;
+                let message = match is_verified {
+                    true => describe(&err),
+                    false => format!("{err}, due to a wrong master password or corruption"),
+                };
+                self.report(Check::of(&err), Severity::Error, message);
+            }
+        }
+    }
+
+    /// Verify master password by `master.dat`, if the vault still has one
+    fn verify_legacy_master(&self, master: &MasterPassword<Verified>) -> Option<bool> {
+        let hash = std::fs::read_to_string(&self.legacy_master).ok()?;
+        let password = master.master_pass.as_ref()?;
+
+        Some(bcrypt::verify(password.expose(), hash.trim()).unwrap_or(false))
+    }
+
+    /// Report entries sharing an id, or service & username, and entries storing nothing
+    fn check_entries(&mut self, entries: &[PasswordEntry]) {
+        let count = self.findings.len();
+
+        let mut ids = HashSet::new();
+        let mut logins = HashMap::new();
+        for entry in entries {
+            let name = format!("'{}' ({})", entry.service, entry.id);
+
+            if entry.id != Uuid::nil() && !ids.insert(entry.id) {
+                self.report(
+                    Check::Entries,
+                    Severity::Warning,
+                    format!("Entry {name} has the id of another entry"),
+                );
+            }
+            match logins.get(&(&entry.service, &entry.username)) {
+                Some(first) => self.report(
+                    Check::Entries,
+                    Severity::Warning,
+                    format!("Entry {name} duplicates the service & username of {first}"),
+                ),
+                None => {
+                    logins.insert((&entry.service, &entry.username), entry.id);
+                }
+            }
+            if entry.service.trim().is_empty() {
+                self.report(
+                    Check::Entries,
+                    Severity::Warning,
+                    format!("Entry {} has no service", entry.id),
+                );
+            } else if entry.is_empty() {
+                self.report(
+                    Check::Entries,
+                    Severity::Warning,
+                    format!("Entry {name} is empty"),
+                );
+            }
+        }
+
+        if self.findings.len() == count {
+            self.report(
+                Check::Entries,
+                Severity::Ok,
+                "No duplicate or empty entries",
+            );
+        }
+    }
+
+    /// Check whether the backup is readable by the master password, to restore the vault from
+    pub fn check_backup(&mut self, master: &MasterPassword<Verified>) {
+        let backup = backup_path(&self.store);
+        let Ok(metadata) = backup.metadata() else {
+            return self.report(Check::Backup, Severity::Ok, "Vault has no backup yet");
+        };
+
+        let header = open_backend(&backup).and_then(|mut backend| backend.open());
+        let result = match header {
+            Ok(Some(header)) => master
+                .derive_encryption_key(&header.kdf, &header.salt)
+                .map_err(|err| err.to_string())
+                .and_then(
+                    |key| match !header.has_key_check() || header.verify_key(key.expose()) {
+                        true => read_vault(&backup, &header, &key).map_err(|err| err.to_string()),
+                        false => Err("it is encrypted by another master password".to_owned()),
+                    },
+                ),
+            Ok(None) => Err("it is empty".to_owned()),
+            Err(err) => Err(err.to_string()),
+        };
+
+        let saved = metadata
+            .modified()
+            .map(|time| format_time(time.into()))
+            .unwrap_or_else(|_| "unknown time".to_owned());
+        match result {
+            Ok((payload, failures)) if failures.is_empty() => {
+                self.is_backup_readable = true;
+                self.report(
+                    Check::Backup,
+                    Severity::Ok,
+                    format!(
+                        "Backup saved at {saved} has {} readable entries",
+                        payload.passwords.len()
+                    ),
+                );
+            }
+            Ok((_, failures)) => self.report(
+                Check::Backup,
+                Severity::Warning,
+                format!(
+                    "Backup saved at {saved} has {} entries failing to decrypt",
+                    failures.len()
+                ),
+            ),
+            Err(err) => self.report(
+                Check::Backup,
+                Severity::Warning,
+                format!("Backup saved at {saved} is unreadable: {err}"),
+            ),
+        }
+    }
+}
+
+/// Error of reading a vault whose key is verified, which can only be due to corruption
+fn describe(err: &BackendError) -> String {
+    match Check::of(err) {
+        Check::Decryption => format!("{err}, its authentication failed as it is corrupted"),
+        Check::Deserialization => format!("{err}, it is authentic but of an unknown format"),
+        _ => err.to_string(),
+    }
+}
+
+/// Read the entries of vault at path & decrypt each of their secrets
+fn read_vault(
+    path: &Path,
+    header: &VaultHeader,
+    key: &SecretKey,
+) -> Result<ReadVault, BackendError> {
+    let mut backend = open_backend(path)?;
+    backend.open()?;
+    let mut payload = backend.read_entries(header, key)?;
+
+    let failures = payload
+        .passwords
+        .iter_mut()
+        .filter_map(|entry| {
+            let metadata = entry.clone();
+            unseal(entry, key).err().map(|err| (metadata, err))
+        })
+        .collect();
+
+    Ok((payload, failures))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pass::kdf::Kdf;
+    use crate::pass::store::PasswordStore;
+
+    fn master(password: &str) -> MasterPassword<Verified> {
+        MasterPassword::from_pass(password).unwrap()
+    }
+
+    fn checks(doctor: &Doctor, severity: Severity) -> Vec<Check> {
+        doctor
+            .findings()
+            .iter()
+            .filter(|finding| finding.severity == severity)
+            .map(|finding| finding.check)
+            .collect()
+    }
+
+    fn examine(store: &Path, password: &str) -> Doctor {
+        let mut doctor = Doctor::new(store, store.with_file_name("master.dat"));
+        doctor.check_files();
+        doctor.check_vault(&master(password));
+        doctor.check_backup(&master(password));
+        doctor
+    }
+
+    #[test]
+    fn test_doctor() -> Result<(), crate::pass::store::PasswordStoreError> {
+        let dir = std::env::temp_dir().join(format!("pass_rs_doctor_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let store = dir.join("passwords.db");
+
+        let header = VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1 });
+        let mut manager =
+            PasswordStore::with_payload(VaultPayload::default(), &master("Test123@"), header)
+                .unwrap();
+        manager.insert_entry(PasswordEntry::new(
+            "github".to_owned(),
+            None,
+            Some("pass"),
+            None,
+        ));
+        manager.insert_entry(PasswordEntry::new("mail".to_owned(), None, Some(""), None));
+
+        // Corruption is found as the failed check of each format
+        for (kind, corrupted) in [
+            (BackendKind::File, Check::Decryption),
+            (BackendKind::Sqlite, Check::Decryption),
+            (BackendKind::Log, Check::Storage),
+        ] {
+            let mut backend = kind
+                .create(&store)
+                .map_err(crate::pass::store::PasswordStoreError::Backend)?;
+            manager.dump_into(backend.as_mut())?;
+            drop(backend);
+            std::fs::copy(&store, backup_path(&store)).unwrap();
+
+            // Healthy vault, except its empty entry
+            let doctor = examine(&store, "Test123@");
+            assert_eq!(checks(&doctor, Severity::Error), vec![]);
+            assert_eq!(checks(&doctor, Severity::Warning), vec![Check::Entries]);
+            assert!(!doctor.is_restorable());
+
+            // Wrong master password is told apart from corruption
+            let doctor = examine(&store, "Wrong123@");
+            assert!(doctor.is_wrong_password());
+            assert_eq!(
+                checks(&doctor, Severity::Error),
+                vec![Check::MasterPassword]
+            );
+
+            // Corrupted entries fail authentication, the backup is offered instead
+            corrupt(&store, kind);
+            let doctor = examine(&store, "Test123@");
+            assert!(!doctor.is_wrong_password());
+            assert_eq!(checks(&doctor, Severity::Error), vec![corrupted]);
+            assert_eq!(doctor.severity(), Severity::Error);
+            assert!(doctor.is_restorable());
+        }
+
+        // Vault missing along with its backup left, e.g. by an interrupted save
+        std::fs::remove_file(&store).unwrap();
+        let doctor = examine(&store, "Test123@");
+        assert_eq!(checks(&doctor, Severity::Error), vec![Check::Files]);
+        assert!(doctor.is_restorable());
+
+        PasswordStore::restore_backup(&store, &master("Test123@"))?;
+        let doctor = examine(&store, "Test123@");
+        assert_eq!(doctor.severity(), Severity::Warning);
+
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    /// Flip a bit of the encrypted entries (or the records) of vault
+    fn corrupt(store: &Path, kind: BackendKind) {
+        match kind {
+            BackendKind::Sqlite => {
+                let connection = rusqlite::Connection::open(store).unwrap();
+                let mut entry: Vec<u8> = connection
+                    .query_row("SELECT entry FROM entries LIMIT 1", [], |row| row.get(0))
+                    .unwrap();
+                let last = entry.len() - 1;
+                entry[last] ^= 1;
+                connection
+                    .execute("UPDATE entries SET entry = ?1", [entry])
+                    .unwrap();
+            }
+            BackendKind::File | BackendKind::Log => {
+                let mut content = std::fs::read(store).unwrap();
+                let last = content.len() - 1;
+                content[last] ^= 1;
+                std::fs::write(store, content).unwrap();
+            }
+        }
+    }
+}
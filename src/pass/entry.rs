@@ -1,24 +1,71 @@
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
+use crate::pass::attachment::Attachment;
+use crate::pass::backend::{EntrySecrets, SealedSecrets};
+use crate::pass::kind::EntryKind;
+use crate::pass::otp::Otp;
+use crate::pass::secret::{Secret, SecretBytes};
 use crate::pass::util::generate_random_password;
 use cli_table::{format::Justify, Cell};
 
-#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub struct Password {
-    password: Vec<u8>,
+    password: SecretBytes,
 }
 
 impl Password {
     pub fn new(password: Option<impl AsRef<[u8]>>) -> Self {
         let pass = match password {
-            Some(pass) => pass.as_ref().to_vec(),
-            None => generate_random_password(12).as_ref().as_bytes().to_vec(),
+            Some(pass) => pass.as_ref().to_vec().into(),
+            None => generate_random_password(12).as_ref().into(),
         };
 
         Password { password: pass }
     }
 }
 
+/// Named custom field of an entry, e.g. recovery email or 2FA backup codes
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct CustomField {
+    pub(crate) name: String,
+    pub(crate) value: Secret<String>,
+
+    /// Value of hidden field is masked while showing the entry
+    pub(crate) hidden: bool,
+}
+
+impl CustomField {
+    pub fn new(name: impl Into<String>, value: impl Into<String>, hidden: bool) -> Self {
+        CustomField {
+            name: name.into(),
+            value: value.into().into(),
+            hidden,
+        }
+    }
+
+    /// Value to show, masked if the field is hidden
+    pub fn display_value(&self) -> &str {
+        match self.hidden {
+            true => "********",
+            false => self.value.expose(),
+        }
+    }
+}
+
+/// Previous password of an entry, kept to roll back a failed rotation
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PasswordHistory {
+    password: Password,
+
+    /// When the password was set, `None` if it is older than timestamps
+    pub(crate) set_at: Option<DateTime<Utc>>,
+
+    /// When the password was replaced
+    pub(crate) replaced_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct PasswordEntry {
     /// Name of service/email/website for which password is stored
@@ -32,15 +79,116 @@ pub struct PasswordEntry {
 
     // Other details
     other: Option<String>,
+
+    /// Custom named fields
+    #[serde(default)]
+    pub(crate) fields: Vec<CustomField>,
+
+    /// TOTP/HOTP secret to compute one-time codes
+    #[serde(default)]
+    pub(crate) otp: Option<Otp>,
+
+    /// Hierarchical folder of entry, e.g. `work/aws/prod`
+    #[serde(default)]
+    pub(crate) folder: Option<String>,
+
+    /// Free-form tags, kept sorted & unique
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
+
+    /// Random identifier given at creation, nil for entries of vaults before ids (until backfilled)
+    #[serde(default)]
+    pub(crate) id: Uuid,
+
+    /// Timestamps of entry, `None` if the entry is older than timestamps
+    #[serde(default)]
+    pub(crate) created_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) modified_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) password_changed_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub(crate) last_accessed_at: Option<DateTime<Utc>>,
+
+    /// Previous passwords, latest first
+    #[serde(default)]
+    pub(crate) history: Vec<PasswordHistory>,
+
+    /// Type of entry with its specific fields, entries before types are logins
+    #[serde(default)]
+    pub(crate) kind: EntryKind,
+
+    /// Files kept along with entry
+    #[serde(default)]
+    pub(crate) attachments: Vec<Attachment>,
+
+    /// Master password is asked again before revealing or changing entry
+    #[serde(default)]
+    pub(crate) reprompt: bool,
+
+    /// Encrypted secrets (password, history, otp, fields, type & attachments) of an entry read
+    /// without them, which are left empty until [unseal](crate::pass::backend::unseal)
+    #[serde(skip)]
+    pub(crate) sealed: Option<SealedSecrets>,
+}
+
+/// Layout of [PasswordEntry] persisted in the bincode payload of legacy vaults
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub(crate) struct LegacyPasswordEntry {
+    pub(crate) service: String,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Password,
+    pub(crate) other: Option<String>,
+}
+
+/// Converted entry has no timestamps & no id yet, its id is backfilled on load
+impl From<LegacyPasswordEntry> for PasswordEntry {
+    fn from(value: LegacyPasswordEntry) -> Self {
+        PasswordEntry {
+            service: value.service,
+            username: value.username,
+            password: value.password,
+            other: value.other,
+            fields: Vec::new(),
+            otp: None,
+            folder: None,
+            tags: Vec::new(),
+            id: Uuid::nil(),
+            created_at: None,
+            modified_at: None,
+            password_changed_at: None,
+            last_accessed_at: None,
+            history: Vec::new(),
+            kind: EntryKind::Login,
+            attachments: Vec::new(),
+            reprompt: false,
+            sealed: None,
+        }
+    }
 }
 
 impl Default for PasswordEntry {
     fn default() -> Self {
+        let now = Utc::now();
         PasswordEntry {
             service: String::new(),
             username: None,
             password: Password::new(None::<&str>),
             other: None,
+            fields: Vec::new(),
+            otp: None,
+            folder: None,
+            tags: Vec::new(),
+            id: Uuid::new_v4(),
+            created_at: Some(now),
+            modified_at: Some(now),
+            password_changed_at: Some(now),
+            last_accessed_at: None,
+            history: Vec::new(),
+            kind: EntryKind::Login,
+            attachments: Vec::new(),
+            reprompt: false,
+            sealed: None,
         }
     }
 }
@@ -53,17 +201,265 @@ impl PasswordEntry {
         password: Option<impl AsRef<[u8]>>,
         other: Option<String>,
     ) -> Self {
+        let now = Utc::now();
         Self {
             service,
             username,
             password: Password::new(password),
             other,
+            fields: Vec::new(),
+            otp: None,
+            folder: None,
+            tags: Vec::new(),
+            id: Uuid::new_v4(),
+            created_at: Some(now),
+            modified_at: Some(now),
+            password_changed_at: Some(now),
+            last_accessed_at: None,
+            history: Vec::new(),
+            kind: EntryKind::Login,
+            attachments: Vec::new(),
+            reprompt: false,
+            sealed: None,
         }
     }
 
-    /// Change password in current entry
+    /// Change password in current entry, the previous password is kept in history
     pub fn change_password(&mut self, password: impl AsRef<str>) {
-        self.password = Password::new(Some(password.as_ref()));
+        self.replace_password(Password::new(Some(password.as_ref())));
+    }
+
+    fn replace_password(&mut self, password: Password) {
+        if password == self.password {
+            return;
+        }
+
+        let now = Utc::now();
+        let previous = std::mem::replace(&mut self.password, password);
+        self.history.insert(
+            0,
+            PasswordHistory {
+                password: previous,
+                set_at: self.password_changed_at,
+                replaced_at: now,
+            },
+        );
+        self.password_changed_at = Some(now);
+    }
+
+    /// Make the `n`th (starting from 1, latest first) previous password current again.
+    /// The current password goes into history, so restoring can be undone.
+    /// Returns `false` if there is no such password or it is the current one.
+    pub fn restore_password(&mut self, n: usize) -> bool {
+        let is_current = |n: usize| self.history[n - 1].password == self.password;
+        if n == 0 || n > self.history.len() || is_current(n) {
+            return false;
+        }
+
+        let restored = self.history.remove(n - 1);
+        self.replace_password(restored.password);
+        true
+    }
+
+    /// Forget the previous passwords beyond `depth`
+    pub fn truncate_history(&mut self, depth: usize) {
+        self.history.truncate(depth);
+    }
+
+    /// Record that the entry is changed
+    pub fn mark_modified(&mut self) {
+        self.modified_at = Some(Utc::now());
+    }
+
+    /// Record that the secrets of entry are used
+    pub fn mark_accessed(&mut self) {
+        self.last_accessed_at = Some(Utc::now());
+    }
+
+    /// Change username in current entry
+    pub fn change_username(&mut self, username: Option<String>) {
+        self.username = username;
+    }
+
+    /// Change notes in current entry
+    pub fn change_notes(&mut self, notes: Option<String>) {
+        self.other = notes;
+    }
+
+    /// Custom field of entry by its name
+    pub fn field(&self, name: impl AsRef<str>) -> Option<&CustomField> {
+        debug_assert!(!self.is_sealed(), "Fields of a sealed entry are read");
+        self.fields.iter().find(|field| field.name == name.as_ref())
+    }
+
+    /// Add a custom field, replacing the existing field of same name
+    pub fn set_field(&mut self, field: CustomField) {
+        match self
+            .fields
+            .iter_mut()
+            .find(|current| current.name == field.name)
+        {
+            Some(current) => *current = field,
+            None => self.fields.push(field),
+        }
+    }
+
+    /// Remove the custom field by its name, returns whether it existed
+    pub fn remove_field(&mut self, name: impl AsRef<str>) -> bool {
+        let count = self.fields.len();
+        self.fields.retain(|field| field.name != name.as_ref());
+        count != self.fields.len()
+    }
+
+    pub fn attachment(&self, name: impl AsRef<str>) -> Option<&Attachment> {
+        debug_assert!(!self.is_sealed(), "Attachments of a sealed entry are read");
+        self.attachments
+            .iter()
+            .find(|attachment| attachment.name == name.as_ref())
+    }
+
+    /// Add an attachment, replacing the existing attachment of same name
+    pub fn set_attachment(&mut self, attachment: Attachment) {
+        match self
+            .attachments
+            .iter_mut()
+            .find(|current| current.name == attachment.name)
+        {
+            Some(current) => *current = attachment,
+            None => self.attachments.push(attachment),
+        }
+    }
+
+    /// Protect (or unprotect) entry by asking master password again before using it
+    pub fn set_reprompt(&mut self, reprompt: bool) {
+        self.reprompt = reprompt;
+    }
+
+    /// Change the type of entry along with its specific fields
+    pub fn change_kind(&mut self, kind: EntryKind) {
+        self.kind = kind;
+    }
+
+    /// Change (or remove) the one-time password secret of entry
+    pub fn change_otp(&mut self, otp: Option<Otp>) {
+        self.otp = otp;
+    }
+
+    /// Move entry into the folder, `None` (or an empty path) moves it to the top level
+    pub fn change_folder(&mut self, folder: Option<impl AsRef<str>>) {
+        self.folder = folder.and_then(normalize_folder);
+    }
+
+    /// Whether entry is in the folder or in any of its subfolders
+    pub fn in_folder(&self, folder: impl AsRef<str>) -> bool {
+        let Some(folder) = normalize_folder(folder) else {
+            return true;
+        };
+
+        match &self.folder {
+            Some(current) => current
+                .strip_prefix(&folder)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/')),
+            None => false,
+        }
+    }
+
+    /// Add a tag, returns whether it was not already there
+    pub fn add_tag(&mut self, tag: impl AsRef<str>) -> bool {
+        let tag = tag.as_ref().trim();
+        if tag.is_empty() {
+            return false;
+        }
+
+        match self
+            .tags
+            .binary_search_by(|current| current.as_str().cmp(tag))
+        {
+            Ok(_) => false,
+            Err(index) => {
+                self.tags.insert(index, tag.to_owned());
+                true
+            }
+        }
+    }
+
+    /// Remove a tag, returns whether it existed
+    pub fn remove_tag(&mut self, tag: impl AsRef<str>) -> bool {
+        let count = self.tags.len();
+        self.tags.retain(|current| current != tag.as_ref().trim());
+        count != self.tags.len()
+    }
+
+    pub fn has_tag(&self, tag: impl AsRef<str>) -> bool {
+        self.tags
+            .iter()
+            .any(|current| current == tag.as_ref().trim())
+    }
+
+    /// Give a new id to entry if it has none, returns whether id was given
+    pub fn backfill_id(&mut self) -> bool {
+        match self.id.is_nil() {
+            true => {
+                self.id = Uuid::new_v4();
+                true
+            }
+            false => false,
+        }
+    }
+
+    /// Move the secrets out of entry, leaving them empty
+    pub(crate) fn take_secrets(&mut self) -> EntrySecrets {
+        EntrySecrets {
+            password: std::mem::take(&mut self.password),
+            history: std::mem::take(&mut self.history),
+            otp: self.otp.take(),
+            fields: std::mem::take(&mut self.fields),
+            kind: std::mem::take(&mut self.kind),
+            attachments: std::mem::take(&mut self.attachments),
+        }
+    }
+
+    /// Put the secrets taken by [PasswordEntry::take_secrets] back into entry
+    pub(crate) fn put_secrets(&mut self, secrets: EntrySecrets) {
+        self.password = secrets.password;
+        self.history = secrets.history;
+        self.otp = secrets.otp;
+        self.fields = secrets.fields;
+        self.kind = secrets.kind;
+        self.attachments = secrets.attachments;
+    }
+
+    /// Whether the secrets of entry are not decrypted yet
+    pub fn is_sealed(&self) -> bool {
+        self.sealed.is_some()
+    }
+
+    /// Name of type of entry, known without its secrets
+    pub fn kind_name(&self) -> &str {
+        match &self.sealed {
+            Some(sealed) => &sealed.kind,
+            None => self.kind.name(),
+        }
+    }
+
+    /// Summary of type of entry, known without its secrets
+    pub fn kind_summary(&self) -> String {
+        match &self.sealed {
+            Some(sealed) => sealed.summary.clone(),
+            None => self.kind.summary(),
+        }
+    }
+
+    /// Whether the entry stores nothing but its service, i.e. a login without any detail
+    pub fn is_empty(&self) -> bool {
+        matches!(self.kind, EntryKind::Login)
+            && !self.is_sealed()
+            && self.username.as_deref().is_none_or(str::is_empty)
+            && self.password.password.expose().is_empty()
+            && self.other.as_deref().is_none_or(str::is_empty)
+            && self.fields.is_empty()
+            && self.otp.is_none()
+            && self.attachments.is_empty()
     }
 
     /// Create table for [PasswordEntry]
@@ -71,19 +467,45 @@ impl PasswordEntry {
         let service = self.service.clone();
         let username = self.username.clone().unwrap_or("None".to_string());
         let notes = self.other.clone().unwrap_or("None".to_string());
+        let folder = self.folder.clone().unwrap_or("/".to_string());
+        let tags = self.tags.join(", ");
+        let kind = self.kind_name();
+        let summary = self.kind_summary();
 
         vec![
             service.cell().justify(Justify::Center),
+            kind.cell().justify(Justify::Center),
             username.cell().justify(Justify::Center),
             notes.cell().justify(Justify::Center),
+            folder.cell().justify(Justify::Center),
+            tags.cell().justify(Justify::Center),
+            summary.cell().justify(Justify::Center),
         ]
     }
 
-    pub fn get_pass_str(&self) -> String {
-        String::from_utf8(self.password.password.clone()).expect("Unable to convert u8 to str")
+    /// Password of entry, which must be unsealed first
+    pub fn get_pass_str(&self) -> &str {
+        debug_assert!(!self.is_sealed(), "Password of a sealed entry is read");
+        self.password
+            .password
+            .expose_str()
+            .expect("Unable to convert u8 to str")
     }
 }
 
+/// Folder path without empty components & surrounding slashes, `None` for the top level
+fn normalize_folder(folder: impl AsRef<str>) -> Option<String> {
+    let folder = folder
+        .as_ref()
+        .split('/')
+        .map(str::trim)
+        .filter(|component| !component.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+
+    (!folder.is_empty()).then_some(folder)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -96,4 +518,120 @@ mod test {
         println!("\nPassword Input: ");
         dbg!(Password::new(Some("PasswordInputed")));
     }
+
+    #[test]
+    fn custom_fields() {
+        let mut entry = PasswordEntry::new("pass 1".to_owned(), None, None::<&str>, None);
+        entry.set_field(CustomField::new("recovery email", "me@example.com", false));
+        entry.set_field(CustomField::new("backup codes", "1234 5678", true));
+
+        assert_eq!(
+            entry.field("recovery email").unwrap().display_value(),
+            "me@example.com"
+        );
+        assert_eq!(
+            entry.field("backup codes").unwrap().display_value(),
+            "********"
+        );
+        assert_eq!(
+            entry.field("backup codes").unwrap().value.expose(),
+            "1234 5678"
+        );
+
+        // Field of same name is replaced
+        entry.set_field(CustomField::new("backup codes", "0000", true));
+        assert_eq!(entry.fields.len(), 2);
+        assert_eq!(entry.field("backup codes").unwrap().value.expose(), "0000");
+
+        assert!(entry.remove_field("backup codes"));
+        assert!(!entry.remove_field("backup codes"));
+        assert!(entry.field("backup codes").is_none());
+    }
+
+    #[test]
+    fn folders_and_tags() {
+        let mut entry = PasswordEntry::new("aws".to_owned(), None, None::<&str>, None);
+        assert!(entry.in_folder("/"));
+        assert!(!entry.in_folder("work"));
+
+        entry.change_folder(Some("/work//aws/prod/"));
+        assert_eq!(entry.folder.as_deref(), Some("work/aws/prod"));
+        assert!(entry.in_folder("work"));
+        assert!(entry.in_folder("work/aws/"));
+        assert!(entry.in_folder("work/aws/prod"));
+        assert!(!entry.in_folder("work/aw"));
+        assert!(!entry.in_folder("personal"));
+
+        entry.change_folder(Some("/"));
+        assert_eq!(entry.folder, None);
+
+        assert!(entry.add_tag("prod"));
+        assert!(entry.add_tag(" critical "));
+        assert!(!entry.add_tag("prod"));
+        assert!(!entry.add_tag(""));
+        assert_eq!(entry.tags, ["critical", "prod"]);
+        assert!(entry.has_tag("prod"));
+
+        assert!(entry.remove_tag("prod"));
+        assert!(!entry.remove_tag("prod"));
+        assert!(!entry.has_tag("prod"));
+    }
+
+    #[test]
+    fn timestamps() {
+        let mut entry = PasswordEntry::new("pass 1".to_owned(), None, Some("first"), None);
+        let created_at = entry.created_at.expect("New entry has creation time");
+        assert_eq!(entry.modified_at, Some(created_at));
+        assert_eq!(entry.password_changed_at, Some(created_at));
+        assert_eq!(entry.last_accessed_at, None);
+
+        // Same password is not a change
+        entry.change_password("first");
+        assert_eq!(entry.password_changed_at, Some(created_at));
+
+        entry.change_password("second");
+        assert!(entry.password_changed_at > Some(created_at));
+
+        entry.mark_accessed();
+        assert!(entry.last_accessed_at >= entry.password_changed_at);
+        assert_eq!(entry.modified_at, Some(created_at));
+    }
+
+    #[test]
+    fn password_history() {
+        let mut entry = PasswordEntry::new("pass 1".to_owned(), None, Some("first"), None);
+        entry.change_password("second");
+        entry.change_password("third");
+        entry.change_password("third");
+
+        assert_eq!(entry.history.len(), 2);
+        assert_eq!(entry.history[0].password, Password::new(Some("second")));
+        assert_eq!(entry.history[1].password, Password::new(Some("first")));
+        assert_eq!(entry.history[1].set_at, entry.created_at);
+
+        // Rolled back password is current again & the replaced one is kept
+        assert!(entry.restore_password(2));
+        assert_eq!(entry.get_pass_str(), "first");
+        assert_eq!(entry.history[0].password, Password::new(Some("third")));
+        assert_eq!(entry.history[1].password, Password::new(Some("second")));
+
+        assert!(!entry.restore_password(0));
+        assert!(!entry.restore_password(3));
+
+        entry.truncate_history(1);
+        assert_eq!(entry.history.len(), 1);
+    }
+
+    #[test]
+    fn restore_current_password() {
+        let mut entry = PasswordEntry::new("pass 1".to_owned(), None, Some("first"), None);
+        entry.change_password("second");
+        entry.change_password("first");
+
+        // Restoring the password which is current again is not a change
+        assert!(!entry.restore_password(2));
+        assert_eq!(entry.get_pass_str(), "first");
+        assert_eq!(entry.history.len(), 2);
+        assert_eq!(entry.history[1].password, Password::new(Some("first")));
+    }
 }
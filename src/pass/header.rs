@@ -0,0 +1,265 @@
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+use crate::pass::kdf::{Kdf, KdfError};
+use crate::pass::util::get_random_salt;
+
+/// Magic bytes at the start of every `passwords.db` having a [VaultHeader]
+pub const VAULT_MAGIC: &[u8; 6] = b"PASSRS";
+
+/// Latest version of the on-disk vault format
+///
+/// - 0: No header, constant salt & master password persisted in payload
+/// - 1: Header with random salt, master password persisted in payload
+/// - 2: Header with random salt, only entries & metadata persisted in payload
+/// - 3: Header with key check, master password is verified without `master.dat`
+/// - 4: Metadata & secrets of entries encrypted separately, by subkeys of the vault key
+pub const VAULT_VERSION: u16 = 4;
+
+/// Message authenticated by the vault key to verify the master password
+const KEY_CHECK_MESSAGE: &[u8] = b"pass_rs vault key check";
+
+/// Salt used by vaults created before the [VaultHeader] was introduced
+pub const LEGACY_SALT: &[u8] = b"Salt";
+
+#[derive(Debug, thiserror::Error)]
+pub enum VaultHeaderError {
+    #[error("Vault header is truncated")]
+    Truncated,
+
+    #[error("Unable to decode vault header: {0}")]
+    UnableToDecode(String),
+
+    #[error("Unable to encode vault header: {0}")]
+    UnableToEncode(String),
+
+    #[error("Unsupported vault version {0}")]
+    UnsupportedVersion(u16),
+
+    #[error("{0}")]
+    InvalidKdf(#[source] KdfError),
+}
+
+/// Plain-text header stored in front of the encrypted entries of `passwords.db`
+///
+/// Layout of file: | VAULT_MAGIC | header length (u32 LE) | header (bincode) | encrypted entries |
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct VaultHeader {
+    /// Version of the vault format
+    pub version: u16,
+
+    /// Key derivation function & its parameters used for the vault key
+    pub kdf: Kdf,
+
+    /// Random salt of this vault
+    pub salt: Vec<u8>,
+
+    /// HMAC of [KEY_CHECK_MESSAGE] by the vault key, empty until the vault is dumped
+    pub key_check: Vec<u8>,
+}
+
+/// Header of vault versions 1 & 2, without key check
+#[derive(Deserialize)]
+struct HeaderV2 {
+    version: u16,
+    kdf: Kdf,
+    salt: Vec<u8>,
+}
+
+impl From<HeaderV2> for VaultHeader {
+    fn from(value: HeaderV2) -> Self {
+        VaultHeader {
+            version: value.version,
+            kdf: value.kdf,
+            salt: value.salt,
+            key_check: Vec::new(),
+        }
+    }
+}
+
+impl Default for VaultHeader {
+    fn default() -> Self {
+        VaultHeader::new()
+    }
+}
+
+impl VaultHeader {
+    /// Create a header for a new vault with a fresh random salt
+    pub fn new() -> Self {
+        VaultHeader {
+            version: VAULT_VERSION,
+            kdf: Kdf::default(),
+            salt: get_random_salt().to_vec(),
+            key_check: Vec::new(),
+        }
+    }
+
+    /// Header matching the layout of vaults created before headers existed
+    pub fn legacy() -> Self {
+        VaultHeader {
+            version: 0,
+            kdf: Kdf::legacy(),
+            salt: LEGACY_SALT.to_vec(),
+            key_check: Vec::new(),
+        }
+    }
+
+    /// Header with a fresh random salt for the given key derivation function
+    pub fn with_kdf(kdf: Kdf) -> Self {
+        VaultHeader {
+            kdf,
+            ..VaultHeader::new()
+        }
+    }
+
+    /// Whether the encrypted payload also holds the master password
+    pub fn has_legacy_payload(&self) -> bool {
+        self.version < 2
+    }
+
+    /// Whether the secrets of entries are encrypted apart from their metadata
+    pub fn has_sealed_entries(&self) -> bool {
+        self.version >= 4
+    }
+
+    /// Whether the master password can be verified by this header
+    pub fn has_key_check(&self) -> bool {
+        !self.key_check.is_empty()
+    }
+
+    /// Set key check for the key derived from master password
+    pub fn set_key_check(&mut self, key: &[u8; 32]) {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+        self.key_check = hmac::sign(&key, KEY_CHECK_MESSAGE).as_ref().to_vec();
+    }
+
+    /// Verify the key derived from master password against the key check (in constant time)
+    pub fn verify_key(&self, key: &[u8; 32]) -> bool {
+        let key = hmac::Key::new(hmac::HMAC_SHA256, key);
+        self.has_key_check() && hmac::verify(&key, KEY_CHECK_MESSAGE, &self.key_check).is_ok()
+    }
+
+    /// Upgrade the header to the latest version of vault format
+    pub fn migrate(&mut self) {
+        if self.version == 0 {
+            // Replace the constant salt by a random one
+            *self = VaultHeader::new();
+        }
+
+        self.version = VAULT_VERSION;
+    }
+
+    /// Prepend the header to the encrypted entries
+    pub fn encode(&self, payload: impl AsRef<[u8]>) -> Result<Vec<u8>, VaultHeaderError> {
+        let header = bincode::serialize(self)
+            .map_err(|e| VaultHeaderError::UnableToEncode(e.to_string()))?;
+
+        let mut content = Vec::with_capacity(VAULT_MAGIC.len() + 4 + header.len());
+        content.extend_from_slice(VAULT_MAGIC);
+        content.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        content.extend_from_slice(&header);
+        content.extend_from_slice(payload.as_ref());
+
+        Ok(content)
+    }
+
+    /// Split the content of `passwords.db` into header & encrypted entries.
+    /// Returns `None` if the content has no header, i.e. a legacy vault.
+    pub fn decode(content: &[u8]) -> Result<Option<(VaultHeader, &[u8])>, VaultHeaderError> {
+        let Some(rest) = content.strip_prefix(VAULT_MAGIC.as_slice()) else {
+            return Ok(None);
+        };
+
+        let (len, rest) = rest
+            .split_first_chunk::<4>()
+            .ok_or(VaultHeaderError::Truncated)?;
+        let len = u32::from_le_bytes(*len) as usize;
+
+        if rest.len() < len {
+            return Err(VaultHeaderError::Truncated);
+        }
+        let (header, payload) = rest.split_at(len);
+
+        // Version is always the first field, so it decides the layout of rest of header
+        let (version, _) = header
+            .split_first_chunk::<2>()
+            .ok_or(VaultHeaderError::Truncated)?;
+
+        let decode_error = |e: bincode::Error| VaultHeaderError::UnableToDecode(e.to_string());
+        let header: VaultHeader = match u16::from_le_bytes(*version) {
+            1 | 2 => bincode::deserialize::<HeaderV2>(header)
+                .map_err(decode_error)?
+                .into(),
+            3..=VAULT_VERSION => bincode::deserialize(header).map_err(decode_error)?,
+            version => return Err(VaultHeaderError::UnsupportedVersion(version)),
+        };
+
+        header
+            .kdf
+            .validate()
+            .map_err(VaultHeaderError::InvalidKdf)?;
+
+        Ok(Some((header, payload)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn header_roundtrip() -> Result<(), VaultHeaderError> {
+        let header = VaultHeader::new();
+        let content = header.encode("encrypted entries")?;
+
+        let (decoded, payload) = VaultHeader::decode(&content)?.expect("Header must exist");
+        assert_eq!(decoded, header);
+        assert_eq!(payload, b"encrypted entries");
+
+        // Content without magic bytes is a legacy vault
+        assert!(VaultHeader::decode(b"encrypted entries")?.is_none());
+
+        // Every vault gets its own salt
+        assert_ne!(VaultHeader::new().salt, header.salt);
+
+        Ok(())
+    }
+
+    #[test]
+    fn key_check() {
+        let mut header = VaultHeader::new();
+        assert!(!header.verify_key(&[1; 32]));
+
+        header.set_key_check(&[1; 32]);
+        assert!(header.verify_key(&[1; 32]));
+        assert!(!header.verify_key(&[2; 32]));
+    }
+
+    #[test]
+    fn decode_v2_header() -> Result<(), VaultHeaderError> {
+        #[derive(Serialize)]
+        struct HeaderV2 {
+            version: u16,
+            kdf: Kdf,
+            salt: Vec<u8>,
+        }
+
+        let header = bincode::serialize(&HeaderV2 {
+            version: 2,
+            kdf: Kdf::legacy(),
+            salt: b"random salt".to_vec(),
+        })
+        .unwrap();
+
+        let mut content = VAULT_MAGIC.to_vec();
+        content.extend_from_slice(&(header.len() as u32).to_le_bytes());
+        content.extend_from_slice(&header);
+
+        let (decoded, _) = VaultHeader::decode(&content)?.expect("Header must exist");
+        assert_eq!(decoded.version, 2);
+        assert_eq!(decoded.salt, b"random salt");
+        assert!(!decoded.has_key_check());
+
+        Ok(())
+    }
+}
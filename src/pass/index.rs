@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+
+use uuid::Uuid;
+
+use crate::pass::entry::PasswordEntry;
+
+/// Positions of the entries of vault by their id & service, to find them without scanning.
+///
+/// Positions are only valid for the entries it is built from, so it has to be rebuilt once
+/// entries are removed, reordered or their service is changed.
+#[derive(Debug, Default, Clone)]
+pub struct EntryIndex {
+    ids: HashMap<Uuid, usize>,
+    services: HashMap<String, Vec<usize>>,
+}
+
+impl EntryIndex {
+    pub fn new(entries: &[PasswordEntry]) -> Self {
+        let mut index = EntryIndex::default();
+        entries
+            .iter()
+            .enumerate()
+            .for_each(|(position, entry)| index.insert(position, entry));
+        index
+    }
+
+    /// Index the entry pushed at position
+    pub fn insert(&mut self, position: usize, entry: &PasswordEntry) {
+        self.ids.insert(entry.id, position);
+        self.services
+            .entry(entry.service.clone())
+            .or_default()
+            .push(position);
+    }
+
+    /// Position of entry by its id
+    pub fn position(&self, id: Uuid) -> Option<usize> {
+        self.ids.get(&id).copied()
+    }
+
+    /// Positions of entries of the service, in order of vault
+    pub fn service(&self, service: impl AsRef<str>) -> &[usize] {
+        self.services
+            .get(service.as_ref())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Distinct services along with positions of their entries
+    pub fn services(&self) -> impl Iterator<Item = (&str, &[usize])> {
+        self.services
+            .iter()
+            .map(|(service, positions)| (service.as_str(), positions.as_slice()))
+    }
+}
@@ -0,0 +1,316 @@
+use std::num::NonZeroU32;
+
+use ring::pbkdf2;
+use serde::{Deserialize, Serialize};
+
+use crate::pass::secret::SecretKey;
+
+/// Iterations used by PBKDF2 for vaults created before Argon2id support
+pub const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// Memory cost (in KiB) used by Argon2id for newly created vaults
+pub const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+
+/// Iterations used by Argon2id for newly created vaults
+pub const ARGON2_ITERATIONS: u32 = 3;
+
+/// Parallelism used by Argon2id for newly created vaults
+pub const ARGON2_PARALLELISM: u32 = 4;
+
+/// Largest iterations of PBKDF2, header is read before it is authenticated so costs are capped
+pub const MAX_PBKDF2_ITERATIONS: u32 = 100_000_000;
+
+/// Largest memory cost (in KiB) of Argon2id, i.e. 4 GiB
+pub const MAX_ARGON2_MEMORY_KIB: u32 = 4 * 1024 * 1024;
+
+/// Largest iterations of Argon2id
+pub const MAX_ARGON2_ITERATIONS: u32 = 100;
+
+/// Largest parallelism of Argon2id
+pub const MAX_ARGON2_PARALLELISM: u32 = 64;
+
+#[derive(Debug, thiserror::Error)]
+pub enum KdfError {
+    #[error("Invalid KDF parameters: {0}")]
+    InvalidParams(String),
+
+    #[error("Unable to derive key: {0}")]
+    UnableToDerive(String),
+}
+
+/// Key derivation function & its parameters used to derive the vault key
+///
+/// NOTE: New variants must only be appended, as the index of variant is stored in vault header
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Kdf {
+    Pbkdf2HmacSha256 {
+        iterations: u32,
+    },
+    Argon2id {
+        /// Memory cost in KiB
+        memory_kib: u32,
+        iterations: u32,
+        parallelism: u32,
+    },
+}
+
+impl Default for Kdf {
+    fn default() -> Self {
+        Kdf::Argon2id {
+            memory_kib: ARGON2_MEMORY_KIB,
+            iterations: ARGON2_ITERATIONS,
+            parallelism: ARGON2_PARALLELISM,
+        }
+    }
+}
+
+impl std::fmt::Display for Kdf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kdf::Pbkdf2HmacSha256 { iterations } => {
+                write!(f, "{} (iterations: {iterations})", self.name())
+            }
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => write!(
+                f,
+                "{} (memory: {memory_kib} KiB, iterations: {iterations}, parallelism: {parallelism})",
+                self.name()
+            ),
+        }
+    }
+}
+
+impl Kdf {
+    /// PBKDF2 with the parameters used before Argon2id support
+    pub fn legacy() -> Self {
+        Kdf::Pbkdf2HmacSha256 {
+            iterations: PBKDF2_ITERATIONS,
+        }
+    }
+
+    /// Name of the key derivation function
+    pub fn name(&self) -> &'static str {
+        match self {
+            Kdf::Pbkdf2HmacSha256 { .. } => "PBKDF2-HMAC-SHA256",
+            Kdf::Argon2id { .. } => "Argon2id",
+        }
+    }
+
+    /// Check the parameters are usable for deriving a key, without running out of memory or
+    /// time on parameters of a tampered header
+    pub fn validate(&self) -> Result<(), KdfError> {
+        match self {
+            Kdf::Pbkdf2HmacSha256 { iterations } => match *iterations {
+                0 => Err(KdfError::InvalidParams(
+                    "Iterations of PBKDF2 can not be zero".to_string(),
+                )),
+                iterations if iterations > MAX_PBKDF2_ITERATIONS => Err(KdfError::InvalidParams(
+                    format!("Iterations of PBKDF2 can not exceed {MAX_PBKDF2_ITERATIONS}"),
+                )),
+                _ => Ok(()),
+            },
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => {
+                let limits = [
+                    ("Memory cost", *memory_kib, MAX_ARGON2_MEMORY_KIB),
+                    ("Iterations", *iterations, MAX_ARGON2_ITERATIONS),
+                    ("Parallelism", *parallelism, MAX_ARGON2_PARALLELISM),
+                ];
+                if let Some((name, _, limit)) =
+                    limits.iter().find(|(_, value, limit)| value > limit)
+                {
+                    return Err(KdfError::InvalidParams(format!(
+                        "{name} of Argon2id can not exceed {limit}"
+                    )));
+                }
+
+                self.argon2_params().map(|_| ())
+            }
+        }
+    }
+
+    /// Whether keys derived by this KDF are cheaper to brute force than by the other one.
+    /// PBKDF2 is weaker than any Argon2id, which is weaker than PBKDF2 only below the
+    /// parameters of new vaults
+    pub fn is_weaker_than(&self, other: &Kdf) -> bool {
+        match (self, other) {
+            (
+                Kdf::Pbkdf2HmacSha256 { iterations },
+                Kdf::Pbkdf2HmacSha256 {
+                    iterations: current,
+                },
+            ) => iterations < current,
+            (Kdf::Pbkdf2HmacSha256 { .. }, Kdf::Argon2id { .. }) => true,
+            (
+                Kdf::Argon2id {
+                    memory_kib,
+                    iterations,
+                    ..
+                },
+                Kdf::Argon2id {
+                    memory_kib: current_memory,
+                    iterations: current_iterations,
+                    ..
+                },
+            ) => memory_kib < current_memory || iterations < current_iterations,
+            (
+                Kdf::Argon2id {
+                    memory_kib,
+                    iterations,
+                    ..
+                },
+                Kdf::Pbkdf2HmacSha256 { .. },
+            ) => *memory_kib < ARGON2_MEMORY_KIB || *iterations < ARGON2_ITERATIONS,
+        }
+    }
+
+    /// Derive a 32 bytes key from password & salt
+    pub fn derive_key(
+        &self,
+        password: impl AsRef<[u8]>,
+        salt: impl AsRef<[u8]>,
+    ) -> Result<SecretKey, KdfError> {
+        // Key is derived in place, so it is never copied out of the locked memory
+        let mut key = SecretKey::zeroed();
+
+        match self {
+            Kdf::Pbkdf2HmacSha256 { iterations } => {
+                let iterations = NonZeroU32::new(*iterations).ok_or_else(|| {
+                    KdfError::InvalidParams("Iterations of PBKDF2 can not be zero".to_string())
+                })?;
+
+                pbkdf2::derive(
+                    pbkdf2::PBKDF2_HMAC_SHA256,
+                    iterations,
+                    salt.as_ref(),
+                    password.as_ref(),
+                    key.expose_mut(),
+                );
+            }
+            Kdf::Argon2id { .. } => {
+                argon2::Argon2::new(
+                    argon2::Algorithm::Argon2id,
+                    argon2::Version::V0x13,
+                    self.argon2_params()?,
+                )
+                .hash_password_into(password.as_ref(), salt.as_ref(), key.expose_mut())
+                .map_err(|e| KdfError::UnableToDerive(e.to_string()))?;
+            }
+        };
+
+        Ok(key)
+    }
+
+    fn argon2_params(&self) -> Result<argon2::Params, KdfError> {
+        match self {
+            Kdf::Argon2id {
+                memory_kib,
+                iterations,
+                parallelism,
+            } => argon2::Params::new(*memory_kib, *iterations, *parallelism, Some(32))
+                .map_err(|e| KdfError::InvalidParams(e.to_string())),
+            _ => Err(KdfError::InvalidParams(format!(
+                "{} has no Argon2 parameters",
+                self.name()
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn derive_key() -> Result<(), KdfError> {
+        let pbkdf2 = Kdf::Pbkdf2HmacSha256 { iterations: 1000 };
+        let argon2 = Kdf::Argon2id {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        };
+
+        for kdf in [pbkdf2, argon2] {
+            let key = kdf.derive_key("Test123@", "random salt")?;
+
+            // Same password & salt always derives the same key
+            assert_eq!(
+                key.expose(),
+                kdf.derive_key("Test123@", "random salt")?.expose()
+            );
+            assert_ne!(
+                key.expose(),
+                kdf.derive_key("Test123@", "other salt")?.expose()
+            );
+            assert_ne!(
+                key.expose(),
+                kdf.derive_key("Test1234@", "random salt")?.expose()
+            );
+        }
+
+        assert_ne!(
+            pbkdf2.derive_key("Test123@", "random salt")?.expose(),
+            argon2.derive_key("Test123@", "random salt")?.expose()
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_params() {
+        assert!(Kdf::Pbkdf2HmacSha256 { iterations: 0 }.validate().is_err());
+        assert!(Kdf::Argon2id {
+            memory_kib: 1,
+            iterations: 1,
+            parallelism: 1
+        }
+        .validate()
+        .is_err());
+        assert!(Kdf::default().validate().is_ok());
+
+        // Costs are capped, e.g. of a tampered header
+        assert!(Kdf::Pbkdf2HmacSha256 {
+            iterations: u32::MAX
+        }
+        .validate()
+        .is_err());
+        assert!(Kdf::Argon2id {
+            memory_kib: MAX_ARGON2_MEMORY_KIB + 1,
+            iterations: ARGON2_ITERATIONS,
+            parallelism: ARGON2_PARALLELISM
+        }
+        .validate()
+        .is_err());
+        assert!(Kdf::Argon2id {
+            memory_kib: ARGON2_MEMORY_KIB,
+            iterations: u32::MAX,
+            parallelism: ARGON2_PARALLELISM
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn weaker_kdf() {
+        let weak_argon2 = Kdf::Argon2id {
+            memory_kib: 1024,
+            iterations: 1,
+            parallelism: 1,
+        };
+
+        assert!(Kdf::Pbkdf2HmacSha256 { iterations: 1 }.is_weaker_than(&Kdf::legacy()));
+        assert!(Kdf::legacy().is_weaker_than(&Kdf::default()));
+        assert!(weak_argon2.is_weaker_than(&Kdf::default()));
+        assert!(weak_argon2.is_weaker_than(&Kdf::legacy()));
+
+        assert!(!Kdf::default().is_weaker_than(&Kdf::legacy()));
+        assert!(!Kdf::default().is_weaker_than(&Kdf::default()));
+        assert!(!Kdf::default().is_weaker_than(&weak_argon2));
+    }
+}
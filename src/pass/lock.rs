@@ -0,0 +1,452 @@
+use std::fmt::Display;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use crate::pass::util::format_time;
+
+/// Seconds to wait for the lock of vault held by another process, unless configured
+pub const LOCK_TIMEOUT_SECS: u64 = 10;
+
+/// Interval of retrying to take the lock while waiting for it
+const LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Lock file of the exclusive lock held by this process, its record is cleared by [exit]
+static HELD: Mutex<Option<File>> = Mutex::new(None);
+
+/// Whether this run only shares the vault, set by [VaultLock::hold]
+static IS_SHARED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, thiserror::Error)]
+pub enum LockError {
+    #[error("Unable to open lock of vault: {0}")]
+    UnableToOpen(#[source] std::io::Error),
+
+    #[error("Unable to lock vault: {0}")]
+    UnableToLock(#[source] std::io::Error),
+
+    #[error("Vault is in use by {}", .0.as_ref().map_or("another process".to_owned(), ToString::to_string))]
+    Locked(Option<LockHolder>),
+}
+
+/// How the vault is used while holding its lock
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Any number of processes only reading the vault
+    Shared,
+
+    /// One process changing the vault
+    Exclusive,
+}
+
+/// Process which took the exclusive lock, as recorded in the lock file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockHolder {
+    pub pid: u32,
+    pub since: DateTime<Utc>,
+}
+
+impl LockHolder {
+    fn current() -> Self {
+        LockHolder {
+            pid: std::process::id(),
+            since: Utc::now(),
+        }
+    }
+
+    fn parse(record: &str) -> Option<Self> {
+        let (pid, since) = record.trim().split_once(' ')?;
+
+        Some(LockHolder {
+            pid: pid.parse().ok()?,
+            since: DateTime::parse_from_rfc3339(since).ok()?.to_utc(),
+        })
+    }
+
+    fn record(&self) -> String {
+        format!("{} {}\n", self.pid, self.since.to_rfc3339())
+    }
+}
+
+impl Display for LockHolder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "process {} since {}", self.pid, format_time(self.since))
+    }
+}
+
+/// Advisory lock of a vault, shared by readers & exclusive for a writer, released on drop.
+///
+/// Locks are taken by the OS, so a process exiting in any way releases its lock. The holder
+/// of exclusive lock is recorded in the lock file & cleared on release, a record found by
+/// the next writer is left by a process which exited while changing the vault.
+#[derive(Debug)]
+pub struct VaultLock {
+    file: File,
+    mode: LockMode,
+    stale: Option<LockHolder>,
+}
+
+impl VaultLock {
+    /// Take the lock, waiting up to the timeout if another process holds it.
+    /// Zero timeout fails fast without waiting.
+    pub fn acquire(
+        path: impl AsRef<Path>,
+        mode: LockMode,
+        timeout: Duration,
+    ) -> Result<Self, LockError> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .map_err(LockError::UnableToOpen)?;
+
+        let deadline = Instant::now().checked_add(timeout);
+        let mut waiting = false;
+        loop {
+            let locked = match mode {
+                LockMode::Shared => file.try_lock_shared(),
+                LockMode::Exclusive => file.try_lock(),
+            };
+
+            match locked {
+                Ok(()) => break,
+                Err(TryLockError::WouldBlock)
+                    if deadline.is_none_or(|deadline| Instant::now() < deadline) =>
+                {
+                    if !waiting {
+                        colour::e_yellow_ln!("Waiting for another process using the vault...");
+                        waiting = true;
+                    }
+                    std::thread::sleep(LOCK_RETRY_INTERVAL);
+                }
+                Err(TryLockError::WouldBlock) => {
+                    return Err(LockError::Locked(Self::read_holder(&mut file)))
+                }
+                Err(TryLockError::Error(err)) => return Err(LockError::UnableToLock(err)),
+            }
+        }
+
+        let stale = match mode {
+            LockMode::Shared => None,
+            LockMode::Exclusive => {
+                let stale = Self::read_holder(&mut file);
+                Self::write_holder(&mut file, &LockHolder::current())
+                    .map_err(LockError::UnableToLock)?;
+                *HELD.lock().unwrap_or_else(|err| err.into_inner()) = file.try_clone().ok();
+                stale
+            }
+        };
+
+        Ok(VaultLock { file, mode, stale })
+    }
+
+    /// Holder recorded by a process which exited without releasing the lock
+    pub fn stale(&self) -> Option<&LockHolder> {
+        self.stale.as_ref()
+    }
+
+    /// Keep the lock for the rest of the run, so that [is_shared] tells how the vault is used
+    pub fn hold(self) -> Self {
+        IS_SHARED.store(self.mode == LockMode::Shared, Ordering::Relaxed);
+        self
+    }
+
+    fn read_holder(file: &mut File) -> Option<LockHolder> {
+        let mut record = String::new();
+        file.seek(SeekFrom::Start(0)).ok()?;
+        file.read_to_string(&mut record).ok()?;
+
+        LockHolder::parse(&record)
+    }
+
+    fn write_holder(file: &mut File, holder: &LockHolder) -> std::io::Result<()> {
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(holder.record().as_bytes())?;
+        file.sync_data()
+    }
+}
+
+impl Drop for VaultLock {
+    fn drop(&mut self) {
+        if self.mode == LockMode::Exclusive {
+            let _ = self.file.set_len(0);
+            HELD.lock().unwrap_or_else(|err| err.into_inner()).take();
+        }
+        let _ = self.file.unlock();
+    }
+}
+
+/// Whether this run holds only a shared lock of vault, so it must leave the vault as it is,
+/// e.g. an outdated vault is migrated by the next run changing it
+pub fn is_shared() -> bool {
+    IS_SHARED.load(Ordering::Relaxed)
+}
+
+/// [std::process::exit] clearing the record of held lock first, as destructors are not run.
+/// Otherwise the next process finds the record & takes it as left by a crashed process
+pub fn exit(code: i32) -> ! {
+    if let Some(file) = HELD.lock().unwrap_or_else(|err| err.into_inner()).take() {
+        let _ = file.set_len(0);
+    }
+    std::process::exit(code)
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader};
+    use std::path::PathBuf;
+    use std::process::{Child, Command, Stdio};
+
+    use super::*;
+    use serde_encrypt::{shared_key::SharedKey, traits::SerdeEncryptSharedKey};
+
+    use crate::pass::backend::{LegacyMasterPassword, LegacyVaultPayload};
+    use crate::pass::entry::{LegacyPasswordEntry, Password, PasswordEntry};
+    use crate::pass::header::VaultHeader;
+    use crate::pass::kdf::Kdf;
+    use crate::pass::master::{MasterPassword, Verified};
+    use crate::pass::store::{PasswordStore, VaultPayload};
+    use crate::pass::util::backup_path;
+
+    const WRITERS: usize = 4;
+
+    const ENTRIES_PER_WRITER: usize = 5;
+
+    /// Vault given to the spawned processes
+    const TEST_VAULT_VAR: &str = "PASS_RS_TEST_LOCK_VAULT";
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pass_rs_lock_{name}_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Run an ignored test of this module in a new process of the test binary
+    fn spawn_test(name: &str, dir: &Path) -> Child {
+        Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--ignored",
+                "--exact",
+                "--nocapture",
+                "--test-threads=1",
+                &format!("pass::lock::test::{name}"),
+            ])
+            .env(TEST_VAULT_VAR, dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap()
+    }
+
+    fn master() -> MasterPassword<Verified> {
+        MasterPassword::from_pass("Test123@").unwrap()
+    }
+
+    #[test]
+    fn test_shared_and_exclusive() {
+        let dir = test_dir("modes");
+        let path = dir.join("vault.lock");
+
+        let first = VaultLock::acquire(&path, LockMode::Shared, Duration::ZERO).unwrap();
+        let second = VaultLock::acquire(&path, LockMode::Shared, Duration::ZERO).unwrap();
+        assert!(matches!(
+            VaultLock::acquire(&path, LockMode::Exclusive, Duration::from_millis(100)),
+            Err(LockError::Locked(None))
+        ));
+
+        drop((first, second));
+        let writer = VaultLock::acquire(&path, LockMode::Exclusive, Duration::ZERO).unwrap();
+        assert!(writer.stale().is_none());
+        assert!(matches!(
+            VaultLock::acquire(&path, LockMode::Shared, Duration::ZERO),
+            Err(LockError::Locked(Some(LockHolder { pid, .. }))) if pid == std::process::id()
+        ));
+
+        // Released lock leaves no record behind
+        drop(writer);
+        assert_eq!(std::fs::read(&path).unwrap(), b"");
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_writers() -> Result<(), crate::pass::store::PasswordStoreError> {
+        let dir = test_dir("writers");
+        let store = dir.join("passwords.db");
+
+        // Key is derived without cost, as every writer loads the vault once per entry
+        let header = VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1 });
+        PasswordStore::with_payload(VaultPayload::default(), &master(), header)?.dump(&store)?;
+
+        let writers = (0..WRITERS)
+            .map(|_| spawn_test("concurrent_writer", &dir))
+            .collect::<Vec<_>>();
+        for mut writer in writers {
+            assert!(writer.wait().unwrap().success());
+        }
+
+        // Every entry added by every writer is kept
+        let manager = PasswordStore::load(&store, &master())?;
+        assert_eq!(
+            manager.payload.passwords.len(),
+            WRITERS * ENTRIES_PER_WRITER
+        );
+
+        let _ = std::fs::remove_file(backup_path(&store));
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    /// Add entries to the vault one by one, as done by `pass_rs add`
+    #[test]
+    #[ignore = "spawned by test_concurrent_writers"]
+    fn concurrent_writer() -> Result<(), crate::pass::store::PasswordStoreError> {
+        let dir = PathBuf::from(std::env::var(TEST_VAULT_VAR).unwrap());
+        let store = dir.join("passwords.db");
+
+        for n in 0..ENTRIES_PER_WRITER {
+            let _lock =
+                VaultLock::acquire(dir.join("vault.lock"), LockMode::Exclusive, Duration::MAX)
+                    .unwrap();
+
+            let mut manager = PasswordStore::load(&store, &master())?;
+            manager.push_entry(PasswordEntry::new(
+                format!("{} {n}", std::process::id()),
+                None,
+                Some("password".to_owned()),
+                None,
+            ));
+            manager.dump(&store)?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_readers() -> Result<(), crate::pass::store::PasswordStoreError> {
+        let dir = test_dir("readers");
+        let store = dir.join("passwords.db");
+
+        // Legacy vault is outdated, so it is migrated by the first run changing it
+        let payload = LegacyVaultPayload {
+            passwords: vec![LegacyPasswordEntry {
+                service: "pass 1".to_owned(),
+                username: None,
+                password: Password::new(Some("EntryPassword")),
+                other: None,
+            }],
+            master_password: LegacyMasterPassword {
+                master_pass: None,
+                hash: None,
+            },
+        };
+        let header = VaultHeader::legacy();
+        let key = master()
+            .derive_encryption_key(&header.kdf, &header.salt)
+            .unwrap();
+        let legacy = payload
+            .encrypt(&SharedKey::new(*key.expose()))
+            .unwrap()
+            .serialize();
+        std::fs::write(&store, &legacy).unwrap();
+
+        // Readers share the lock with each other & with this process
+        let _lock =
+            VaultLock::acquire(dir.join("vault.lock"), LockMode::Shared, Duration::ZERO).unwrap();
+        let readers = (0..2)
+            .map(|_| spawn_test("shared_reader", &dir))
+            .collect::<Vec<_>>();
+        for mut reader in readers {
+            assert!(reader.wait().unwrap().success());
+        }
+
+        // Vault is left as it is
+        assert_eq!(std::fs::read(&store).unwrap(), legacy);
+        assert!(!backup_path(&store).exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+
+    /// List the entries of vault under a shared lock, as done by `pass_rs list`
+    #[test]
+    #[ignore = "spawned by test_shared_readers"]
+    fn shared_reader() -> Result<(), crate::pass::store::PasswordStoreError> {
+        let dir = PathBuf::from(std::env::var(TEST_VAULT_VAR).unwrap());
+        let _lock = VaultLock::acquire(dir.join("vault.lock"), LockMode::Shared, Duration::ZERO)
+            .unwrap()
+            .hold();
+
+        let manager = PasswordStore::load_metadata(dir.join("passwords.db"), &master())?;
+        assert_eq!(manager.get("pass 1").len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_stale_lock() {
+        let dir = test_dir("stale");
+        let path = dir.join("vault.lock");
+
+        // Wait till the holder has taken the lock, its output follows the name of test
+        let mut holder = spawn_test("lock_holder", &dir);
+        let locked = BufReader::new(holder.stdout.take().unwrap())
+            .lines()
+            .map_while(Result::ok)
+            .any(|line| line.ends_with("locked"));
+        assert!(locked);
+
+        let pid = holder.id();
+        assert!(matches!(
+            VaultLock::acquire(&path, LockMode::Exclusive, Duration::ZERO),
+            Err(LockError::Locked(Some(LockHolder { pid: holder, .. }))) if holder == pid
+        ));
+
+        // Lock of a killed process is released, its record is detected as stale
+        holder.kill().unwrap();
+        holder.wait().unwrap();
+
+        let lock = VaultLock::acquire(&path, LockMode::Exclusive, Duration::ZERO).unwrap();
+        assert_eq!(lock.stale().map(|holder| holder.pid), Some(pid));
+        drop(lock);
+
+        // Exiting on purpose is not taken as a crash
+        assert!(spawn_test("lock_exiter", &dir).wait().unwrap().success());
+        let lock = VaultLock::acquire(&path, LockMode::Exclusive, Duration::ZERO).unwrap();
+        assert!(lock.stale().is_none());
+
+        drop(lock);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Take the lock & never release it, until killed
+    #[test]
+    #[ignore = "spawned by test_stale_lock"]
+    fn lock_holder() {
+        let dir = PathBuf::from(std::env::var(TEST_VAULT_VAR).unwrap());
+        let _lock = VaultLock::acquire(dir.join("vault.lock"), LockMode::Exclusive, Duration::ZERO)
+            .unwrap();
+
+        println!("locked");
+        std::thread::sleep(Duration::from_secs(60));
+    }
+
+    /// Take the lock & exit without dropping it
+    #[test]
+    #[ignore = "spawned by test_stale_lock"]
+    fn lock_exiter() {
+        let dir = PathBuf::from(std::env::var(TEST_VAULT_VAR).unwrap());
+        let _lock = VaultLock::acquire(dir.join("vault.lock"), LockMode::Exclusive, Duration::ZERO)
+            .unwrap();
+
+        exit(0);
+    }
+}
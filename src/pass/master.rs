@@ -1,22 +1,26 @@
-use std::{io::Write, marker::PhantomData, num::NonZeroU32, string::FromUtf8Error};
+use std::{io::Write, marker::PhantomData, string::FromUtf8Error};
 
-use anyhow::Context;
 use once_cell::sync::Lazy;
-use ring::pbkdf2;
-use serde::{Deserialize, Serialize};
 
+#[cfg(unix)]
+use super::agent::Agent;
 use super::{
-    store::{PasswordStore, PASS_ENTRY_STORE},
-    util::password_input,
+    header::VaultHeader,
+    kdf::{Kdf, KdfError},
+    lock,
+    secret::{SecretBytes, SecretKey},
+    store::{PasswordStore, PasswordStoreError, PASS_ENTRY_STORE},
+    util::{backup_path, password_input},
 };
 
-use crate::pass::util::{input_master_pass, password_hash, PASS_DIR_PATH, XDG_BASE};
+use crate::pass::util::{input_master_pass, PASS_DIR_PATH};
+use crate::pass::vault::Vault;
 
-pub static MASTER_PASS_STORE: Lazy<std::path::PathBuf> = Lazy::new(|| {
-    XDG_BASE
-        .place_state_file("master.dat")
-        .expect("Unable to place master.dat file")
-}); // $HOME/.local/state/.pass/Master.dat
+pub static MASTER_PASS_STORE: Lazy<std::path::PathBuf> =
+    Lazy::new(|| Vault::selected().master_path()); // $HOME/.local/state/.pass/Master.dat, only read to migrate legacy vaults
+
+/// Env variable having the path (or file descriptor) to read master password from
+pub const MASTER_FILE_ENV: &str = "PASS_RS_MASTER_FILE";
 
 #[derive(Debug, thiserror::Error)]
 pub enum MasterPasswordError {
@@ -49,6 +53,112 @@ pub enum MasterPasswordError {
 
     #[error("Master password is not strong enough")]
     PassNotStrong,
+
+    #[error("Unable to derive key from master password: {0}")]
+    UnableToDeriveKey(#[source] KdfError),
+
+    #[error("Unable to access vault: {0}")]
+    UnableToAccessVault(#[source] PasswordStoreError),
+
+    #[error("Unable to read master password from {0}: {1}")]
+    UnableToReadPassword(String, #[source] std::io::Error),
+
+    #[error("Password command failed: {0}")]
+    PasswordCommandFailed(String),
+}
+
+/// Where the master password is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PasswordSource {
+    /// Prompt on the terminal, allowing retries
+    Prompt,
+
+    /// First line of stdin
+    Stdin,
+
+    /// Content of a file, or of a file descriptor if given a number
+    File(String),
+
+    /// Stdout of a shell command
+    Command(String),
+}
+
+impl PasswordSource {
+    /// Source given by flags, else by `PASS_RS_MASTER_FILE`, else prompt
+    pub fn new(stdin: bool, command: Option<String>) -> Self {
+        match (stdin, command) {
+            (true, _) => PasswordSource::Stdin,
+            (false, Some(command)) => PasswordSource::Command(command),
+            (false, None) => match std::env::var(MASTER_FILE_ENV) {
+                Ok(file) if !file.is_empty() => PasswordSource::File(file),
+                _ => PasswordSource::Prompt,
+            },
+        }
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        *self == PasswordSource::Prompt
+    }
+
+    /// Read master password from the source
+    pub fn read(&self) -> Result<SecretBytes, MasterPasswordError> {
+        let mut password = match self {
+            PasswordSource::Prompt => password_input("Enter your master password: ")
+                .map_err(|_| MasterPasswordError::UnableToReadFromConsole)?,
+
+            PasswordSource::Stdin => {
+                let mut line = String::new();
+                std::io::stdin()
+                    .read_line(&mut line)
+                    .map_err(|e| MasterPasswordError::UnableToReadPassword("stdin".into(), e))?;
+                line.into_bytes()
+            }
+
+            PasswordSource::File(file) => {
+                // A number is a file descriptor inherited from the parent process
+                let path = match file.parse::<u32>() {
+                    Ok(fd) => format!("/dev/fd/{fd}"),
+                    Err(_) => file.clone(),
+                };
+                std::fs::read(&path)
+                    .map_err(|e| MasterPasswordError::UnableToReadPassword(path, e))?
+            }
+
+            PasswordSource::Command(command) => {
+                #[cfg(unix)]
+                let output = std::process::Command::new("sh")
+                    .args(["-c", command])
+                    .stderr(std::process::Stdio::inherit())
+                    .output();
+                #[cfg(windows)]
+                let output = std::process::Command::new("cmd")
+                    .args(["/C", command])
+                    .stderr(std::process::Stdio::inherit())
+                    .output();
+
+                let output = output.map_err(|e| {
+                    MasterPasswordError::UnableToReadPassword(format!("`{command}`"), e)
+                })?;
+                if !output.status.success() {
+                    return Err(MasterPasswordError::PasswordCommandFailed(format!(
+                        "`{command}` exited with {}",
+                        output.status
+                    )));
+                }
+                output.stdout
+            }
+        };
+
+        // Only the trailing newline is stripped, any other whitespace is part of password
+        if password.ends_with(b"\n") {
+            password.pop();
+            if password.ends_with(b"\r") {
+                password.pop();
+            }
+        }
+
+        Ok(password.into())
+    }
 }
 
 /// Default state of [MasterPassword]
@@ -66,12 +176,35 @@ pub struct UnVerified;
 #[derive(Debug, Clone, Copy)]
 pub struct Verified;
 
-#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+/// Vault key derived from master password, along with the KDF & salt it is derived by
+#[derive(Debug, Clone)]
+pub struct VaultKey {
+    kdf: Kdf,
+    salt: Vec<u8>,
+    key: SecretKey,
+}
+
+impl VaultKey {
+    pub fn new(kdf: Kdf, salt: impl AsRef<[u8]>, key: SecretKey) -> Self {
+        VaultKey {
+            kdf,
+            salt: salt.as_ref().to_vec(),
+            key,
+        }
+    }
+}
+
+/// Master password (or the vault key derived from it) in the states of unlocking, never cloned
+#[derive(Debug, Default)]
 pub struct MasterPassword<State = UnInit> {
     /// Master password
-    pub master_pass: Option<Vec<u8>>,
-    /// Master password hashed
+    pub master_pass: Option<SecretBytes>,
+    /// Bcrypt hash of master password, only for vaults still having legacy `master.dat`
     pub hash: Option<String>,
+    /// Header of vault, having the key check to verify master password
+    pub header: Option<VaultHeader>,
+    /// Vault key already derived from master password, e.g. by the agent
+    pub key: Option<VaultKey>,
     /// [MasterPassword] state
     pub state: PhantomData<State>,
 }
@@ -81,19 +214,13 @@ impl Default for MasterPassword<Init> {
         Self {
             master_pass: Default::default(),
             hash: Default::default(),
+            header: Default::default(),
+            key: Default::default(),
             state: PhantomData,
         }
     }
 }
 
-impl MasterPassword {
-    pub fn dump(hash_pass: impl AsRef<str>) -> Result<(), MasterPasswordError> {
-        std::fs::write(MASTER_PASS_STORE.to_path_buf(), hash_pass.as_ref())
-            .map_err(MasterPasswordError::UnableToWriteFile)?;
-
-        Ok(())
-    }
-}
 impl MasterPassword<UnInit> {
     pub fn new() -> MasterPassword<Init> {
         MasterPassword::default()
@@ -105,7 +232,7 @@ impl MasterPassword<UnInit> {
     }
 
     pub fn is_initialised() -> bool {
-        MASTER_PASS_STORE.exists()
+        PASS_ENTRY_STORE.exists() || MASTER_PASS_STORE.exists()
     }
 }
 
@@ -116,19 +243,44 @@ impl MasterPassword<Init> {
         let master_pass = input_master_pass("Enter master password: ")
             .map_err(|_| MasterPasswordError::UnableToReadFromConsole)?;
 
+        let master = MasterPassword::from_pass(master_pass)?;
+
+        // Empty vault having the key check of master password
+        PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), &master)
+            .and_then(|store| store.dump(PASS_ENTRY_STORE.to_path_buf()))
+            .map_err(MasterPasswordError::UnableToAccessVault)?;
+
         colour::green_ln!("Pass initialised successfully");
 
-        MasterPassword::from_pass(master_pass)
+        Ok(master)
     }
 
     /// Convert initialised state to unverified state
     pub fn load(self) -> Result<MasterPassword<UnVerified>, MasterPasswordError> {
         handle_master_not_initialised();
 
-        // Read hashed password from DB and set to object
+        // Read header having key check from vault, or from its backup if vault is corrupted
+        let header = PasswordStore::read_header(PASS_ENTRY_STORE.to_path_buf())
+            .or_else(
+                |err| match backup_path(PASS_ENTRY_STORE.as_path()).exists() {
+                    true => PasswordStore::read_header(backup_path(PASS_ENTRY_STORE.as_path())),
+                    false => Err(err),
+                },
+            )
+            .map_err(MasterPasswordError::UnableToAccessVault)?
+            .filter(|header| header.has_key_check());
+
+        // Fallback to hashed password of legacy `master.dat`
+        let hash = match header {
+            Some(_) => None,
+            None => Some(self.get_master_hash_from_db()?),
+        };
+
         Ok(MasterPassword {
-            hash: Some(self.get_master_hash_from_db()?),
             master_pass: None,
+            hash,
+            header,
+            key: None,
             state: PhantomData::<UnVerified>,
         })
     }
@@ -141,23 +293,29 @@ impl MasterPassword<Init> {
         .map_err(MasterPasswordError::UnableToConvert)
     }
 
-    /// Create a verified [MasterPassword] and set master & its hash in it
-    pub fn from_pass<P>(password: P) -> Result<MasterPassword<Verified>, MasterPasswordError>
-    where
-        P: AsRef<[u8]>,
-    {
-        let hashed_password = password_hash(&password)
-            .map_err(|_| MasterPasswordError::BcryptError("Unable to hash".to_string()))?;
-
-        // Store hashed master password
-        MasterPassword::dump(&hashed_password)?;
-
+    /// Create a verified [MasterPassword] from password
+    pub fn from_pass(
+        password: impl Into<SecretBytes>,
+    ) -> Result<MasterPassword<Verified>, MasterPasswordError> {
         Ok(MasterPassword {
-            master_pass: Some(password.as_ref().to_vec()),
-            hash: Some(hashed_password),
+            master_pass: Some(password.into()),
+            hash: None,
+            header: None,
+            key: None,
             state: PhantomData::<Verified>,
         })
     }
+
+    /// Create a verified [MasterPassword] from the vault key already derived from it
+    pub fn from_key(key: VaultKey) -> MasterPassword<Verified> {
+        MasterPassword {
+            master_pass: None,
+            hash: None,
+            header: None,
+            key: Some(key),
+            state: PhantomData::<Verified>,
+        }
+    }
 }
 
 impl MasterPassword<UnVerified> {
@@ -165,40 +323,35 @@ impl MasterPassword<UnVerified> {
     pub fn prompt(&mut self) -> Result<(), MasterPasswordError> {
         std::io::stdout().flush().map_err(MasterPasswordError::IO)?; // Flush the output to ensure prompt is displayed
 
-        // Taking input master password
-        let prompt_master_password = password_input("Enter your master password: ")
-            .map_err(|_| MasterPasswordError::UnableToReadFromConsole)?;
-
-        // Storing prompt password to object
-        self.master_pass = Some(prompt_master_password);
-        Ok(())
+        // Taking input master password & storing it to object
+        self.read_from(&PasswordSource::Prompt)
     }
 
-    fn get_hash(&self) -> String {
-        assert!(self.hash.is_some());
-        self.hash
-            .clone()
-            .expect("Unreachable: Master password hash can not be empty")
+    /// Takes master password from the given source
+    pub fn read_from(&mut self, source: &PasswordSource) -> Result<(), MasterPasswordError> {
+        self.master_pass = Some(source.read()?);
+        Ok(())
     }
 
-    fn get_pass(&self) -> Vec<u8> {
-        assert!(self.master_pass.is_some());
+    fn get_pass(&self) -> &SecretBytes {
         self.master_pass
-            .clone()
+            .as_ref()
             .expect("Unreachable: Master password can not be empty")
     }
 
-    pub fn get_master_str(&self) -> String {
-        assert!(self.master_pass.is_some());
-
-        let master_vec = self
-            .master_pass
-            .clone()
-            .expect("Unreachable: Master password can not be empty");
-
-        String::from_utf8(master_vec)
-            .context("Unable to convert utf8 to String")
-            .unwrap()
+    /// Unlock by the vault key held by agent, if agent is running & has the key of vault
+    #[cfg(unix)]
+    pub fn unlock_by_agent(&self) -> Option<MasterPassword<Verified>> {
+        let header = self.header.as_ref()?;
+        let key = Agent::default()
+            .get_key(PASS_ENTRY_STORE.as_path())
+            .ok()
+            .flatten()?;
+
+        // Key is stale if master password or KDF has been changed since handing it over
+        header
+            .verify_key(key.expose())
+            .then(|| MasterPassword::from_key(VaultKey::new(header.kdf, &header.salt, key)))
     }
 
     // Unlock the master password
@@ -206,17 +359,47 @@ impl MasterPassword<UnVerified> {
         std::io::stdout().flush().map_err(MasterPasswordError::IO)?; // Flush the output to ensure prompt is displayed
 
         let password = self.get_pass();
-        let hash = self.get_hash();
-
-        bcrypt::verify(&password, &hash)
-            .map(|verification_status| {
-                verification_status.then_some(MasterPassword {
-                    master_pass: Some(password),
-                    hash: Some(hash),
-                    state: PhantomData::<Verified>,
-                })
-            })
-            .map_err(|e| MasterPasswordError::BcryptError(e.to_string()))
+        let mut verified = MasterPassword {
+            master_pass: Some(password.clone()),
+            hash: None,
+            header: None,
+            key: None,
+            state: PhantomData::<Verified>,
+        };
+
+        match (&self.header, &self.hash) {
+            // Derive the vault key & authenticate it by key check of header, the key is kept so
+            // that loading the vault does not derive it again
+            (Some(header), _) => {
+                let key = verified
+                    .derive_encryption_key(&header.kdf, &header.salt)
+                    .map_err(MasterPasswordError::UnableToDeriveKey)?;
+                if !header.verify_key(key.expose()) {
+                    return Ok(None);
+                }
+
+                verified.key = Some(VaultKey::new(header.kdf, &header.salt, key));
+                Ok(Some(verified))
+            }
+
+            // Legacy vault verified by bcrypt hash of `master.dat`
+            (None, Some(hash)) => {
+                let is_verified = bcrypt::verify(password.expose(), hash)
+                    .map_err(|e| MasterPasswordError::BcryptError(e.to_string()))?;
+
+                if !is_verified {
+                    return Ok(None);
+                }
+
+                // Legacy vault is migrated by the next run changing it, if this one only reads it
+                if !lock::is_shared() {
+                    verified.migrate_legacy_master()?;
+                }
+                Ok(Some(verified))
+            }
+
+            (None, None) => Err(MasterPasswordError::WrongMasterPassword),
+        }
     }
 }
 
@@ -225,101 +408,74 @@ impl MasterPassword<Verified> {
         let prompt_new_master = input_master_pass("Enter new master password: ")
             .map_err(|_| MasterPasswordError::UnableToReadFromConsole)?;
 
-        // Storing old master pass for later
-        let old_master = self.get_master_str();
+        let new_master = MasterPassword::from_pass(prompt_new_master)?;
 
-        self.set_new_master(prompt_new_master)?;
+        // Re-encrypting contents over new master pass (which also updates the key check),
+        // vault stays encrypted by old master pass on failure
+        PasswordStore::rekey(PASS_ENTRY_STORE.as_path(), self, &new_master)
+            .map_err(MasterPasswordError::UnableToAccessVault)?;
+        *self = new_master;
 
-        // Re-encrypting contents over new master pass
-        self.re_encrypt_contents(old_master)
-            .expect("Unable to re-encrypt entries");
-
-        // Store hash of changed master pass
-        MasterPassword::dump(self.get_hash())?;
         colour::green_ln!("Master password changed successfully");
 
         Ok(())
     }
 
-    pub fn set_new_master(
-        &mut self,
-        new_master: impl AsRef<str>,
-    ) -> Result<(), MasterPasswordError> {
-        // Hash the password and set it to the self
-        let hash = password_hash(new_master.as_ref())
-            .map_err(|_| MasterPasswordError::BcryptError("Unable to hash".to_string()))?;
-
-        self.master_pass = Some(new_master.as_ref().as_bytes().to_vec());
-        self.hash = Some(hash.clone());
-        Ok(())
-    }
-
-    pub fn re_encrypt_contents<P>(&self, old_master_password: P) -> anyhow::Result<()>
-    where
-        P: AsRef<[u8]>,
-    {
-        if PASS_ENTRY_STORE.exists() {
-            let master_pass = MasterPassword::from_pass(old_master_password)?;
-
-            // Load all entries form db by old master
-            let mut storage = PasswordStore::load(PASS_ENTRY_STORE.to_path_buf(), master_pass)?;
-
-            storage.change_master(self.clone());
+    /// Move verification of master password from legacy `master.dat` into the key check of vault
+    fn migrate_legacy_master(&self) -> Result<(), MasterPasswordError> {
+        // Loading also migrates the vault to the latest format
+        PasswordStore::new(PASS_ENTRY_STORE.to_path_buf(), self)
+            .and_then(|store| store.dump(PASS_ENTRY_STORE.to_path_buf()))
+            .map_err(MasterPasswordError::UnableToAccessVault)?;
 
-            // Again encrypt entries with new pass
-            storage.dump(PASS_ENTRY_STORE.to_path_buf())?;
-        }
+        std::fs::remove_file(MASTER_PASS_STORE.as_path())
+            .map_err(MasterPasswordError::UnableToWriteFile)?;
 
         Ok(())
     }
 
-    pub fn derive_encryption_key(&self, salt: impl AsRef<[u8]>) -> [u8; 32] {
-        let mut encryption_key = [0_u8; 32];
+    pub fn derive_encryption_key(
+        &self,
+        kdf: &Kdf,
+        salt: impl AsRef<[u8]>,
+    ) -> Result<SecretKey, KdfError> {
+        // Reuse the key if it is already derived for the same KDF & salt
+        if let Some(key) = self
+            .key
+            .as_ref()
+            .filter(|key| key.kdf == *kdf && key.salt == salt.as_ref())
+        {
+            return Ok(key.key.clone());
+        }
 
         // Deriving a encryption key using master pass
-        pbkdf2::derive(
-            pbkdf2::PBKDF2_HMAC_SHA256,
-            NonZeroU32::new(600_000).unwrap(),
-            salt.as_ref(),
-            self.master_pass.as_ref().unwrap(),
-            &mut encryption_key,
-        );
-
-        encryption_key
-    }
-
-    pub fn get_master_str(&self) -> String {
-        assert!(self.master_pass.is_some());
-
-        let master_vec = self
-            .master_pass
-            .clone()
-            .expect("Unreachable: Master password can not be empty");
-
-        String::from_utf8(master_vec)
-            .context("Unable to convert utf8 to String")
-            .unwrap()
-    }
-
-    fn get_hash(&self) -> String {
-        assert!(self.hash.is_some());
-        self.hash
-            .clone()
-            .expect("Unreachable: Master password hash can not be empty")
+        let master_pass = self.master_pass.as_ref().ok_or_else(|| {
+            KdfError::UnableToDerive("Master password is required to derive a new key".to_owned())
+        })?;
+        kdf.derive_key(master_pass.expose(), salt)
     }
 }
 
 pub fn handle_master_not_initialised() {
     if !MasterPassword::is_initialised() {
-        colour::e_red_ln!(">> Pass is not initialised");
-        println!("Usage: pass_rs init");
-        std::process::exit(0);
+        let vault = Vault::selected();
+        match vault.is_default() {
+            true => {
+                colour::e_red_ln!(">> Pass is not initialised");
+                println!("Usage: pass_rs init");
+            }
+            false => {
+                colour::e_red_ln!(">> Vault '{}' is not initialised", vault.name());
+                println!("Usage: pass_rs --vault {} init", vault.name());
+            }
+        }
+        lock::exit(0);
     }
 }
 
 #[cfg(test)]
 mod test {
-    use super::MasterPassword;
+    use super::*;
 
     #[test]
     #[ignore = "unimplemented"]
@@ -327,4 +483,56 @@ mod test {
         let _master = MasterPassword::new();
         // let _unlocked = master.unwrap().verify();
     }
+
+    #[test]
+    fn verify_by_key_check() -> Result<(), MasterPasswordError> {
+        let mut header = VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 });
+        let key = MasterPassword::from_pass("Test123@")?
+            .derive_encryption_key(&header.kdf, &header.salt)
+            .map_err(MasterPasswordError::UnableToDeriveKey)?;
+        header.set_key_check(key.expose());
+
+        let mut master = MasterPassword {
+            master_pass: None,
+            hash: None,
+            header: Some(header),
+            key: None,
+            state: PhantomData::<UnVerified>,
+        };
+
+        master.master_pass = Some("Test123@".into());
+        let verified = master.verify()?.expect("Master password is verified");
+        // Key derived for the key check is reused by loading the vault
+        assert!(verified
+            .key
+            .as_ref()
+            .is_some_and(|cached| cached.key.expose() == key.expose()));
+
+        master.master_pass = Some("Wrong123@".into());
+        assert!(master.verify()?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn read_from_non_interactive_source() -> Result<(), MasterPasswordError> {
+        let file = std::env::temp_dir().join("pass_rs_master_file");
+        std::fs::write(&file, "Test123@ \r\n").unwrap();
+
+        // Only the trailing newline is stripped
+        let source = PasswordSource::File(file.display().to_string());
+        assert!(!source.is_interactive());
+        assert_eq!(source.read()?.expose(), b"Test123@ ");
+        std::fs::remove_file(&file).unwrap();
+
+        #[cfg(unix)]
+        {
+            let source = PasswordSource::Command("echo Test123@".to_owned());
+            assert_eq!(source.read()?.expose(), b"Test123@");
+
+            assert!(PasswordSource::Command("exit 1".to_owned()).read().is_err());
+        }
+
+        Ok(())
+    }
 }
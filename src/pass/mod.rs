@@ -1,4 +1,17 @@
+#[cfg(unix)]
+pub mod agent;
+pub mod attachment;
+pub mod backend;
+pub mod doctor;
 pub mod entry;
+pub mod header;
+pub mod index;
+pub mod kdf;
+pub mod kind;
+pub mod lock;
 pub mod master;
+pub mod otp;
+pub mod secret;
 pub mod store;
 pub mod util;
+pub mod vault;
@@ -0,0 +1,334 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ring::hmac;
+use serde::{Deserialize, Serialize};
+
+use crate::pass::secret::SecretBytes;
+
+/// Digits of code used when not specified
+pub const OTP_DIGITS: u32 = 6;
+
+/// Seconds for which a TOTP code is valid when not specified
+pub const TOTP_PERIOD: u64 = 30;
+
+#[derive(Debug, thiserror::Error)]
+pub enum OtpError {
+    #[error("Invalid base32 secret")]
+    InvalidSecret,
+
+    #[error("Invalid otpauth URI: {0}")]
+    InvalidUri(String),
+
+    #[error("Unsupported OTP algorithm '{0}'")]
+    UnsupportedAlgorithm(String),
+
+    #[error("Digits of code must be between 6 and 10")]
+    InvalidDigits,
+
+    #[error("Period of TOTP can not be zero")]
+    InvalidPeriod,
+}
+
+/// HMAC algorithm used to compute the code
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OtpAlgorithm {
+    #[default]
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl OtpAlgorithm {
+    fn parse(value: &str) -> Result<Self, OtpError> {
+        match value.to_ascii_uppercase().as_str() {
+            "SHA1" => Ok(OtpAlgorithm::Sha1),
+            "SHA256" => Ok(OtpAlgorithm::Sha256),
+            "SHA512" => Ok(OtpAlgorithm::Sha512),
+            _ => Err(OtpError::UnsupportedAlgorithm(value.to_owned())),
+        }
+    }
+
+    fn hmac(&self) -> hmac::Algorithm {
+        match self {
+            OtpAlgorithm::Sha1 => hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY,
+            OtpAlgorithm::Sha256 => hmac::HMAC_SHA256,
+            OtpAlgorithm::Sha512 => hmac::HMAC_SHA512,
+        }
+    }
+}
+
+/// Time based (RFC 6238) or counter based (RFC 4226) one-time password
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OtpKind {
+    Totp { period: u64 },
+    Hotp { counter: u64 },
+}
+
+/// One-time password secret of an entry, along with the parameters to compute codes
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Otp {
+    secret: SecretBytes,
+    algorithm: OtpAlgorithm,
+    digits: u32,
+    pub(crate) kind: OtpKind,
+}
+
+impl Otp {
+    pub fn new(
+        secret: impl AsRef<[u8]>,
+        algorithm: OtpAlgorithm,
+        digits: u32,
+        kind: OtpKind,
+    ) -> Result<Self, OtpError> {
+        if !(6..=10).contains(&digits) {
+            return Err(OtpError::InvalidDigits);
+        }
+        if kind == (OtpKind::Totp { period: 0 }) {
+            return Err(OtpError::InvalidPeriod);
+        }
+
+        Ok(Otp {
+            secret: secret.as_ref().to_vec().into(),
+            algorithm,
+            digits,
+            kind,
+        })
+    }
+
+    /// Parse an `otpauth://` URI, or a base32 secret of TOTP with default parameters
+    pub fn parse(value: impl AsRef<str>) -> Result<Self, OtpError> {
+        let value = value.as_ref().trim();
+
+        match value.strip_prefix("otpauth://") {
+            Some(uri) => Otp::parse_uri(uri),
+            None => Otp::new(
+                decode_base32(value)?,
+                OtpAlgorithm::default(),
+                OTP_DIGITS,
+                OtpKind::Totp {
+                    period: TOTP_PERIOD,
+                },
+            ),
+        }
+    }
+
+    /// Parse `<type>/<label>?<params>` of an `otpauth://` URI
+    fn parse_uri(uri: &str) -> Result<Self, OtpError> {
+        let invalid = |message: &str| OtpError::InvalidUri(message.to_owned());
+
+        let (kind, rest) = uri.split_once('/').ok_or_else(|| invalid("missing type"))?;
+        let query = rest.split_once('?').map(|(_, query)| query).unwrap_or("");
+
+        let mut secret = None;
+        let mut algorithm = OtpAlgorithm::default();
+        let mut digits = OTP_DIGITS;
+        let mut period = TOTP_PERIOD;
+        let mut counter = None;
+
+        for param in query.split('&').filter(|param| !param.is_empty()) {
+            let (key, value) = param.split_once('=').unwrap_or((param, ""));
+            let value = percent_decode(value);
+
+            match key.to_ascii_lowercase().as_str() {
+                "secret" => secret = Some(decode_base32(&value)?),
+                "algorithm" => algorithm = OtpAlgorithm::parse(&value)?,
+                "digits" => digits = value.parse().map_err(|_| invalid("invalid digits"))?,
+                "period" => period = value.parse().map_err(|_| invalid("invalid period"))?,
+                "counter" => counter = Some(value.parse().map_err(|_| invalid("invalid counter"))?),
+                // Issuer & other params are not needed to compute codes
+                _ => {}
+            }
+        }
+
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "totp" => OtpKind::Totp { period },
+            "hotp" => OtpKind::Hotp {
+                counter: counter.ok_or_else(|| invalid("missing counter of HOTP"))?,
+            },
+            kind => return Err(OtpError::InvalidUri(format!("unknown type '{kind}'"))),
+        };
+
+        Otp::new(
+            secret.ok_or_else(|| invalid("missing secret"))?,
+            algorithm,
+            digits,
+            kind,
+        )
+    }
+
+    /// Code for the counter (RFC 4226)
+    pub fn generate(&self, counter: u64) -> String {
+        let key = hmac::Key::new(self.algorithm.hmac(), self.secret.expose());
+        let hash = hmac::sign(&key, &counter.to_be_bytes());
+        let hash = hash.as_ref();
+
+        // Dynamic truncation
+        let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+        let binary = u32::from_be_bytes([
+            hash[offset] & 0x7f,
+            hash[offset + 1],
+            hash[offset + 2],
+            hash[offset + 3],
+        ]);
+
+        let code = binary as u64 % 10_u64.pow(self.digits);
+        format!("{code:0width$}", width = self.digits as usize)
+    }
+
+    /// TOTP code at the unix time, along with the seconds it remains valid (RFC 6238)
+    pub fn totp_at(&self, unix_time: u64) -> Option<(String, u64)> {
+        match self.kind {
+            OtpKind::Totp { period } => Some((
+                self.generate(unix_time / period),
+                period - unix_time % period,
+            )),
+            OtpKind::Hotp { .. } => None,
+        }
+    }
+
+    /// Current code & seconds it remains valid for TOTP. HOTP code is computed
+    /// for the current counter which is then incremented, so the entry must be saved.
+    pub fn next_code(&mut self) -> (String, Option<u64>) {
+        match &mut self.kind {
+            OtpKind::Totp { .. } => {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or_default();
+                let (code, remaining) = self.totp_at(now).expect("Unreachable: OTP is TOTP");
+                (code, Some(remaining))
+            }
+            OtpKind::Hotp { counter } => {
+                let current = *counter;
+                *counter += 1;
+                (self.generate(current), None)
+            }
+        }
+    }
+}
+
+/// Decode a base32 secret, ignoring case, spaces & padding as authenticator apps do
+fn decode_base32(secret: &str) -> Result<Vec<u8>, OtpError> {
+    let secret = secret
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '=' && *c != '-')
+        .collect::<String>()
+        .to_ascii_uppercase();
+
+    match data_encoding::BASE32_NOPAD.decode(secret.as_bytes()) {
+        Ok(secret) if !secret.is_empty() => Ok(secret),
+        _ => Err(OtpError::InvalidSecret),
+    }
+}
+
+/// Decode `%XX` escapes of URI component
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = bytes
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn hotp_rfc4226_vectors() -> Result<(), OtpError> {
+        let otp = Otp::new(
+            b"12345678901234567890",
+            OtpAlgorithm::Sha1,
+            6,
+            OtpKind::Hotp { counter: 0 },
+        )?;
+        let expected = [
+            "755224", "287082", "359152", "969429", "338314", "254676", "287922", "162583",
+            "399871", "520489",
+        ];
+
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(otp.generate(counter as u64), *code);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn totp_rfc6238_vectors() -> Result<(), OtpError> {
+        let totp =
+            |secret: &[u8], algorithm| Otp::new(secret, algorithm, 8, OtpKind::Totp { period: 30 });
+        let sha1 = totp(b"12345678901234567890", OtpAlgorithm::Sha1)?;
+        let sha256 = totp(b"12345678901234567890123456789012", OtpAlgorithm::Sha256)?;
+        let sha512 = totp(
+            b"1234567890123456789012345678901234567890123456789012345678901234",
+            OtpAlgorithm::Sha512,
+        )?;
+
+        let vectors = [
+            (59, "94287082", "46119246", "90693936"),
+            (1111111109, "07081804", "68084774", "25091201"),
+            (1111111111, "14050471", "67062674", "99943326"),
+            (1234567890, "89005924", "91819424", "93441116"),
+            (2000000000, "69279037", "90698825", "38618901"),
+            (20000000000, "65353130", "77737706", "47863826"),
+        ];
+
+        for (time, code_sha1, code_sha256, code_sha512) in vectors {
+            assert_eq!(sha1.totp_at(time).unwrap().0, code_sha1);
+            assert_eq!(sha256.totp_at(time).unwrap().0, code_sha256);
+            assert_eq!(sha512.totp_at(time).unwrap().0, code_sha512);
+        }
+
+        assert_eq!(sha1.totp_at(59).unwrap().1, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_otp() -> Result<(), OtpError> {
+        // Base32 of "12345678901234567890"
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+
+        let otp = Otp::parse(secret.to_lowercase())?;
+        assert_eq!(otp.secret.expose(), b"12345678901234567890");
+        assert_eq!(otp.kind, OtpKind::Totp { period: 30 });
+
+        let otp = Otp::parse(format!(
+            "otpauth://totp/ACME%20Co:john@example.com?secret={secret}&issuer=ACME%20Co&algorithm=SHA256&digits=8&period=60"
+        ))?;
+        assert_eq!(otp.algorithm, OtpAlgorithm::Sha256);
+        assert_eq!(otp.digits, 8);
+        assert_eq!(otp.kind, OtpKind::Totp { period: 60 });
+
+        let mut otp = Otp::parse(format!("otpauth://hotp/ACME?secret={secret}&counter=1"))?;
+        assert_eq!(otp.next_code(), ("287082".to_owned(), None));
+        assert_eq!(otp.kind, OtpKind::Hotp { counter: 2 });
+
+        assert!(Otp::parse("not base32!").is_err());
+        assert!(Otp::parse(format!("otpauth://hotp/ACME?secret={secret}")).is_err());
+        assert!(Otp::parse(format!("otpauth://totp/ACME?secret={secret}&algorithm=MD5")).is_err());
+        assert!(Otp::parse(format!("otpauth://totp/ACME?secret={secret}&digits=4")).is_err());
+
+        Ok(())
+    }
+}
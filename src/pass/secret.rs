@@ -0,0 +1,192 @@
+use ring::hkdf;
+use serde::{Deserialize, Serialize};
+use zeroize::Zeroize;
+
+/// Bytes of a secret, e.g. a password, zeroed on drop & never printed. Clones (e.g. of the
+/// entries having it) are zeroed on drop as well.
+/// Serialized as plain bytes, so it is a drop-in replacement of `Vec<u8>` in the payload
+#[derive(Default, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct SecretBytes(Vec<u8>);
+
+impl SecretBytes {
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn expose_str(&self) -> Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl From<Vec<u8>> for SecretBytes {
+    fn from(bytes: Vec<u8>) -> Self {
+        SecretBytes(bytes)
+    }
+}
+
+impl From<String> for SecretBytes {
+    fn from(string: String) -> Self {
+        SecretBytes(string.into_bytes())
+    }
+}
+
+impl From<&str> for SecretBytes {
+    fn from(string: &str) -> Self {
+        SecretBytes(string.as_bytes().to_vec())
+    }
+}
+
+impl Drop for SecretBytes {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl std::fmt::Debug for SecretBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// [SecretBytes] serialized as a byte string by `serde_bytes`, for payloads storing it so
+pub mod as_byte_string {
+    use super::SecretBytes;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &SecretBytes, serializer: S) -> Result<S::Ok, S::Error> {
+        serde_bytes::serialize(bytes.expose(), serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<SecretBytes, D::Error> {
+        serde_bytes::deserialize::<Vec<u8>, _>(deserializer).map(SecretBytes::from)
+    }
+}
+
+/// Secret value other than bytes, e.g. a card number or a private key, zeroed on drop & never
+/// printed. Serialized as the value itself
+#[derive(Default, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(transparent)]
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> std::fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+/// 32 bytes key on the heap, locked in memory against swapping (with `mlock` feature, if
+/// permitted by the OS) & zeroed on drop
+pub struct SecretKey {
+    // Fields drop in order, so the key is unlocked before its memory is freed
+    #[cfg(feature = "mlock")]
+    _guard: Option<region::LockGuard>,
+
+    key: Box<[u8; 32]>,
+}
+
+impl SecretKey {
+    /// Zeroed key, to be filled in place by [SecretKey::expose_mut] without copying it around
+    pub fn zeroed() -> Self {
+        let key = Box::new([0_u8; 32]);
+
+        #[cfg(feature = "mlock")]
+        let guard = region::lock(key.as_ptr(), key.len()).ok();
+
+        SecretKey {
+            #[cfg(feature = "mlock")]
+            _guard: guard,
+            key,
+        }
+    }
+
+    pub fn expose(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    pub fn expose_mut(&mut self) -> &mut [u8; 32] {
+        &mut self.key
+    }
+
+    /// Independent key for the purpose, derived from this key by HKDF-SHA256
+    pub fn subkey(&self, purpose: &[u8]) -> Self {
+        let mut subkey = SecretKey::zeroed();
+        hkdf::Salt::new(hkdf::HKDF_SHA256, &[])
+            .extract(self.expose())
+            .expand(&[purpose], hkdf::HKDF_SHA256)
+            .and_then(|okm| okm.fill(subkey.expose_mut()))
+            .expect("Unreachable: 32 bytes is a valid length of HKDF-SHA256 output");
+        subkey
+    }
+}
+
+/// Copy is locked & zeroed on drop as well
+impl Clone for SecretKey {
+    fn clone(&self) -> Self {
+        let mut key = SecretKey::zeroed();
+        key.expose_mut().copy_from_slice(self.expose());
+        key
+    }
+}
+
+impl Drop for SecretKey {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl std::fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("<redacted>")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn secrets_are_redacted() {
+        let password = SecretBytes::from("hunter2");
+        assert_eq!(format!("{password:?}"), "<redacted>");
+        assert_eq!(password.expose_str(), Ok("hunter2"));
+
+        let number = Secret::from("4242424242424242".to_owned());
+        assert_eq!(format!("{number:?}"), "<redacted>");
+        assert_eq!(number.expose(), "4242424242424242");
+
+        let mut key = SecretKey::zeroed();
+        key.expose_mut()[0] = 0xab;
+        assert_eq!(format!("{key:?}"), "<redacted>");
+        assert_eq!(key.clone().expose(), key.expose());
+
+        // Subkeys are stable & differ by purpose
+        assert_eq!(key.subkey(b"a").expose(), key.subkey(b"a").expose());
+        assert_ne!(key.subkey(b"a").expose(), key.subkey(b"b").expose());
+        assert_ne!(key.subkey(b"a").expose(), key.expose());
+    }
+}
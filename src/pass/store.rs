@@ -1,25 +1,36 @@
 use std::borrow::BorrowMut;
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
 
 use cli_table::format::Justify;
 use cli_table::{Cell, Style, Table};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
-use serde_encrypt::{
-    serialize::impls::BincodeSerializer, shared_key::SharedKey, traits::SerdeEncryptSharedKey,
-    EncryptedMessage,
-};
+use serde_encrypt::{serialize::impls::CborSerializer, traits::SerdeEncryptSharedKey};
+use uuid::Uuid;
 
-use crate::pass::master::{MasterPassword, Verified};
-use crate::pass::util::print_pass_entry_info;
+use crate::pass::attachment::{
+    attachments_dir, collect_garbage, remove_blobs, Attachment, AttachmentError, ATTACHMENT_LIMIT,
+};
+use crate::pass::backend::{
+    open_backend, unseal as unseal_entry, BackendError, BackendKind, VaultBackend,
+};
+use crate::pass::header::{VaultHeader, VAULT_VERSION};
+use crate::pass::index::EntryIndex;
+use crate::pass::kdf::{Kdf, KdfError};
+use crate::pass::lock;
+use crate::pass::master::{MasterPassword, VaultKey, Verified};
+use crate::pass::secret::SecretKey;
+use crate::pass::util::{
+    ask_for_confirm, backup_path, format_time, print_pass_entry_info, replace_file, staging_path,
+    sync_parent_dir, write_staging_file,
+};
+use crate::pass::vault::Vault;
 use crate::pass::{entry::PasswordEntry, util::XDG_BASE};
 
-// $HOME/.local/state/pass/passwords.db
-pub static PASS_ENTRY_STORE: Lazy<std::path::PathBuf> = Lazy::new(|| {
-    XDG_BASE
-        .place_state_file("passwords.db")
-        .expect("Unable to place passwords.db file in state")
-});
+// $HOME/.local/state/pass/passwords.db, or passwords.db of the selected vault
+pub static PASS_ENTRY_STORE: Lazy<std::path::PathBuf> =
+    Lazy::new(|| Vault::selected().store_path());
 
 // $HOME/.local/state/pass/testing.db
 pub static TESTING_PASS: Lazy<std::path::PathBuf> = Lazy::new(|| {
@@ -54,28 +65,123 @@ pub enum PasswordStoreError {
     #[error("Encrypt Error: {0}")]
     UnableToEncryptError(String),
 
+    #[error("Unable to derive encryption key: {0}")]
+    UnableToDeriveKey(#[source] KdfError),
+
     #[error("No available entry")]
     NoEntryAvailable,
 
+    #[error("No entry with id '{0}'")]
+    NoEntryWithId(Uuid),
+
     #[error("There is nothing to do")]
     NothingToDo,
+
+    #[error("Attachment error: {0}")]
+    Attachment(#[source] AttachmentError),
+
+    #[error("{0}")]
+    Backend(#[source] BackendError),
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
-pub struct PasswordStore {
+/// Content of the vault which is encrypted & persisted in db
+#[derive(Debug, Deserialize, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct VaultPayload {
     pub(crate) passwords: Vec<PasswordEntry>,
-    pub(crate) master_password: MasterPassword<Verified>,
+
+    #[serde(default)]
+    pub(crate) settings: VaultSettings,
+}
+
+/// Number of previous passwords kept by each entry unless configured
+pub const HISTORY_DEPTH: usize = 5;
+
+/// Preferences of the vault, persisted along with the entries
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct VaultSettings {
+    /// Number of previous passwords kept by each entry
+    pub(crate) history_depth: usize,
+
+    /// Largest attachment in bytes
+    pub(crate) attachment_limit: u64,
+
+    /// Blobs of removed entries, still referenced by the backup until the next dump
+    pub(crate) removed_blobs: Vec<String>,
+}
+
+impl Default for VaultSettings {
+    fn default() -> Self {
+        VaultSettings {
+            history_depth: HISTORY_DEPTH,
+            attachment_limit: ATTACHMENT_LIMIT,
+            removed_blobs: Vec::new(),
+        }
+    }
+}
+
+impl SerdeEncryptSharedKey for VaultPayload {
+    type S = CborSerializer<Self>;
 }
 
-impl SerdeEncryptSharedKey for PasswordStore {
-    type S = BincodeSerializer<Self>;
+/// Steps of [PasswordStore::rekey] & [PasswordStore::migrate]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RekeyStep {
+    Decrypt,
+    Stage,
+    Verify,
+    Swap,
+}
+
+/// Unlocked vault, i.e. the decrypted payload along with the context to encrypt it again
+#[derive(Debug)]
+pub struct PasswordStore {
+    pub(crate) payload: VaultPayload,
+
+    /// Vault key derived from master password for the KDF & salt of header, the master
+    /// password itself is not kept
+    key: SecretKey,
+
+    /// Salt & KDF of the vault, stored unencrypted in front of the entries
+    pub(crate) header: VaultHeader,
+
+    /// Positions of entries by their id & service, kept along with the entries
+    index: EntryIndex,
+
+    /// Blobs removed by this run, which are referenced by the vault on disk
+    removed_blobs: HashSet<String>,
 }
 
 impl PasswordStore {
+    /// Unlocked vault having the entries of payload
+    pub fn with_payload(
+        payload: VaultPayload,
+        master_password: &MasterPassword<Verified>,
+        header: VaultHeader,
+    ) -> Result<Self, PasswordStoreError> {
+        let key = master_password
+            .derive_encryption_key(&header.kdf, &header.salt)
+            .map_err(PasswordStoreError::UnableToDeriveKey)?;
+
+        Ok(PasswordStore::with_key(payload, key, header))
+    }
+
+    /// Unlocked vault having the entries of payload, by the key already derived for header
+    fn with_key(payload: VaultPayload, key: SecretKey, header: VaultHeader) -> Self {
+        let index = EntryIndex::new(&payload.passwords);
+        PasswordStore {
+            payload,
+            key,
+            header,
+            index,
+            removed_blobs: HashSet::new(),
+        }
+    }
+
     /// Extract the data from database(if exist) & store in [PasswordStore]
     pub fn new(
         file_path: impl AsRef<Path>,
-        master_password: MasterPassword<Verified>,
+        master_password: &MasterPassword<Verified>,
     ) -> Result<Self, PasswordStoreError> {
         match file_path.as_ref().exists() {
             // Load all existing password entry from db
@@ -86,101 +192,343 @@ impl PasswordStore {
                 std::fs::File::create(file_path.as_ref())
                     .map_err(PasswordStoreError::UnableToCreateFile)?;
 
-                // Returning an empty Vec<> because of no Entry available
-                Ok(PasswordStore {
-                    passwords: Vec::new(),
+                // Returning an empty payload because of no Entry available
+                PasswordStore::with_payload(
+                    VaultPayload::default(),
                     master_password,
-                })
+                    VaultHeader::new(),
+                )
             }
         }
     }
 
-    /// Encrypt the Password entries
-    pub fn encrypt_entry(&self) -> Result<impl AsRef<[u8]>, serde_encrypt::Error> {
-        // TODO: A method for SALT generation storage in db.
-
-        // Derive encryption key from master password
-        let key = self
-            .master_password
-            .derive_encryption_key("Salt".as_bytes());
-        let key = SharedKey::new(key);
-
-        // Encrypt contents and serialize it
-        let encrypted_content = self.encrypt(&key)?;
-        Ok(encrypted_content.serialize())
+    /// Read only the unencrypted header of vault, if the vault has one
+    pub fn read_header(
+        file_path: impl AsRef<Path>,
+    ) -> Result<Option<VaultHeader>, PasswordStoreError> {
+        open_backend(file_path)
+            .and_then(|mut backend| backend.open())
+            .map_err(PasswordStoreError::Backend)
     }
 
-    // Decrypt the entry
-    pub fn decrypt_entry(
-        content: impl AsRef<[u8]>,
-        master_password: MasterPassword<Verified>,
-    ) -> Result<Self, serde_encrypt::Error> {
-        let key = master_password.derive_encryption_key("Salt".as_bytes());
-        let key = SharedKey::new(key);
-
-        let encrypted_content = EncryptedMessage::deserialize(content.as_ref().to_vec())?;
-        let decrypted_content = PasswordStore::decrypt_owned(&encrypted_content, &key)?;
-        Ok(decrypted_content)
+    /// Master password unlocking only by the vault key, to read other files of the vault
+    /// (e.g. its backup) having the same KDF & salt
+    fn master_password(&self) -> MasterPassword<Verified> {
+        MasterPassword::from_key(VaultKey::new(
+            self.header.kdf,
+            &self.header.salt,
+            self.key.clone(),
+        ))
     }
 
     /// Add entries to the existing entries
     pub fn push_entry(&mut self, entry: PasswordEntry) {
-        let is_dupe = self.passwords.iter().any(|current_entry| {
-            current_entry.service == entry.service && current_entry.username == entry.username
-        });
-
-        if !is_dupe {
-            self.passwords.push(entry.clone());
-            colour::green_ln!("Successfully added entry");
-        } else {
-            colour::e_red_ln!("Password entry of same service or username found");
+        match self.insert_entry(entry) {
+            true => colour::green_ln!("Successfully added entry"),
+            false => colour::e_red_ln!("Password entry of same service or username found"),
+        }
+    }
+
+    /// Add entry unless an entry of same service & username exists, returns whether it is added
+    pub fn insert_entry(&mut self, mut entry: PasswordEntry) -> bool {
+        entry.truncate_history(self.payload.settings.history_depth);
+
+        if self.is_dupe(&entry.service, &entry.username, None) {
+            return false;
         }
+
+        self.index.insert(self.payload.passwords.len(), &entry);
+        self.payload.passwords.push(entry);
+        true
+    }
+
+    /// Whether an entry (other than the one having `except` id) has same service & username
+    fn is_dupe(&self, service: &str, username: &Option<String>, except: Option<Uuid>) -> bool {
+        self.index
+            .service(service)
+            .iter()
+            .map(|&position| &self.payload.passwords[position])
+            .any(|entry| Some(entry.id) != except && entry.username == *username)
+    }
+
+    /// Rebuild the index, after the entries are changed without the methods of store
+    pub fn reindex(&mut self) {
+        self.index = EntryIndex::new(&self.payload.passwords);
     }
 
     /// Encrypt the entries & dump it to db
     pub fn dump(&self, file_path: impl AsRef<Path>) -> Result<(), PasswordStoreError> {
-        // Encrypting all password entries
-        let encrypted_data = self.encrypt_entry().map_err(|_| {
-            PasswordStoreError::UnableToEncryptError("Failed to encrypt entry".to_owned())
-        })?;
+        let file_path = file_path.as_ref();
+        let mut backend = open_backend(file_path).map_err(PasswordStoreError::Backend)?;
 
-        // Dump it to the db
-        std::fs::write(file_path, encrypted_data).map_err(PasswordStoreError::UnableToWriteFile)?;
+        // Blobs removed by previous runs are referenced neither by the vault nor by its backup
+        // once the vault on disk is rotated into it
+        let (removed, collectable): (Vec<_>, Vec<_>) = self
+            .payload
+            .settings
+            .removed_blobs
+            .iter()
+            .cloned()
+            .partition(|blob| self.removed_blobs.contains(blob));
+        let settings = VaultSettings {
+            removed_blobs: removed,
+            ..self.payload.settings.clone()
+        };
+        self.commit_into(backend.as_mut(), &settings)?;
+
+        remove_blobs(attachments_dir(file_path), &collectable)
+            .map_err(PasswordStoreError::Attachment)?;
 
         Ok(())
     }
 
-    /// Read entries from database & decrypt it
+    /// Write the header, settings & entries into backend, deleting the entries removed since
+    pub fn dump_into(&self, backend: &mut dyn VaultBackend) -> Result<(), PasswordStoreError> {
+        self.commit_into(backend, &self.payload.settings)
+    }
+
+    fn commit_into(
+        &self,
+        backend: &mut dyn VaultBackend,
+        settings: &VaultSettings,
+    ) -> Result<(), PasswordStoreError> {
+        let key = &self.key;
+
+        // Entries are always written in the latest format, whatever the vault was read from
+        let mut header = self.header.clone();
+        header.version = VAULT_VERSION;
+
+        let ids = self
+            .payload
+            .passwords
+            .iter()
+            .map(|entry| entry.id)
+            .collect::<HashSet<_>>();
+        let removed = backend
+            .ids()
+            .map_err(PasswordStoreError::Backend)?
+            .into_iter()
+            .filter(|id| !ids.contains(id));
+
+        for id in removed {
+            backend.delete(id).map_err(PasswordStoreError::Backend)?;
+        }
+        for entry in self.payload.passwords.iter() {
+            backend
+                .upsert(entry, key)
+                .map_err(PasswordStoreError::Backend)?;
+        }
+
+        backend
+            .commit(&header, settings, key)
+            .map_err(PasswordStoreError::Backend)
+    }
+
+    /// Read entries from database & decrypt it, offering the backup if database is unreadable
     pub fn load(
         file_path: impl AsRef<Path>,
-        master_password: MasterPassword<Verified>,
+        master_password: &MasterPassword<Verified>,
+    ) -> Result<Self, PasswordStoreError> {
+        let mut store = PasswordStore::load_metadata(file_path, master_password)?;
+        store.unseal_all()?;
+
+        Ok(store)
+    }
+
+    /// [PasswordStore::load] without decrypting the secrets of entries, e.g. to list them.
+    /// Secrets of an entry are decrypted on use by [PasswordStore::unseal]
+    pub fn load_metadata(
+        file_path: impl AsRef<Path>,
+        master_password: &MasterPassword<Verified>,
+    ) -> Result<Self, PasswordStoreError> {
+        let file_path = file_path.as_ref();
+        let backup = backup_path(file_path);
+
+        // Vault is only replaced by its backup while no other process reads it
+        let mut store = match PasswordStore::read_metadata(file_path, master_password) {
+            Err(
+                err @ PasswordStoreError::Backend(
+                    BackendError::UnableToDecrypt(_)
+                    | BackendError::UnableToDeserialize(_)
+                    | BackendError::MismatchedId(_)
+                    | BackendError::UnauthenticatedRecord(_)
+                    | BackendError::TruncatedLog
+                    | BackendError::InvalidHeader(_),
+                ),
+            ) if backup.exists() && !lock::is_shared() => {
+                colour::e_red_ln!("Unable to unlock {}: {err}", file_path.display());
+
+                match ask_for_confirm(format!("Restore vault from {}?", backup.display())) {
+                    Ok(true) => PasswordStore::restore_backup(file_path, master_password),
+                    _ => Err(err),
+                }
+            }
+            result => result,
+        }?;
+
+        // Entries of vaults before ids get their ids on first unlock
+        let mut is_backfilled = false;
+        for entry in store.payload.passwords.iter_mut() {
+            is_backfilled |= entry.backfill_id();
+        }
+        if is_backfilled {
+            store.reindex();
+        }
+
+        // Vault is read as it is under a shared lock, as other processes may be reading it
+        if lock::is_shared() {
+            return Ok(store);
+        }
+
+        // Migrate older vaults to the latest format on first unlock
+        let is_outdated = store.header.version < VAULT_VERSION;
+        if is_outdated {
+            let mut header = store.header.clone();
+            header.migrate();
+            store.replace_header(master_password, header)?;
+        }
+
+        if is_outdated || is_backfilled {
+            store.dump(file_path)?;
+        }
+
+        Ok(store)
+    }
+
+    /// Replace the database by its backup, if the backup is decryptable by master password
+    pub fn restore_backup(
+        file_path: impl AsRef<Path>,
+        master_password: &MasterPassword<Verified>,
+    ) -> Result<Self, PasswordStoreError> {
+        let file_path = file_path.as_ref();
+        let backup = backup_path(file_path);
+
+        let store = PasswordStore::read_file(&backup, master_password)?;
+
+        // Unreadable database is overwritten without rotating it into the backup
+        let content = std::fs::read(&backup).map_err(PasswordStoreError::UnableToRead)?;
+        write_staging_file(file_path, content)
+            .and_then(|staging| std::fs::rename(staging, file_path))
+            .and_then(|_| sync_parent_dir(file_path))
+            .map_err(PasswordStoreError::UnableToWriteFile)?;
+
+        colour::green_ln!("Vault restored from {}", backup.display());
+
+        Ok(store)
+    }
+
+    /// Read & decrypt a vault file as it is, without migrating it
+    fn read_file(
+        file_path: impl AsRef<Path>,
+        master_password: &MasterPassword<Verified>,
+    ) -> Result<Self, PasswordStoreError> {
+        let mut store = PasswordStore::read_metadata(file_path, master_password)?;
+        store.unseal_all()?;
+
+        Ok(store)
+    }
+
+    /// [PasswordStore::read_file] leaving the secrets of entries sealed
+    fn read_metadata(
+        file_path: impl AsRef<Path>,
+        master_password: &MasterPassword<Verified>,
     ) -> Result<Self, PasswordStoreError> {
-        let encrypted_data = std::fs::read(file_path).map_err(PasswordStoreError::UnableToRead)?;
+        let mut backend = open_backend(file_path).map_err(PasswordStoreError::Backend)?;
+        PasswordStore::read_from(backend.as_mut(), master_password)
+    }
 
-        if encrypted_data.is_empty() {
-            return Ok(PasswordStore {
-                passwords: vec![],
+    /// Read & decrypt the vault stored by backend, leaving the secrets of entries sealed
+    pub fn read_from(
+        backend: &mut dyn VaultBackend,
+        master_password: &MasterPassword<Verified>,
+    ) -> Result<Self, PasswordStoreError> {
+        let Some(header) = backend.open().map_err(PasswordStoreError::Backend)? else {
+            return PasswordStore::with_payload(
+                VaultPayload::default(),
                 master_password,
-            });
+                VaultHeader::new(),
+            );
+        };
+
+        let key = master_password
+            .derive_encryption_key(&header.kdf, &header.salt)
+            .map_err(PasswordStoreError::UnableToDeriveKey)?;
+        let payload = backend
+            .read_entries(&header, &key)
+            .map_err(PasswordStoreError::Backend)?;
+
+        Ok(PasswordStore::with_key(payload, key, header))
+    }
+
+    /// Decrypt the secrets of all entries which are still sealed
+    pub fn unseal_all(&mut self) -> Result<(), PasswordStoreError> {
+        if !self.payload.passwords.iter().any(PasswordEntry::is_sealed) {
+            return Ok(());
         }
 
-        PasswordStore::decrypt_entry(encrypted_data, master_password).map_err(|_| {
-            PasswordStoreError::UnableToDecryptError("Failed to decrypt entries".to_owned())
-        })
+        self.payload
+            .passwords
+            .iter_mut()
+            .try_for_each(|entry| unseal_entry(entry, &self.key))
+            .map_err(PasswordStoreError::Backend)
+    }
+
+    /// Decrypt the secrets of entry by its id, right before they are used
+    pub fn unseal(&mut self, id: Uuid) -> Result<PasswordEntry, PasswordStoreError> {
+        let position = self
+            .index
+            .position(id)
+            .ok_or(PasswordStoreError::NoEntryWithId(id))?;
+        let entry = &mut self.payload.passwords[position];
+        unseal_entry(entry, &self.key).map_err(PasswordStoreError::Backend)?;
+
+        Ok(entry.clone())
     }
 
     /// Remove entries from existing entries
     pub fn remove(
         &mut self,
         entries: impl AsRef<[PasswordEntry]>,
+        file_path: impl AsRef<Path>,
     ) -> Result<(), PasswordStoreError> {
         let entries = entries.as_ref();
+        let is_removed =
+            |entry: &PasswordEntry| entries.iter().any(|removed| removed.id == entry.id);
+
+        // Blobs of removed entries are deleted by the next dump, as the backup written by this
+        // one still references them. Attachments of sealed entries are unknown, so blobs shared
+        // with them can't be told apart
+        if !self.payload.passwords.iter().any(PasswordEntry::is_sealed) {
+            let blobs = |removed: bool| {
+                self.payload
+                    .passwords
+                    .iter()
+                    .filter(move |entry| is_removed(entry) == removed)
+                    .flat_map(|entry| entry.attachments.iter())
+                    .filter_map(Attachment::blob)
+            };
+            let kept = blobs(false).collect::<HashSet<_>>();
+            let removed = blobs(true)
+                .filter(|blob| !kept.contains(blob))
+                .map(str::to_owned)
+                .collect::<Vec<_>>();
+
+            for blob in removed {
+                if !self.payload.settings.removed_blobs.contains(&blob) {
+                    self.payload.settings.removed_blobs.push(blob.clone());
+                }
+                self.removed_blobs.insert(blob);
+            }
+        }
 
         self.borrow_mut()
+            .payload
             .passwords
-            .retain(|entry| !entries.contains(entry));
+            .retain(|entry| !is_removed(entry));
+        self.reindex();
 
-        self.dump(PASS_ENTRY_STORE.as_path())?;
+        self.dump(file_path.as_ref())?;
+        self.collect_garbage(file_path.as_ref())?;
 
         colour::green_ln!("Removed {} password entry", entries.len());
         print_pass_entry_info(entries);
@@ -188,39 +536,371 @@ impl PasswordStore {
         Ok(())
     }
 
+    /// Replace an existing entry with its updated version
+    pub fn update(
+        &mut self,
+        old_entry: &PasswordEntry,
+        mut new_entry: PasswordEntry,
+    ) -> Result<(), PasswordStoreError> {
+        if self.is_dupe(&new_entry.service, &new_entry.username, Some(old_entry.id)) {
+            colour::e_red_ln!("Password entry of same service or username found");
+            return Err(PasswordStoreError::NothingToDo);
+        }
+
+        let history_depth = self.payload.settings.history_depth;
+        let entry = self.entry_mut(old_entry.id)?;
+        let is_renamed = entry.service != new_entry.service || entry.id != new_entry.id;
+        new_entry.mark_modified();
+        new_entry.truncate_history(history_depth);
+        *entry = new_entry;
+
+        if is_renamed {
+            self.reindex();
+        }
+
+        Ok(())
+    }
+
     /// Get [PasswordEntry] by matching service
     pub fn get(&self, service: impl AsRef<str>) -> Vec<PasswordEntry> {
-        self.passwords
-            .clone()
-            .into_iter()
-            .filter(|entry| entry.service == service.as_ref())
+        self.index
+            .service(service)
+            .iter()
+            .map(|&position| self.payload.passwords[position].clone())
             .collect::<Vec<PasswordEntry>>()
     }
 
+    /// Get [PasswordEntry] by its id
+    pub fn get_by_id(&self, id: Uuid) -> Result<PasswordEntry, PasswordStoreError> {
+        self.index
+            .position(id)
+            .map(|position| self.payload.passwords[position].clone())
+            .ok_or(PasswordStoreError::NoEntryWithId(id))
+    }
+
+    /// Change the number of previous passwords kept by each entry, forgetting the ones beyond it
+    pub fn set_history_depth(&mut self, depth: usize) -> Result<(), PasswordStoreError> {
+        // History is a secret, so sealed entries are truncated only once decrypted
+        if depth < self.payload.settings.history_depth {
+            self.unseal_all()?;
+        }
+
+        self.payload.settings.history_depth = depth;
+        self.payload
+            .passwords
+            .iter_mut()
+            .for_each(|entry| entry.truncate_history(depth));
+
+        Ok(())
+    }
+
+    /// Refuse attachments larger than the limit of vault, e.g. before reading the file
+    pub fn check_attachment_size(&self, size: u64) -> Result<(), PasswordStoreError> {
+        let limit = self.payload.settings.attachment_limit;
+        match size > limit {
+            true => Err(PasswordStoreError::Attachment(AttachmentError::TooLarge {
+                size,
+                limit,
+            })),
+            false => Ok(()),
+        }
+    }
+
+    /// Attach a file to entry, replacing its attachment of same name. Blob of the replaced
+    /// attachment is kept until [PasswordStore::collect_garbage], i.e. after the vault is dumped
+    pub fn attach(
+        &mut self,
+        id: Uuid,
+        name: impl Into<String>,
+        content: Vec<u8>,
+        blob_dir: impl AsRef<Path>,
+    ) -> Result<(), PasswordStoreError> {
+        self.check_attachment_size(content.len() as u64)?;
+
+        // Attachments of a sealed entry would be replaced by its sealed secrets on dump
+        self.unseal(id)?;
+        let attachment =
+            Attachment::new(name, content, blob_dir).map_err(PasswordStoreError::Attachment)?;
+
+        // Blob of the same content as a removed one is referenced again
+        if let Some(blob) = attachment.blob() {
+            self.payload
+                .settings
+                .removed_blobs
+                .retain(|removed| removed != blob);
+            self.removed_blobs.remove(blob);
+        }
+
+        let entry = self.entry_mut(id)?;
+        entry.set_attachment(attachment);
+        entry.mark_modified();
+
+        Ok(())
+    }
+
+    /// Delete the blobs not referenced by any entry of the vault (or of its backup, which may
+    /// be restored), returns their count
+    pub fn collect_garbage(
+        &self,
+        file_path: impl AsRef<Path>,
+    ) -> Result<usize, PasswordStoreError> {
+        let file_path = file_path.as_ref();
+
+        // Attachments of sealed entries are unknown, so every blob might be referenced
+        if self.payload.passwords.iter().any(PasswordEntry::is_sealed) {
+            return Ok(0);
+        }
+
+        // Nothing is collected while the backup is unreadable, e.g. under a previous master password
+        let backup = backup_path(file_path);
+        let backup_entries = match backup.exists() {
+            true => match PasswordStore::read_file(&backup, &self.master_password()) {
+                Ok(store) => store.payload.passwords,
+                Err(_) => return Ok(0),
+            },
+            false => Vec::new(),
+        };
+
+        let attachments = self
+            .payload
+            .passwords
+            .iter()
+            .chain(backup_entries.iter())
+            .flat_map(|entry| entry.attachments.iter());
+
+        collect_garbage(attachments_dir(file_path), attachments)
+            .map_err(PasswordStoreError::Attachment)
+    }
+
+    /// Mutable [PasswordEntry] by its id, e.g. to record its access.
+    /// Its service is indexed, so it is changed by [PasswordStore::update] instead
+    pub fn entry_mut(&mut self, id: Uuid) -> Result<&mut PasswordEntry, PasswordStoreError> {
+        self.index
+            .position(id)
+            .map(|position| &mut self.payload.passwords[position])
+            .ok_or(PasswordStoreError::NoEntryWithId(id))
+    }
+
     /// Fuzzy find & get [PasswordEntry] by service
     pub fn fuzzy_find(&self, service: impl AsRef<str>) -> Vec<PasswordEntry> {
         // TODO: Most matched entry should come first
-        self.passwords
-            .clone()
-            .into_iter()
-            .filter(|entry| {
-                rust_fuzzy_search::fuzzy_compare(&entry.service, service.as_ref()) >= 0.5
+        // Each service is compared once, however many entries it has
+        let mut positions = self
+            .index
+            .services()
+            .filter(|(current, _)| {
+                rust_fuzzy_search::fuzzy_compare(current, service.as_ref()) >= 0.5
             })
+            .flat_map(|(_, positions)| positions.iter().copied())
+            .collect::<Vec<_>>();
+        positions.sort_unstable();
+
+        positions
+            .into_iter()
+            .map(|position| self.payload.passwords[position].clone())
             .collect::<Vec<PasswordEntry>>()
     }
 
-    pub fn change_master(&mut self, master: MasterPassword<Verified>) {
-        self.master_password = master;
+    /// Get [PasswordEntry] in the folder (or its subfolders) having all of the tags
+    pub fn filter(&self, folder: Option<impl AsRef<str>>, tags: &[String]) -> Vec<PasswordEntry> {
+        self.payload
+            .passwords
+            .iter()
+            .filter(|entry| folder.as_ref().is_none_or(|folder| entry.in_folder(folder)))
+            .filter(|entry| tags.iter().all(|tag| entry.has_tag(tag)))
+            .cloned()
+            .collect()
+    }
+
+    /// All tags used by entries, along with the number of entries having them
+    pub fn tags(&self) -> BTreeMap<String, usize> {
+        let mut tags = BTreeMap::new();
+        self.payload
+            .passwords
+            .iter()
+            .flat_map(|entry| entry.tags.iter())
+            .for_each(|tag| *tags.entry(tag.clone()).or_insert(0) += 1);
+
+        tags
+    }
+
+    /// Re-encrypt the vault under a new master password as a single transaction.
+    /// The vault is left untouched (encrypted by old master password) if any step fails.
+    pub fn rekey(
+        file_path: impl AsRef<Path>,
+        old_master: &MasterPassword<Verified>,
+        new_master: &MasterPassword<Verified>,
+    ) -> Result<Self, PasswordStoreError> {
+        PasswordStore::rekey_with_checkpoint(file_path, old_master, new_master, |_| Ok(()))
+    }
+
+    /// [PasswordStore::rekey], calling `checkpoint` before each step to inject failures in tests
+    fn rekey_with_checkpoint(
+        file_path: impl AsRef<Path>,
+        old_master: &MasterPassword<Verified>,
+        new_master: &MasterPassword<Verified>,
+        checkpoint: impl Fn(RekeyStep) -> Result<(), PasswordStoreError>,
+    ) -> Result<Self, PasswordStoreError> {
+        let file_path = file_path.as_ref();
+
+        // Decrypt all entries by old master password
+        checkpoint(RekeyStep::Decrypt)?;
+        let mut store = PasswordStore::load(file_path, old_master)?;
+
+        // Re-encrypt entries by new master password, in the same format as vault
+        checkpoint(RekeyStep::Stage)?;
+        store.change_master(new_master)?;
+        let kind = BackendKind::detect(file_path).map_err(PasswordStoreError::Backend)?;
+        store.replace_vault(file_path, kind, checkpoint)?;
+
+        Ok(store)
+    }
+
+    /// Convert the vault into another on-disk format as a single transaction, the vault in
+    /// previous format is kept as backup
+    pub fn migrate(
+        file_path: impl AsRef<Path>,
+        master_password: &MasterPassword<Verified>,
+        kind: BackendKind,
+    ) -> Result<Self, PasswordStoreError> {
+        let store = PasswordStore::load(file_path.as_ref(), master_password)?;
+        store.replace_vault(file_path, kind, |_| Ok(()))?;
+
+        Ok(store)
+    }
+
+    /// Write the vault in the format into a staging file & atomically swap it with the vault,
+    /// once it is verified to decrypt to the same entries.
+    /// The vault is left untouched if any step fails.
+    fn replace_vault(
+        &self,
+        file_path: impl AsRef<Path>,
+        kind: BackendKind,
+        checkpoint: impl Fn(RekeyStep) -> Result<(), PasswordStoreError>,
+    ) -> Result<(), PasswordStoreError> {
+        let file_path = file_path.as_ref();
+        let staging = staging_path(file_path);
+
+        let swap = || -> Result<(), PasswordStoreError> {
+            let mut backend = kind.create(&staging).map_err(PasswordStoreError::Backend)?;
+            self.dump_into(backend.as_mut())?;
+            drop(backend);
+
+            // Staging file must decrypt to the same entries
+            checkpoint(RekeyStep::Verify)?;
+            let staged = PasswordStore::read_file(&staging, &self.master_password())?;
+            if staged.payload != self.payload {
+                return Err(PasswordStoreError::UnableToDecryptError(
+                    "Re-encrypted entries do not match the vault".to_owned(),
+                ));
+            }
+
+            // Atomically replace the vault, old generation is kept as backup
+            checkpoint(RekeyStep::Swap)?;
+            replace_file(&staging, file_path).map_err(PasswordStoreError::UnableToWriteFile)
+        };
+
+        // Rollback by discarding the staging file, vault is still as it was
+        swap().inspect_err(|_| {
+            let _ = std::fs::remove_file(&staging);
+        })
+    }
+
+    /// Change the master password, entries are re-encrypted under a fresh salt on next dump
+    pub fn change_master(
+        &mut self,
+        master: &MasterPassword<Verified>,
+    ) -> Result<(), PasswordStoreError> {
+        self.replace_header(master, VaultHeader::with_kdf(self.header.kdf))
+    }
+
+    /// Switch the vault to another KDF (or its parameters) with a fresh salt.
+    /// Entries are re-encrypted under the newly derived key on next [PasswordStore::dump]
+    pub fn change_kdf(
+        &mut self,
+        kdf: Kdf,
+        master: &MasterPassword<Verified>,
+    ) -> Result<(), PasswordStoreError> {
+        kdf.validate()
+            .map_err(PasswordStoreError::UnableToDeriveKey)?;
+        self.replace_header(master, VaultHeader::with_kdf(kdf))
+    }
+
+    /// Replace the header, deriving the vault key from master password for its KDF & salt
+    fn replace_header(
+        &mut self,
+        master: &MasterPassword<Verified>,
+        header: VaultHeader,
+    ) -> Result<(), PasswordStoreError> {
+        // Sealed secrets can't be decrypted by the new key
+        self.unseal_all()?;
+
+        self.key = master
+            .derive_encryption_key(&header.kdf, &header.salt)
+            .map_err(PasswordStoreError::UnableToDeriveKey)?;
+        self.header = header;
+        Ok(())
+    }
+}
+
+/// Optional column of [print_table], shown besides service & username
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TableColumn {
+    Id,
+    Created,
+    Modified,
+    PasswordChanged,
+    Accessed,
+}
+
+impl TableColumn {
+    fn title(&self) -> &'static str {
+        match self {
+            TableColumn::Id => "ID",
+            TableColumn::Created => "Created",
+            TableColumn::Modified => "Modified",
+            TableColumn::PasswordChanged => "Password changed",
+            TableColumn::Accessed => "Last used",
+        }
+    }
+
+    fn value(&self, entry: &PasswordEntry) -> String {
+        let time = match self {
+            TableColumn::Id => return entry.id.to_string(),
+            TableColumn::Created => entry.created_at,
+            TableColumn::Modified => entry.modified_at,
+            TableColumn::PasswordChanged => entry.password_changed_at,
+            TableColumn::Accessed => entry.last_accessed_at,
+        };
+
+        // Entries older than timestamps (or never used) have no time
+        time.map(format_time).unwrap_or("-".to_string())
     }
 }
 
 /// Provide display table having password entry
-pub fn print_table(passwords: impl AsRef<[PasswordEntry]>) {
+pub fn print_table(passwords: impl AsRef<[PasswordEntry]>, columns: &[TableColumn]) {
     if passwords.as_ref().is_empty() {
         colour::green_ln!("No entry available");
         return;
     }
 
+    let mut title = vec![
+        "Serial no.".cell().bold(true),
+        "Service".cell().bold(true),
+        "Type".cell().bold(true),
+        "Username".cell().bold(true),
+        "Notes".cell().bold(true),
+        "Folder".cell().bold(true),
+        "Tags".cell().bold(true),
+        "Summary".cell().bold(true),
+    ];
+    title.extend(
+        columns
+            .iter()
+            .map(|column| column.title().cell().bold(true)),
+    );
+
     let table = passwords
         .as_ref()
         .iter()
@@ -229,16 +909,16 @@ pub fn print_table(passwords: impl AsRef<[PasswordEntry]>) {
             let mut table = data.table();
             let serial = (index + 1).to_string().cell().justify(Justify::Center);
             table.insert(0, serial);
+            table.extend(
+                columns
+                    .iter()
+                    .map(|column| column.value(data).cell().justify(Justify::Center)),
+            );
             table
         })
         .collect::<Vec<Vec<_>>>()
         .table()
-        .title(vec![
-            "Serial no.".cell().bold(true),
-            "Service".cell().bold(true),
-            "Username".cell().bold(true),
-            "Notes".cell().bold(true),
-        ])
+        .title(title)
         .bold(true);
 
     println!("{}", table.display().expect("Unable to draw table"));
@@ -247,17 +927,22 @@ pub fn print_table(passwords: impl AsRef<[PasswordEntry]>) {
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::pass::entry::PasswordEntry;
+    use crate::pass::attachment::INLINE_ATTACHMENT_SIZE;
+    use crate::pass::backend::{
+        metadata_key, LegacyMasterPassword, LegacyVaultPayload, SealedPayload,
+    };
+    use crate::pass::entry::{LegacyPasswordEntry, Password, PasswordEntry};
+    use serde_encrypt::{serialize::TypedSerialized, shared_key::SharedKey, EncryptedMessage};
+
+    fn test_master_password(password: &str) -> MasterPassword<Verified> {
+        MasterPassword::from_pass(password).unwrap()
+    }
 
     #[test]
     fn test_storage() -> Result<(), PasswordStoreError> {
-        let test_master_pass = MasterPassword {
-            master_pass: Some("Test123@".as_bytes().to_vec()),
-            hash: None,
-            state: std::marker::PhantomData,
-        };
+        let test_master_pass = test_master_password("Test123@");
         // Making a new Password manager
-        let mut manager = PasswordStore::new(TESTING_PASS.to_path_buf(), test_master_pass.clone())?;
+        let mut manager = PasswordStore::new(TESTING_PASS.to_path_buf(), &test_master_pass)?;
 
         let entries = vec![
             PasswordEntry::new(
@@ -283,12 +968,692 @@ mod test {
         manager.dump(TESTING_PASS.to_path_buf())?;
 
         // Loading contents from database
-        let decrypted_manager = PasswordStore::load(TESTING_PASS.to_path_buf(), test_master_pass)?;
+        let decrypted_manager = PasswordStore::load(TESTING_PASS.to_path_buf(), &test_master_pass)?;
 
-        assert_eq!(manager.passwords[0], decrypted_manager.passwords[0]);
-        assert_eq!(manager.passwords[1], decrypted_manager.passwords[1]);
+        assert_eq!(
+            manager.payload.passwords[0],
+            decrypted_manager.payload.passwords[0]
+        );
+        assert_eq!(
+            manager.payload.passwords[1],
+            decrypted_manager.payload.passwords[1]
+        );
 
         std::fs::remove_file(TESTING_PASS.as_path()).unwrap();
+        let _ = std::fs::remove_file(backup_path(TESTING_PASS.as_path()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_legacy_vault_migration() -> Result<(), PasswordStoreError> {
+        let legacy_path = XDG_BASE
+            .place_state_file("testing_legacy.db")
+            .expect("Unable to place testing_legacy.db file in state");
+        let test_master_pass = test_master_password("Test123@");
+        let legacy_payload = LegacyVaultPayload {
+            passwords: vec![LegacyPasswordEntry {
+                service: "pass 1".to_owned(),
+                username: Some("Ishan".to_owned()),
+                password: Password::new(Some("EntryPassword")),
+                other: None,
+            }],
+            master_password: LegacyMasterPassword {
+                master_pass: Some("Test123@".into()),
+                hash: None,
+            },
+        };
+
+        // Legacy vaults have no header & persist the master password in the payload
+        let header = VaultHeader::legacy();
+        let key = SharedKey::new(
+            *test_master_pass
+                .derive_encryption_key(&header.kdf, &header.salt)
+                .unwrap()
+                .expose(),
+        );
+        let legacy_data = legacy_payload.encrypt(&key).unwrap().serialize();
+        std::fs::write(&legacy_path, legacy_data).unwrap();
+
+        let legacy_entries = legacy_payload
+            .passwords
+            .iter()
+            .cloned()
+            .map(PasswordEntry::from)
+            .collect::<Vec<_>>();
+
+        let migrated = PasswordStore::load(&legacy_path, &test_master_pass)?;
+        assert!(!migrated.payload.passwords[0].id.is_nil());
+        let legacy_entries = legacy_entries
+            .into_iter()
+            .zip(&migrated.payload.passwords)
+            .map(|(mut entry, migrated)| {
+                entry.id = migrated.id;
+                entry
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(migrated.payload.passwords, legacy_entries);
+        assert_eq!(
+            migrated.payload.passwords[0].get_pass_str(),
+            "EntryPassword"
+        );
+        assert_ne!(migrated.header.salt, VaultHeader::legacy().salt);
+
+        // Latest header with the random salt is written back on first unlock
+        let content = std::fs::read(&legacy_path).unwrap();
+        let (header, _) = VaultHeader::decode(&content)
+            .map_err(|err| PasswordStoreError::Backend(BackendError::InvalidHeader(err)))?
+            .expect("Vault must be migrated");
+        assert_eq!(header.salt, migrated.header.salt);
+        assert_eq!(header.version, VAULT_VERSION);
+
+        // Along with the key check of master password
+        let key = test_master_pass
+            .derive_encryption_key(&header.kdf, &header.salt)
+            .unwrap();
+        assert!(header.verify_key(key.expose()));
+
+        let reloaded = PasswordStore::load(&legacy_path, &test_master_pass)?;
+        assert_eq!(reloaded.payload.passwords, legacy_entries);
+
+        std::fs::remove_file(&legacy_path).unwrap();
+        let _ = std::fs::remove_file(backup_path(&legacy_path));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_master_password_not_persisted() -> Result<(), PasswordStoreError> {
+        let vault_path = XDG_BASE
+            .place_state_file("testing_not_persisted.db")
+            .expect("Unable to place testing_not_persisted.db file in state");
+        let master_secret = "MasterSecret#42";
+        let manager = PasswordStore::with_payload(
+            VaultPayload {
+                passwords: vec![PasswordEntry::new(
+                    "pass 1".to_owned(),
+                    Some("Ishan".to_owned()),
+                    Some("EntryPassword"),
+                    Some("hello notes".to_owned()),
+                )],
+                ..Default::default()
+            },
+            &test_master_password(master_secret),
+            VaultHeader::new(),
+        )?;
+
+        manager.dump(&vault_path)?;
+        let content = std::fs::read(&vault_path).unwrap();
+        let (_, encrypted_data) = VaultHeader::decode(&content)
+            .unwrap()
+            .expect("Vault has header");
+
+        // Decrypt the raw metadata to inspect exactly what is persisted
+        let key = SharedKey::new(*metadata_key(&manager.key).expose());
+        let encrypted_content = EncryptedMessage::deserialize(encrypted_data.to_vec()).unwrap();
+        let decrypted_payload = SealedPayload::decrypt_ref(&encrypted_content, &key).unwrap();
+        let decrypted_payload = decrypted_payload.as_slice();
+
+        // Secrets are stored either as raw bytes or as a CBOR array of u8
+        let contains = |secret: &[u8]| {
+            let cbor_array = secret
+                .iter()
+                .flat_map(|&byte| match byte < 24 {
+                    true => vec![byte],
+                    false => vec![0x18, byte],
+                })
+                .collect::<Vec<u8>>();
+
+            [secret.to_vec(), cbor_array].iter().any(|needle| {
+                decrypted_payload
+                    .windows(needle.len())
+                    .any(|window| window == needle.as_slice())
+            })
+        };
+        // Password of entry is sealed apart from its metadata
+        assert!(contains(b"hello notes"));
+        assert!(!contains(b"EntryPassword"));
+        assert!(!contains(master_secret.as_bytes()));
+
+        std::fs::remove_file(&vault_path).unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_change_kdf() -> Result<(), PasswordStoreError> {
+        let kdf_path = XDG_BASE
+            .place_state_file("testing_kdf.db")
+            .expect("Unable to place testing_kdf.db file in state");
+        let test_master_pass = test_master_password("Test123@");
+        let mut manager = PasswordStore::with_payload(
+            VaultPayload {
+                passwords: vec![PasswordEntry::new(
+                    "pass 1".to_owned(),
+                    Some("Ishan".to_owned()),
+                    None::<&str>,
+                    None,
+                )],
+                ..Default::default()
+            },
+            &test_master_pass,
+            VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 }),
+        )?;
+        manager.dump(&kdf_path)?;
+
+        // Invalid parameters are rejected before touching the vault
+        assert!(manager
+            .change_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 0 }, &test_master_pass)
+            .is_err());
+
+        let old_salt = manager.header.salt.clone();
+        let kdf = Kdf::Argon2id {
+            memory_kib: 1024,
+            iterations: 2,
+            parallelism: 1,
+        };
+        manager.change_kdf(kdf, &test_master_pass)?;
+        manager.dump(&kdf_path)?;
+
+        let reloaded = PasswordStore::load(&kdf_path, &test_master_pass)?;
+        assert_eq!(reloaded.header.kdf, kdf);
+        assert_ne!(reloaded.header.salt, old_salt);
+        assert_eq!(reloaded.payload, manager.payload);
+
+        std::fs::remove_file(&kdf_path).unwrap();
+        let _ = std::fs::remove_file(backup_path(&kdf_path));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_backup_recovery() -> Result<(), PasswordStoreError> {
+        let vault_path = XDG_BASE
+            .place_state_file("testing_backup.db")
+            .expect("Unable to place testing_backup.db file in state");
+        let test_master_pass = test_master_password("Test123@");
+        let mut manager = PasswordStore::with_payload(
+            VaultPayload::default(),
+            &test_master_pass,
+            VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 }),
+        )?;
+        manager.push_entry(PasswordEntry::new(
+            "pass 1".to_owned(),
+            None,
+            None::<&str>,
+            None,
+        ));
+        manager.dump(&vault_path)?;
+        let first_generation = manager.payload.clone();
+
+        manager.push_entry(PasswordEntry::new(
+            "pass 2".to_owned(),
+            None,
+            None::<&str>,
+            None,
+        ));
+        manager.dump(&vault_path)?;
+
+        // Previous generation is kept as backup & no staging file is left behind
+        let backup = PasswordStore::read_file(backup_path(&vault_path), &test_master_pass)?;
+        assert_eq!(backup.payload, first_generation);
+        assert!(!vault_path.with_file_name("testing_backup.db.tmp").exists());
+
+        // Corrupted vault is restored from its backup
+        std::fs::write(&vault_path, b"corrupted").unwrap();
+        assert!(PasswordStore::read_file(&vault_path, &test_master_pass).is_err());
+
+        let restored = PasswordStore::restore_backup(&vault_path, &test_master_pass)?;
+        assert_eq!(restored.payload, first_generation);
+
+        let reloaded = PasswordStore::load(&vault_path, &test_master_pass)?;
+        assert_eq!(reloaded.payload, first_generation);
+
+        std::fs::remove_file(&vault_path).unwrap();
+        std::fs::remove_file(backup_path(&vault_path)).unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rekey_rollback() -> Result<(), PasswordStoreError> {
+        let vault_path = XDG_BASE
+            .place_state_file("testing_rekey.db")
+            .expect("Unable to place testing_rekey.db file in state");
+        let staging_path = vault_path.with_file_name("testing_rekey.db.tmp");
+        let old_master = test_master_password("Test123@");
+        let new_master = test_master_password("NewTest123@");
+
+        let manager = PasswordStore::with_payload(
+            VaultPayload {
+                passwords: vec![PasswordEntry::new(
+                    "pass 1".to_owned(),
+                    Some("Ishan".to_owned()),
+                    Some("EntryPassword"),
+                    None,
+                )],
+                ..Default::default()
+            },
+            &old_master,
+            VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 }),
+        )?;
+        manager.dump(&vault_path)?;
+        let original = std::fs::read(&vault_path).unwrap();
+
+        // Failure at any step leaves the vault encrypted by old master password
+        for step in [
+            RekeyStep::Decrypt,
+            RekeyStep::Stage,
+            RekeyStep::Verify,
+            RekeyStep::Swap,
+        ] {
+            let result = PasswordStore::rekey_with_checkpoint(
+                &vault_path,
+                &old_master,
+                &new_master,
+                |current| match current == step {
+                    true => Err(PasswordStoreError::NothingToDo),
+                    false => Ok(()),
+                },
+            );
+
+            assert!(result.is_err(), "Rekey must fail at {step:?}");
+            assert_eq!(std::fs::read(&vault_path).unwrap(), original);
+            assert!(!staging_path.exists());
+            assert!(PasswordStore::read_file(&vault_path, &old_master).is_ok());
+        }
+
+        let rekeyed = PasswordStore::rekey(&vault_path, &old_master, &new_master)?;
+        assert_eq!(rekeyed.payload, manager.payload);
+        assert_ne!(rekeyed.header.salt, manager.header.salt);
+        assert!(!staging_path.exists());
+
+        // Only new master password unlocks the vault, old generation is kept as backup
+        assert!(PasswordStore::read_file(&vault_path, &old_master).is_err());
+        let reloaded = PasswordStore::load(&vault_path, &new_master)?;
+        assert_eq!(reloaded.payload, manager.payload);
+        assert_eq!(std::fs::read(backup_path(&vault_path)).unwrap(), original);
+
+        std::fs::remove_file(&vault_path).unwrap();
+        std::fs::remove_file(backup_path(&vault_path)).unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_update() -> Result<(), PasswordStoreError> {
+        let mut manager = PasswordStore::with_payload(
+            VaultPayload {
+                passwords: vec![
+                    PasswordEntry::new("pass 1".to_owned(), None, None::<&str>, None),
+                    PasswordEntry::new(
+                        "pass 2".to_owned(),
+                        Some("Tanveer".to_owned()),
+                        None::<&str>,
+                        None,
+                    ),
+                ],
+                ..Default::default()
+            },
+            &test_master_password("Test123@"),
+            VaultHeader::new(),
+        )?;
+
+        let old_entry = manager.payload.passwords[0].clone();
+        let mut new_entry = old_entry.clone();
+        new_entry.change_username(Some("Ishan".to_owned()));
+        new_entry.change_password("NewPassword");
+        manager.update(&old_entry, new_entry.clone())?;
+
+        // Modification time is recorded by update
+        assert!(manager.payload.passwords[0].modified_at > old_entry.modified_at);
+        new_entry.modified_at = manager.payload.passwords[0].modified_at;
+        assert_eq!(manager.payload.passwords[0], new_entry);
+        assert_eq!(manager.payload.passwords[0].get_pass_str(), "NewPassword");
+
+        // Updating into an already existing service & username is rejected
+        let mut dupe_entry = new_entry.clone();
+        dupe_entry.change_username(Some("Tanveer".to_owned()));
+        dupe_entry.service = "pass 2".to_owned();
+        assert!(manager.update(&new_entry, dupe_entry).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_to_sqlite() -> Result<(), PasswordStoreError> {
+        let vault_path = XDG_BASE
+            .place_state_file("testing_migrate.db")
+            .expect("Unable to place testing_migrate.db file in state");
+        let test_master_pass = test_master_password("Test123@");
+
+        let manager = PasswordStore::with_payload(
+            VaultPayload {
+                passwords: ["pass 1", "pass 2", "pass 3"]
+                    .map(|service| PasswordEntry::new(service.to_owned(), None, Some("pass"), None))
+                    .to_vec(),
+                ..Default::default()
+            },
+            &test_master_pass,
+            VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 }),
+        )?;
+        manager.dump(&vault_path)?;
+        let original = std::fs::read(&vault_path).unwrap();
+
+        // Vault is converted in place, previous format is kept as backup
+        let migrated = PasswordStore::migrate(&vault_path, &test_master_pass, BackendKind::Sqlite)?;
+        assert_eq!(migrated.payload, manager.payload);
+        assert_eq!(
+            BackendKind::detect(&vault_path).map_err(PasswordStoreError::Backend)?,
+            BackendKind::Sqlite
+        );
+        assert_eq!(std::fs::read(backup_path(&vault_path)).unwrap(), original);
+        assert!(
+            PasswordStore::read_header(&vault_path)?.is_some_and(|header| header.has_key_check())
+        );
+
+        // Removed & updated entries are persisted by their rows
+        let mut loaded = PasswordStore::load(&vault_path, &test_master_pass)?;
+        loaded.payload.passwords.remove(1);
+        loaded.payload.passwords[0].service = "pass 0".to_owned();
+        loaded.dump(&vault_path)?;
+        let reloaded = PasswordStore::read_file(&vault_path, &test_master_pass)?;
+        assert_eq!(reloaded.payload, loaded.payload);
+
+        // Rekey keeps the format of vault
+        let new_master = test_master_password("NewTest123@");
+        PasswordStore::rekey(&vault_path, &test_master_pass, &new_master)?;
+        assert_eq!(
+            BackendKind::detect(&vault_path).map_err(PasswordStoreError::Backend)?,
+            BackendKind::Sqlite
+        );
+        let rekeyed = PasswordStore::read_file(&vault_path, &new_master)?;
+        assert_eq!(rekeyed.payload, loaded.payload);
+
+        std::fs::remove_file(&vault_path).unwrap();
+        std::fs::remove_file(backup_path(&vault_path)).unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sqlite_rows() -> Result<(), PasswordStoreError> {
+        let vault_path = XDG_BASE
+            .place_state_file("testing_rows.db")
+            .expect("Unable to place testing_rows.db file in state");
+        let test_master_pass = test_master_password("Test123@");
+
+        let manager = PasswordStore::with_payload(
+            VaultPayload {
+                passwords: ["pass 1", "pass 2", "pass 3"]
+                    .map(|service| PasswordEntry::new(service.to_owned(), None, Some("pass"), None))
+                    .to_vec(),
+                ..Default::default()
+            },
+            &test_master_pass,
+            VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 }),
+        )?;
+        let mut backend = BackendKind::Sqlite
+            .create(&vault_path)
+            .map_err(PasswordStoreError::Backend)?;
+        manager.dump_into(backend.as_mut())?;
+        drop(backend);
+
+        let rows = || -> Vec<(String, Vec<u8>)> {
+            let connection = rusqlite::Connection::open(&vault_path).unwrap();
+            let mut statement = connection
+                .prepare("SELECT id, entry FROM entries ORDER BY position")
+                .unwrap();
+            let rows = statement
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap();
+            rows
+        };
+        let written = rows();
+
+        // Only the changed row is rewritten across a load & dump
+        let mut loaded = PasswordStore::load(&vault_path, &test_master_pass)?;
+        loaded.payload.passwords[0].service = "pass 0".to_owned();
+        loaded.dump(&vault_path)?;
+        let rewritten = rows();
+        assert_ne!(rewritten[0], written[0]);
+        assert_eq!(rewritten[1..], written[1..]);
+
+        // Entry moved into the row of another entry is refused
+        let connection = rusqlite::Connection::open(&vault_path).unwrap();
+        connection
+            .execute(
+                "UPDATE entries SET entry = ?1 WHERE id = ?2",
+                rusqlite::params![written[1].1, written[2].0],
+            )
+            .unwrap();
+        drop(connection);
+        assert!(matches!(
+            PasswordStore::read_file(&vault_path, &test_master_pass),
+            Err(PasswordStoreError::Backend(BackendError::MismatchedId(_)))
+        ));
+
+        std::fs::remove_file(&vault_path).unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_metadata() -> Result<(), PasswordStoreError> {
+        let vault_path = XDG_BASE
+            .place_state_file("testing_metadata.db")
+            .expect("Unable to place testing_metadata.db file in state");
+        let test_master_pass = test_master_password("Test123@");
+
+        let manager = PasswordStore::with_payload(
+            VaultPayload {
+                passwords: vec![
+                    PasswordEntry::new("pass 1".to_owned(), None, Some("first"), None),
+                    PasswordEntry::new("pass 2".to_owned(), None, Some("second"), None),
+                ],
+                ..Default::default()
+            },
+            &test_master_pass,
+            VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 }),
+        )?;
+
+        for kind in [BackendKind::File, BackendKind::Sqlite, BackendKind::Log] {
+            let mut backend = kind
+                .create(&vault_path)
+                .map_err(PasswordStoreError::Backend)?;
+            manager.dump_into(backend.as_mut())?;
+            drop(backend);
+
+            // Only metadata is decrypted, secrets are decrypted on use
+            let mut loaded = PasswordStore::load_metadata(&vault_path, &test_master_pass)?;
+            assert!(loaded
+                .payload
+                .passwords
+                .iter()
+                .all(PasswordEntry::is_sealed));
+            assert!(loaded.get("pass 2")[0].is_sealed());
+
+            let id = loaded.payload.passwords[1].id;
+            assert_eq!(loaded.unseal(id)?.get_pass_str(), "second");
+            assert!(loaded.payload.passwords[0].is_sealed());
+
+            // Entries still sealed are dumped as they are
+            loaded.entry_mut(id)?.mark_accessed();
+            loaded.dump(&vault_path)?;
+            let reloaded = PasswordStore::load(&vault_path, &test_master_pass)?;
+            assert_eq!(reloaded.payload.passwords[0], manager.payload.passwords[0]);
+            assert_eq!(reloaded.get_by_id(id)?.get_pass_str(), "second");
+        }
+
+        std::fs::remove_file(&vault_path).unwrap();
+        std::fs::remove_file(backup_path(&vault_path)).ok();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_entry_ids() -> Result<(), PasswordStoreError> {
+        let vault_path = XDG_BASE
+            .place_state_file("testing_ids.db")
+            .expect("Unable to place testing_ids.db file in state");
+        let test_master_pass = test_master_password("Test123@");
+
+        // Entries of a vault before ids, identical except of their passwords
+        let mut manager = PasswordStore::with_payload(
+            VaultPayload {
+                passwords: vec![
+                    PasswordEntry::new("pass 1".to_owned(), None, Some("first"), None),
+                    PasswordEntry::new("pass 1".to_owned(), None, Some("second"), None),
+                ],
+                ..Default::default()
+            },
+            &test_master_pass,
+            VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 }),
+        )?;
+        manager
+            .payload
+            .passwords
+            .iter_mut()
+            .for_each(|entry| entry.id = Uuid::nil());
+        manager.dump(&vault_path)?;
+
+        // Ids are backfilled & persisted on load
+        let mut loaded = PasswordStore::load(&vault_path, &test_master_pass)?;
+        let ids = loaded
+            .payload
+            .passwords
+            .iter()
+            .map(|entry| entry.id)
+            .collect::<Vec<_>>();
+        assert!(ids.iter().all(|id| !id.is_nil()));
+        assert_ne!(ids[0], ids[1]);
+        let reloaded = PasswordStore::read_file(&vault_path, &test_master_pass)?;
+        assert_eq!(reloaded.payload, loaded.payload);
+
+        // Entry is targeted by id, even if another entry looks the same
+        assert_eq!(loaded.get_by_id(ids[1])?.get_pass_str(), "second");
+        assert!(loaded.get_by_id(Uuid::new_v4()).is_err());
+
+        let mut renamed = loaded.get_by_id(ids[1])?;
+        renamed.service = "pass 2".to_owned();
+        loaded.update(&loaded.get_by_id(ids[1])?, renamed)?;
+        assert_eq!(loaded.payload.passwords[0].service, "pass 1");
+        assert_eq!(loaded.payload.passwords[1].service, "pass 2");
+
+        // Renamed entry is found by its new service only
+        assert_eq!(loaded.get("pass 1").len(), 1);
+        assert_eq!(loaded.get("pass 2")[0].id, ids[1]);
+        assert_eq!(loaded.fuzzy_find("pass").len(), 2);
+        assert!(!loaded.insert_entry(loaded.get_by_id(ids[1])?));
+
+        std::fs::remove_file(&vault_path).unwrap();
+        let _ = std::fs::remove_file(backup_path(&vault_path));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_garbage_of_backup() -> Result<(), PasswordStoreError> {
+        let dir = std::env::temp_dir().join(format!("pass_rs_gc_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let vault_path = dir.join("passwords.db");
+        let blob_dir = attachments_dir(&vault_path);
+        let blob_count = || std::fs::read_dir(&blob_dir).unwrap().count();
+
+        let mut manager = PasswordStore::with_payload(
+            VaultPayload::default(),
+            &test_master_password("Test123@"),
+            VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 }),
+        )?;
+        manager.push_entry(PasswordEntry::new(
+            "pass 1".to_owned(),
+            None,
+            None::<&str>,
+            None,
+        ));
+        let id = manager.payload.passwords[0].id;
+        let size = INLINE_ATTACHMENT_SIZE as usize + 1;
+
+        manager.attach(id, "kubeconfig", vec![1; size], &blob_dir)?;
+        manager.dump(&vault_path)?;
+        manager.attach(id, "kubeconfig", vec![2; size], &blob_dir)?;
+        manager.dump(&vault_path)?;
+
+        // Replaced blob is still referenced by the backup
+        assert_eq!(manager.collect_garbage(&vault_path)?, 0);
+        assert_eq!(blob_count(), 2);
+
+        // Blob is collected once no generation references it
+        manager.dump(&vault_path)?;
+        assert_eq!(manager.collect_garbage(&vault_path)?, 1);
+        assert_eq!(blob_count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_garbage_of_removed() -> Result<(), PasswordStoreError> {
+        let dir = std::env::temp_dir().join(format!("pass_rs_removed_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let vault_path = dir.join("passwords.db");
+        let blob_dir = attachments_dir(&vault_path);
+        let blob_count = || std::fs::read_dir(&blob_dir).unwrap().count();
+        let master = test_master_password("Test123@");
+
+        for kind in [BackendKind::File, BackendKind::Sqlite] {
+            let mut manager = PasswordStore::with_payload(
+                VaultPayload::default(),
+                &master,
+                VaultHeader::with_kdf(Kdf::Pbkdf2HmacSha256 { iterations: 1000 }),
+            )?;
+            for service in ["pass 1", "pass 2"] {
+                manager.push_entry(PasswordEntry::new(
+                    service.to_owned(),
+                    None,
+                    None::<&str>,
+                    None,
+                ));
+            }
+            let size = INLINE_ATTACHMENT_SIZE as usize + 1;
+            let ids = manager.payload.passwords.iter().map(|entry| entry.id);
+            for (id, byte) in ids.collect::<Vec<_>>().into_iter().zip(1..) {
+                manager.attach(id, "kubeconfig", vec![byte; size], &blob_dir)?;
+            }
+            let mut backend = kind
+                .create(&vault_path)
+                .map_err(PasswordStoreError::Backend)?;
+            manager.dump_into(backend.as_mut())?;
+            drop(backend);
+            manager.dump(&vault_path)?;
+            // Backup of SQLite vault is left by migration & never rotated
+            if matches!(kind, BackendKind::Sqlite) {
+                std::fs::copy(&vault_path, backup_path(&vault_path)).unwrap();
+            }
+
+            // Blob is referenced by the backup until the vault is dumped again
+            let removed = manager.payload.passwords[..1].to_vec();
+            manager.remove(removed, &vault_path)?;
+            assert_eq!(blob_count(), 2);
+
+            let manager = PasswordStore::load(&vault_path, &master)?;
+            assert_eq!(manager.payload.settings.removed_blobs.len(), 1);
+            manager.dump(&vault_path)?;
+            assert_eq!(blob_count(), 1);
+            assert!(PasswordStore::load(&vault_path, &master)?
+                .payload
+                .settings
+                .removed_blobs
+                .is_empty());
+
+            std::fs::remove_dir_all(&dir).unwrap();
+            std::fs::create_dir_all(&dir).unwrap();
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
 
         Ok(())
     }
@@ -1,11 +1,16 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Local, Utc};
 use clipboard::{ClipboardContext, ClipboardProvider};
 use colour::e_prnt_ln;
 use once_cell::sync::Lazy;
 use ring::rand::{SecureRandom, SystemRandom};
+use zeroize::Zeroize;
 
 use inquire::{validator::Validation, CustomType, PasswordDisplayMode, Text};
 
-use super::{entry::PasswordEntry, store::PasswordStoreError};
+use super::{entry::PasswordEntry, lock, store::PasswordStoreError, vault::Vault};
 type InquirePassword<'a> = inquire::Password<'a>;
 
 // Making Base directories by xdg config
@@ -14,14 +19,12 @@ pub(crate) static XDG_BASE: Lazy<xdg::BaseDirectories> = Lazy::new(|| {
     xdg::BaseDirectories::with_prefix(APP_NAME).expect("Failed to initialised XDG BaseDirectories")
 });
 
-// $HOME/.local/state/pass
-pub(crate) static PASS_DIR_PATH: Lazy<std::path::PathBuf> = Lazy::new(|| XDG_BASE.get_state_home());
+// $HOME/.local/state/pass, or directory of the selected vault
+pub(crate) static PASS_DIR_PATH: Lazy<std::path::PathBuf> =
+    Lazy::new(|| Vault::selected().dir().to_path_buf());
 
 #[derive(Debug, thiserror::Error)]
 pub enum UtilError {
-    #[error("Bcrypt Error: {0}")]
-    BcryptError(String),
-
     #[error("Cannot read from console due to IO error")]
     UnableToReadFromConsole,
 }
@@ -34,10 +37,96 @@ pub fn get_random_salt() -> [u8; 16] {
     salt
 }
 
-// Generate hash for given content
-pub fn password_hash(content: impl AsRef<[u8]>) -> Result<String, UtilError> {
-    bcrypt::hash(content.as_ref(), bcrypt::DEFAULT_COST)
-        .map_err(|_| UtilError::BcryptError(String::from("Unable to hash password")))
+/// Path of the previous generation of a file, i.e. `<file>.bak`
+pub fn backup_path(path: impl AsRef<Path>) -> PathBuf {
+    sibling_path(path, "bak")
+}
+
+/// Path next to the file, having the extension appended to its name
+fn sibling_path(path: impl AsRef<Path>, extension: &str) -> PathBuf {
+    let path = path.as_ref();
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(extension);
+    path.with_file_name(name)
+}
+
+/// Path of the staging file of a file, i.e. `<file>.tmp`
+pub fn staging_path(path: impl AsRef<Path>) -> PathBuf {
+    sibling_path(path, "tmp")
+}
+
+/// Write content into a staging file next to `path` & flush it to disk.
+/// Returns the path of staging file, which is later moved over `path` by [replace_file]
+pub fn write_staging_file(
+    path: impl AsRef<Path>,
+    content: impl AsRef<[u8]>,
+) -> std::io::Result<PathBuf> {
+    let staging = staging_path(path);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(&staging)?;
+    file.write_all(content.as_ref())?;
+    file.sync_all()?;
+
+    Ok(staging)
+}
+
+/// Atomically move the staging file over `path`, keeping the previous generation as `<file>.bak`
+pub fn replace_file(staging: impl AsRef<Path>, path: impl AsRef<Path>) -> std::io::Result<()> {
+    let path = path.as_ref();
+
+    // Empty file (of a just created vault) has no generation worth keeping
+    if path.metadata().is_ok_and(|metadata| metadata.len() > 0) {
+        let backup = backup_path(path);
+        let backup_staging = staging_path(&backup);
+        if backup_staging.exists() {
+            std::fs::remove_file(&backup_staging)?;
+        }
+
+        // Hard link keeps the current generation alive after rename, copy if links are unsupported
+        if std::fs::hard_link(path, &backup_staging).is_err() {
+            std::fs::copy(path, &backup_staging)?;
+            std::fs::File::open(&backup_staging)?.sync_all()?;
+        }
+        std::fs::rename(&backup_staging, backup)?;
+    }
+
+    std::fs::rename(staging, path)?;
+    sync_parent_dir(path)
+}
+
+/// Crash-safe write: content is flushed to a staging file which then atomically replaces `path`
+pub fn atomic_write(path: impl AsRef<Path>, content: impl AsRef<[u8]>) -> std::io::Result<()> {
+    let staging = write_staging_file(path.as_ref(), content)?;
+
+    replace_file(&staging, path).inspect_err(|_| {
+        let _ = std::fs::remove_file(&staging);
+    })
+}
+
+/// Flush the directory entry of file, so that a rename survives a crash
+pub fn sync_parent_dir(path: impl AsRef<Path>) -> std::io::Result<()> {
+    #[cfg(unix)]
+    if let Some(dir) = path.as_ref().parent() {
+        let dir = match dir.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => dir,
+        };
+        std::fs::File::open(dir)?.sync_all()?;
+    }
+
+    #[cfg(not(unix))]
+    let _ = path;
+
+    Ok(())
 }
 
 pub fn input_master_pass(message: impl AsRef<str>) -> Result<String, UtilError> {
@@ -157,16 +246,62 @@ pub fn input_number(message: impl AsRef<str>) -> Result<usize, UtilError> {
         .map_err(|_| UtilError::UnableToReadFromConsole)
 }
 
+/// Time in the local timezone, precise to minutes
+pub fn format_time(time: DateTime<Utc>) -> String {
+    time.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+/// Size in bytes with a binary unit, e.g. `1.5 MiB`
+pub fn format_size(size: u64) -> String {
+    let units = ["KiB", "MiB", "GiB"];
+
+    let mut value = size as f64;
+    let mut unit = "B";
+    for next in units {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+
+    match unit {
+        "B" => format!("{size} B"),
+        _ => format!("{value:.1} {unit}"),
+    }
+}
+
 // Set content to clipboard
-pub fn copy_to_clipboard(password: String) -> anyhow::Result<()> {
+pub fn copy_to_clipboard(password: impl AsRef<str>) -> anyhow::Result<()> {
     let mut ctx =
         ClipboardContext::new().map_err(|_| anyhow::anyhow!("Unable to initialize clipboard"))?;
-    ctx.set_contents(password)
+    ctx.set_contents(password.as_ref().to_owned())
         .map_err(|_| anyhow::anyhow!("Unable to set clipboard contents"))?;
 
     // Get method is neccessary for some OS. (Refer to this issue: https://github.com/aweinstock314/rust-clipboard/issues/86)
     ctx.get_contents()
-        .map_err(|_| anyhow::anyhow!("Unable to get clipboard contents"))?;
+        .map_err(|_| anyhow::anyhow!("Unable to get clipboard contents"))?
+        .zeroize();
+    Ok(())
+}
+
+/// Copy secret to clipboard. Secret is never printed here, so without a clipboard (e.g. over
+/// SSH) it fails unless the caller prints the secret for `--print`
+pub fn copy_secret(
+    name: impl AsRef<str>,
+    secret: impl AsRef<str>,
+    is_printed: bool,
+) -> anyhow::Result<()> {
+    let name = name.as_ref();
+
+    match copy_to_clipboard(secret.as_ref()) {
+        Ok(()) => colour::green_ln!("{} copied to clipboard", name),
+        Err(err) if is_printed => colour::e_yellow_ln!("{err}, so {name} is only printed"),
+        Err(err) => anyhow::bail!("{err}, print {name} by `pass_rs get --print` instead"),
+    }
+
     Ok(())
 }
 
@@ -184,7 +319,7 @@ pub fn print_pass_entry_info(pass_entries: impl AsRef<[PasswordEntry]>) {
     let pass_entries = pass_entries.as_ref();
     if pass_entries.is_empty() {
         colour::e_red_ln!("No entries");
-        std::process::exit(1);
+        lock::exit(1);
     } else if pass_entries.len() == 1 {
         pass_entries.iter().for_each(|entry| {
             colour::green_ln!(
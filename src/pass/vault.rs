@@ -0,0 +1,368 @@
+use std::path::{Path, PathBuf};
+
+use once_cell::sync::{Lazy, OnceCell};
+
+use super::attachment::attachments_dir;
+use super::util::{backup_path, staging_path, XDG_BASE};
+
+/// Name of the vault stored directly in the pass directory
+pub const DEFAULT_VAULT: &str = "default";
+
+// $HOME/.local/state/pass/vaults
+pub static VAULTS_DIR_PATH: Lazy<PathBuf> = Lazy::new(|| XDG_BASE.get_state_home().join("vaults"));
+
+// $HOME/.config/pass/vault, having name or path of the vault chosen by `pass_rs vault use`
+pub static CURRENT_VAULT_FILE: Lazy<PathBuf> =
+    Lazy::new(|| XDG_BASE.get_config_home().join("vault"));
+
+/// Vault used by this run of pass, every store path is resolved from it
+static SELECTED_VAULT: OnceCell<Vault> = OnceCell::new();
+
+#[derive(Debug, thiserror::Error)]
+pub enum VaultError {
+    #[error("Invalid vault name '{0}', only letters, digits, '-' & '_' are allowed")]
+    InvalidName(String),
+
+    #[error("Vault '{0}' already exists")]
+    AlreadyExists(String),
+
+    #[error("Vault '{0}' does not exist")]
+    NotFound(String),
+
+    #[error("Only named vaults can be deleted, use `pass_rs reset --hard` for '{0}'")]
+    UnableToDelete(String),
+
+    #[error(
+        "Vault '{0}' is given by path & its directory may have other files, remove it manually"
+    )]
+    NotOwned(String),
+
+    #[error("Vault is already selected")]
+    AlreadySelected,
+
+    #[error("Unable to read current vault: {0}")]
+    UnableToRead(#[source] std::io::Error),
+
+    #[error("Unable to write current vault: {0}")]
+    UnableToWrite(#[source] std::io::Error),
+
+    #[error("Unable to create vault directory: {0}")]
+    UnableToCreateDir(#[source] std::io::Error),
+
+    #[error("Unable to remove vault: {0}")]
+    UnableToRemove(#[source] std::io::Error),
+}
+
+/// A directory holding the store files of a vault, each vault has its own master password
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vault {
+    /// Name of vault, `None` if the vault is given by path
+    name: Option<String>,
+
+    /// Directory having `passwords.db`
+    dir: PathBuf,
+}
+
+impl Vault {
+    /// Vault living in `$XDG_STATE_HOME/pass`, used unless another vault is chosen
+    pub fn default_vault() -> Self {
+        Vault {
+            name: Some(DEFAULT_VAULT.to_owned()),
+            dir: XDG_BASE.get_state_home().components().collect(),
+        }
+    }
+
+    /// Named vault living in `$XDG_STATE_HOME/pass/vaults/<name>`
+    pub fn named(name: impl AsRef<str>) -> Result<Self, VaultError> {
+        let name = name.as_ref();
+
+        if name == DEFAULT_VAULT {
+            return Ok(Vault::default_vault());
+        }
+
+        let is_valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !is_valid {
+            return Err(VaultError::InvalidName(name.to_owned()));
+        }
+
+        Ok(Vault {
+            name: Some(name.to_owned()),
+            dir: VAULTS_DIR_PATH.join(name),
+        })
+    }
+
+    /// Vault living in any directory, e.g. a synced folder
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+
+        // Expand `~` as shells do not expand it inside of env variables & config
+        let path = match (path.strip_prefix("~"), std::env::var_os("HOME")) {
+            (Ok(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => path.to_path_buf(),
+        };
+
+        Vault {
+            name: None,
+            dir: std::path::absolute(&path).unwrap_or(path),
+        }
+    }
+
+    /// Parse `<name|path>`, anything looking like a path is used as directory of vault
+    pub fn parse(value: impl AsRef<str>) -> Result<Self, VaultError> {
+        let value = value.as_ref();
+        let is_path = value.contains('/')
+            || value.contains(std::path::MAIN_SEPARATOR)
+            || value.starts_with('.')
+            || value.starts_with('~');
+
+        match is_path {
+            true => Ok(Vault::from_path(value)),
+            false => Vault::named(value),
+        }
+    }
+
+    /// Vault given by `--vault`/`PASS_RS_VAULT`, else the one chosen by `vault use`, else default
+    pub fn resolve(vault: Option<&str>) -> Result<Self, VaultError> {
+        if let Some(vault) = vault {
+            return Vault::parse(vault);
+        }
+
+        if !CURRENT_VAULT_FILE.exists() {
+            return Ok(Vault::default_vault());
+        }
+
+        let current = std::fs::read_to_string(CURRENT_VAULT_FILE.as_path())
+            .map_err(VaultError::UnableToRead)?;
+        match current.trim() {
+            "" => Ok(Vault::default_vault()),
+            current => Vault::parse(current),
+        }
+    }
+
+    /// Use this vault for the rest of the run, must be called before accessing any store path
+    pub fn select(self) -> Result<&'static Vault, VaultError> {
+        SELECTED_VAULT
+            .set(self)
+            .map_err(|_| VaultError::AlreadySelected)?;
+
+        Ok(Vault::selected())
+    }
+
+    /// Vault used by this run of pass
+    pub fn selected() -> &'static Vault {
+        SELECTED_VAULT.get_or_init(Vault::default_vault)
+    }
+
+    /// All vaults stored in the pass directory, default vault comes first
+    pub fn list() -> Result<Vec<Vault>, VaultError> {
+        let mut vaults = match VAULTS_DIR_PATH.exists() {
+            true => std::fs::read_dir(VAULTS_DIR_PATH.as_path())
+                .map_err(VaultError::UnableToRead)?
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| entry.path().is_dir())
+                .filter_map(|entry| Vault::named(entry.file_name().to_string_lossy()).ok())
+                .collect::<Vec<_>>(),
+            false => Vec::new(),
+        };
+        vaults.sort_by(|a, b| a.name.cmp(&b.name));
+        vaults.insert(0, Vault::default_vault());
+
+        Ok(vaults)
+    }
+
+    /// Name of vault, or its directory if it is given by path
+    pub fn name(&self) -> String {
+        match &self.name {
+            Some(name) => name.clone(),
+            None => self.dir.display().to_string(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name.as_deref() == Some(DEFAULT_VAULT)
+    }
+
+    /// Whether the vault owns its directory, i.e. it is not given by path
+    pub fn is_named(&self) -> bool {
+        self.name.is_some()
+    }
+
+    /// Path of encrypted entries of vault
+    pub fn store_path(&self) -> PathBuf {
+        self.dir.join("passwords.db")
+    }
+
+    /// Path of the file locked by processes using the vault
+    pub fn lock_path(&self) -> PathBuf {
+        self.dir.join("vault.lock")
+    }
+
+    /// Path of the master password hash of legacy vaults
+    pub fn master_path(&self) -> PathBuf {
+        self.dir.join("master.dat")
+    }
+
+    /// Whether the vault has been initialised
+    pub fn exists(&self) -> bool {
+        self.store_path().exists() || self.master_path().exists()
+    }
+
+    /// Create the directory of a new vault
+    pub fn create(&self) -> Result<(), VaultError> {
+        if self.exists() {
+            return Err(VaultError::AlreadyExists(self.name()));
+        }
+
+        std::fs::create_dir_all(&self.dir).map_err(VaultError::UnableToCreateDir)
+    }
+
+    /// Use this vault whenever `--vault` is not given
+    pub fn set_current(&self) -> Result<(), VaultError> {
+        if !self.exists() {
+            return Err(VaultError::NotFound(self.name()));
+        }
+
+        if self.is_default() {
+            return match CURRENT_VAULT_FILE.exists() {
+                true => std::fs::remove_file(CURRENT_VAULT_FILE.as_path())
+                    .map_err(VaultError::UnableToWrite),
+                false => Ok(()),
+            };
+        }
+
+        XDG_BASE
+            .create_config_directory("")
+            .map_err(VaultError::UnableToWrite)?;
+        std::fs::write(CURRENT_VAULT_FILE.as_path(), self.name()).map_err(VaultError::UnableToWrite)
+    }
+
+    /// Remove the named vault along with all of its files (entries, backup, attachments, lock)
+    pub fn delete(&self) -> Result<(), VaultError> {
+        if !self.is_named() {
+            return Err(VaultError::NotOwned(self.name()));
+        }
+        if self.is_default() {
+            return Err(VaultError::UnableToDelete(self.name()));
+        }
+
+        // Named vault owns its whole directory
+        if !self.dir.exists() {
+            return Err(VaultError::NotFound(self.name()));
+        }
+        std::fs::remove_dir_all(&self.dir).map_err(VaultError::UnableToRemove)?;
+
+        // Fallback to default vault if the deleted one was in use
+        let is_current = Vault::resolve(None).is_ok_and(|current| current == *self);
+        if is_current && CURRENT_VAULT_FILE.exists() {
+            std::fs::remove_file(CURRENT_VAULT_FILE.as_path())
+                .map_err(VaultError::UnableToWrite)?;
+        }
+
+        Ok(())
+    }
+
+    /// Remove the files of the vault but leave the rest of its directory, e.g. named vaults
+    /// stored under the directory of default vault
+    pub fn reset(&self) -> Result<(), VaultError> {
+        if !self.is_named() {
+            return Err(VaultError::NotOwned(self.name()));
+        }
+
+        let store = self.store_path();
+        for file in [
+            backup_path(&store),
+            staging_path(&store),
+            self.master_path(),
+            self.lock_path(),
+            store,
+        ] {
+            match std::fs::remove_file(&file) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(VaultError::UnableToRemove(err));
+                }
+                _ => {}
+            }
+        }
+
+        let attachments = attachments_dir(self.store_path());
+        if attachments.exists() {
+            std::fs::remove_dir_all(attachments).map_err(VaultError::UnableToRemove)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_vault() -> Result<(), VaultError> {
+        assert_eq!(Vault::parse(DEFAULT_VAULT)?, Vault::default_vault());
+
+        let work = Vault::parse("work")?;
+        assert_eq!(work.name(), "work");
+        assert_eq!(work.store_path(), VAULTS_DIR_PATH.join("work/passwords.db"));
+
+        let synced = Vault::parse("/tmp/synced")?;
+        assert_eq!(synced.name(), "/tmp/synced");
+        assert_eq!(
+            synced.store_path(),
+            PathBuf::from("/tmp/synced/passwords.db")
+        );
+
+        assert!(Vault::parse("./relative")?.dir().is_absolute());
+        assert!(Vault::named("work vault").is_err());
+        assert!(Vault::named("").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn delete_unowned_vault() -> Result<(), VaultError> {
+        let synced = Vault::parse("/tmp/synced")?;
+        assert!(matches!(synced.delete(), Err(VaultError::NotOwned(_))));
+        assert!(matches!(
+            Vault::default_vault().delete(),
+            Err(VaultError::UnableToDelete(_))
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reset_default_vault() -> Result<(), VaultError> {
+        let dir = std::env::temp_dir().join(format!("pass_rs_reset_{}", std::process::id()));
+        let vault = Vault {
+            name: Some(DEFAULT_VAULT.to_owned()),
+            dir: dir.clone(),
+        };
+        let named = dir.join("vaults/work/passwords.db");
+        std::fs::create_dir_all(named.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(attachments_dir(vault.store_path())).unwrap();
+        for file in [
+            &vault.store_path(),
+            &backup_path(vault.store_path()),
+            &named,
+        ] {
+            std::fs::write(file, b"vault").unwrap();
+        }
+
+        vault.reset()?;
+        assert!(!vault.exists());
+        assert!(!backup_path(vault.store_path()).exists());
+        assert!(!attachments_dir(vault.store_path()).exists());
+        assert!(named.exists());
+
+        std::fs::remove_dir_all(dir).unwrap();
+        Ok(())
+    }
+}